@@ -4,6 +4,61 @@ export const apiClient = axios.create({
   baseURL: "/api"
 })
 
+const REFRESH_TOKEN_KEY = 'refresh_token'
+
+export const save_refresh_token = (refresh_token: string | null) => {
+  if (refresh_token) {
+    localStorage.setItem(REFRESH_TOKEN_KEY, refresh_token)
+  } else {
+    localStorage.removeItem(REFRESH_TOKEN_KEY)
+  }
+}
+
+// access token 续期后通知 AuthProvider 保存新的 token，续期失败时传入 null 表示需要重新登录
+let onTokenRefreshed: ((token: string | null) => void) | null = null
+
+export const set_token_refreshed_handler = (handler: ((token: string | null) => void) | null) => {
+  onTokenRefreshed = handler
+}
+
+// 同时有多个请求返回 401 时只续期一次
+let refreshing: Promise<string | null> | null = null
+
+const refresh_access_token = (): Promise<string | null> => {
+  if (!refreshing) {
+    refreshing = (async () => {
+      const refresh_token = localStorage.getItem(REFRESH_TOKEN_KEY)
+      if (!refresh_token) return null
+      try {
+        const response = await apiClient.post('/auth/refresh', { refresh_token })
+        save_refresh_token(response.data.refresh_token)
+        return response.data.token as string
+      } catch (error) {
+        console.error("Failed to refresh token", error)
+        save_refresh_token(null)
+        return null
+      }
+    })().finally(() => {
+      refreshing = null
+    })
+  }
+  return refreshing
+}
+
+// access token 过期时用 refresh token 换取新的 token 并重试一次
+apiClient.interceptors.response.use(undefined, async (error) => {
+  const config: any = error.config
+  if (error.response?.status !== 401 || !config || config._retried || config.url?.startsWith('/auth/')) {
+    throw error
+  }
+  const token = await refresh_access_token()
+  onTokenRefreshed?.(token)
+  if (!token) throw error
+  config._retried = true
+  config.headers['Authorization'] = `Bearer ${token}`
+  return apiClient(config)
+})
+
 export interface ServerInfo {
   features: string[]
 }
@@ -28,6 +83,7 @@ export interface RegisterPayload {
 export interface RegisterResult {
   user: User
   token: string
+  refresh_token: string
 }
 
 export interface LoginPayload {
@@ -38,6 +94,7 @@ export interface LoginPayload {
 export interface LoginResult {
   user: User
   token: string
+  refresh_token: string
 }
 
 export interface Image {
@@ -51,6 +108,16 @@ export interface Image {
 
 export interface ListImagesResult {
   photos: Image[]
+  // 没有更多照片时为 null
+  next_cursor: string | null
+}
+
+export interface ListImagesParams {
+  tags?: string
+  untagged?: boolean
+  // 上一页返回的 next_cursor
+  cursor?: string
+  limit?: number
 }
 
 export interface UploadImagesResult {
@@ -75,6 +142,14 @@ export const login = async (payload: LoginPayload): Promise<LoginResult> => {
   return response.data
 }
 
+export const logout = async (token: string): Promise<void> => {
+  await apiClient.post('/auth/logout', {}, {
+    headers: {
+      'Authorization': `Bearer ${token}`
+    }
+  })
+}
+
 export const get_own_profile = async (token: string): Promise<{ user: User }> => {
   const response = await apiClient.get('/users/me', {
     headers: {
@@ -96,10 +171,12 @@ export const upload_image = async (file: File, token: string): Promise<UploadIma
   return response.data
 }
 
-export const list_images = async (token: string, tags?: string, untagged?: boolean): Promise<ListImagesResult> => {
+export const list_images = async (token: string, { tags, untagged, cursor, limit }: ListImagesParams = {}): Promise<ListImagesResult> => {
   const params: any = {};
   if (tags) params.tags = tags;
   if (untagged) params.untagged = untagged;
+  if (cursor) params.cursor = cursor;
+  if (limit) params.limit = limit;
 
   const response = await apiClient.get('/photos/list', {
     headers: {
@@ -1,5 +1,5 @@
 import { createContext, useCallback, useContext, useEffect, useMemo, useState } from "react";
-import { get_own_profile, login, type User } from "@/api";
+import { get_own_profile, login, logout, save_refresh_token, set_token_refreshed_handler, type User } from "@/api";
 import { useLocalStorage } from "@/lib/use-local-storage";
 
 type AuthContextType = {
@@ -50,9 +50,15 @@ export const AuthProvider = ({ children }: { children: React.ReactNode }) => {
     refreshAuthHandler(token);
   }, [refreshAuthHandler, token]);
 
+  useEffect(() => {
+    set_token_refreshed_handler(setToken);
+    return () => set_token_refreshed_handler(null);
+  }, [setToken]);
+
   const loginHandler = async (email: string, password: string) => {
     try {
       const result = await login({ email, password });
+      save_refresh_token(result.refresh_token);
       setToken(result.token);
     } catch (error) {
       console.error("Login failed:", error);
@@ -61,9 +67,13 @@ export const AuthProvider = ({ children }: { children: React.ReactNode }) => {
   };
 
   const logoutHandler = useCallback(() => {
+    if (token) {
+      logout(token).catch((error) => console.error("Logout failed:", error));
+    }
+    save_refresh_token(null);
     setToken(null);
     setUser(null);
-  }, [setToken]);
+  }, [token, setToken]);
 
   const contextValue = useMemo(() => ({
     user,
@@ -3,7 +3,7 @@ import { useNavigate, Link } from '@tanstack/react-router'
 import { Button } from '@/components/ui/button'
 import { Input } from '@/components/ui/input'
 import { Label } from '@/components/ui/label'
-import { register } from '@/api'
+import { register, save_refresh_token } from '@/api'
 import { useAuth } from './hooks'
 import { X, Loader2 } from 'lucide-react'
 
@@ -57,6 +57,7 @@ export default function RegisterPage() {
     try {
       const result = await register({ name, email, password })
       // Use setToken to persist the token immediately, this triggers the AuthProvider effect
+      save_refresh_token(result.refresh_token)
       setToken(result.token)
       setError(null)
       navigate({ to: '/photos' })
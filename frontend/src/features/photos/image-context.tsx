@@ -5,7 +5,9 @@ import { toast } from 'sonner';
 interface ImagesContextType {
     images: Image[];
     isLoading: boolean;
+    hasMoreImages: boolean;
     refreshImages: () => Promise<void>;
+    loadMoreImages: () => Promise<void>;
     uploadImages: (files: File[]) => Promise<void>;
     deleteImages: (photo_ids: string[]) => Promise<void>;
     addTag: (photo_id: string, tag: string) => Promise<void>;
@@ -18,12 +20,18 @@ interface ImagesContextType {
     refreshTags: () => Promise<void>;
 }
 
+// 每页的照片数，服务端最多返回 500 张
+const PAGE_SIZE = 100;
+
 const ImagesContext = createContext<ImagesContextType | undefined>(undefined);
 
 export const ImagesProvider = ({ token, children }: { token: string, children: React.ReactNode }) => {
     const [images, setImages] = useState<Image[]>([]);
     const blobCacheRef = useRef<Map<string, string>>(new Map());
     const [isLoading, setIsLoading] = useState(false);
+    const [nextCursor, setNextCursor] = useState<string | null>(null);
+    const loadedCountRef = useRef(0);
+    const loadingMoreRef = useRef(false);
 
     const [tags, setTags] = useState<TagWithCount[]>([]);
 
@@ -31,9 +39,19 @@ export const ImagesProvider = ({ token, children }: { token: string, children: R
         if (!token) return;
         setIsLoading(true);
         try {
-            const { photos } = await list_images(token);
-
+            // 重新加载已经显示的页数，避免修改标签等操作后列表缩回第一页
+            const target = Math.max(loadedCountRef.current, PAGE_SIZE);
+            const photos: Image[] = [];
+            let cursor: string | undefined;
+            do {
+                const page = await list_images(token, { cursor, limit: PAGE_SIZE });
+                photos.push(...page.photos);
+                cursor = page.next_cursor ?? undefined;
+            } while (cursor && photos.length < target);
+
+            loadedCountRef.current = photos.length;
             setImages(photos);
+            setNextCursor(cursor ?? null);
         } catch (error) {
             console.error("Failed to fetch images", error);
             toast.error("Failed to refresh gallery");
@@ -42,6 +60,25 @@ export const ImagesProvider = ({ token, children }: { token: string, children: R
         }
     }, [token]);
 
+    const loadMoreImages = useCallback(async () => {
+        if (!token || !nextCursor || loadingMoreRef.current) return;
+        loadingMoreRef.current = true;
+        try {
+            const page = await list_images(token, { cursor: nextCursor, limit: PAGE_SIZE });
+            setImages(prev => {
+                const next = [...prev, ...page.photos];
+                loadedCountRef.current = next.length;
+                return next;
+            });
+            setNextCursor(page.next_cursor);
+        } catch (error) {
+            console.error("Failed to fetch images", error);
+            toast.error("Failed to load more photos");
+        } finally {
+            loadingMoreRef.current = false;
+        }
+    }, [token, nextCursor]);
+
     const refreshTags = useCallback(async () => {
         if (!token) return;
         try {
@@ -119,7 +156,9 @@ export const ImagesProvider = ({ token, children }: { token: string, children: R
     const value = {
         images,
         isLoading,
+        hasMoreImages: nextCursor !== null,
         refreshImages,
+        loadMoreImages,
         uploadImages,
         deleteImages,
         addTag,
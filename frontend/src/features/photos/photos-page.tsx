@@ -17,7 +17,7 @@ import { TagFilterPanel } from '../tags/tags-filter-panel'
 import { SlideshowModal } from './slideshow-modal'
 
 export default function PhotosPage() {
-  const { images: serverImages, uploadImages, deleteImages, tags } = useImages();
+  const { images: serverImages, hasMoreImages, loadMoreImages, uploadImages, deleteImages, tags } = useImages();
   const { setSidebarOpen, sidebarOpen, isMobile, toggleMobileSidebar } = useDashboardContext();
 
   // Grid State
@@ -63,6 +63,20 @@ export default function PhotosPage() {
 
   // Refs
   const fileInputRef = useRef<HTMLInputElement>(null);
+  const loadMoreRef = useRef<HTMLDivElement>(null);
+
+  // 滚动到网格底部时加载下一页
+  useEffect(() => {
+    const target = loadMoreRef.current;
+    if (!target || !hasMoreImages) return;
+    const observer = new IntersectionObserver((entries) => {
+      if (entries.some(entry => entry.isIntersecting)) {
+        loadMoreImages();
+      }
+    }, { rootMargin: '400px' });
+    observer.observe(target);
+    return () => observer.disconnect();
+  }, [hasMoreImages, loadMoreImages]);
 
   // Derived Data
   const filteredImages = serverImages.filter(img => {
@@ -234,6 +248,7 @@ export default function PhotosPage() {
                 />
               ))}
             </div >
+            {hasMoreImages && <div ref={loadMoreRef} className="h-px" />}
           </div>
         )
       }
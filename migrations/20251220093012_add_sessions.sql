@@ -0,0 +1,19 @@
+-- 会话表，每个会话对应一个可轮换的 refresh token
+CREATE TABLE "session" (
+    "id" UUID PRIMARY KEY,
+    "user_id" UUID NOT NULL REFERENCES "user"("id") ON DELETE CASCADE,
+    "refresh_token_hash" TEXT NOT NULL UNIQUE, -- SHA256 HEX
+    "previous_refresh_token_hash" TEXT, -- 上一次轮换前的 token，用于检测重放
+    "expires_at" TIMESTAMPTZ NOT NULL,
+    "revoked_at" TIMESTAMPTZ,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER set_updated_at_column
+BEFORE UPDATE ON "session"
+FOR EACH ROW
+EXECUTE FUNCTION set_updated_at_column();
+
+CREATE INDEX "idx_session_user_id" ON "session" ("user_id");
+CREATE INDEX "idx_session_previous_refresh_token_hash" ON "session" ("previous_refresh_token_hash");
@@ -0,0 +1,4 @@
+-- 软删除：非空表示照片在回收站中
+ALTER TABLE "photo" ADD COLUMN "deleted_at" TIMESTAMPTZ;
+
+CREATE INDEX "idx_photo_deleted_at" ON "photo" ("deleted_at") WHERE "deleted_at" IS NOT NULL;
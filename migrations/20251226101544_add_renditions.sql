@@ -0,0 +1,12 @@
+-- 缩略图 / 预览图，由原图按配置的尺寸生成并缓存在对象存储中
+CREATE TABLE "rendition" (
+    "image_hash" TEXT NOT NULL REFERENCES "image"("hash") ON DELETE CASCADE,
+    "size" INTEGER NOT NULL, -- 长边的最大像素数
+    "format" TEXT NOT NULL, -- jpeg, webp
+    "storage_key" TEXT NOT NULL,
+    "width" INTEGER NOT NULL,
+    "height" INTEGER NOT NULL,
+    "byte_size" BIGINT NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY ("image_hash", "size", "format")
+);
@@ -0,0 +1,29 @@
+-- 可续传的分块上传会话，分块暂存在对象存储中，完成后合并为一张照片
+CREATE TABLE "upload_session" (
+    "id" UUID PRIMARY KEY,
+    "user_id" UUID NOT NULL REFERENCES "user"("id") ON DELETE CASCADE,
+    "file_name" TEXT,
+    "content_type" TEXT NOT NULL,
+    "size" BIGINT NOT NULL, -- 文件总大小
+    "sha256" TEXT NOT NULL, -- 完整文件的 SHA256 HEX，合并后校验
+    "expires_at" TIMESTAMPTZ NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER set_updated_at_column
+BEFORE UPDATE ON "upload_session"
+FOR EACH ROW
+EXECUTE FUNCTION set_updated_at_column();
+
+CREATE INDEX "idx_upload_session_user_id" ON "upload_session" ("user_id");
+CREATE INDEX "idx_upload_session_expires_at" ON "upload_session" ("expires_at");
+
+CREATE TABLE "upload_chunk" (
+    "session_id" UUID NOT NULL REFERENCES "upload_session"("id") ON DELETE CASCADE,
+    "index" INTEGER NOT NULL, -- 分块序号，从 0 开始
+    "size" BIGINT NOT NULL,
+    "storage_key" TEXT NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY ("session_id", "index")
+);
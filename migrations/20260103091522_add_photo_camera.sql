@@ -0,0 +1,6 @@
+-- 从 EXIF 中读取的相机和镜头信息
+ALTER TABLE "photo"
+    ADD COLUMN "camera_make" TEXT,
+    ADD COLUMN "camera_model" TEXT,
+    ADD COLUMN "lens_make" TEXT,
+    ADD COLUMN "lens_model" TEXT;
@@ -0,0 +1,10 @@
+-- 视频时长，图片为空
+ALTER TABLE "image" ADD COLUMN "duration_ms" BIGINT;
+
+-- Live Photo：照片的静态图片和配对的视频
+ALTER TABLE "photo"
+    ADD COLUMN "content_identifier" TEXT,
+    ADD COLUMN "live_video_hash" TEXT REFERENCES "image"("hash");
+
+CREATE INDEX "idx_photo_content_identifier" ON "photo" ("user_id", "content_identifier") WHERE "content_identifier" IS NOT NULL;
+CREATE INDEX "idx_photo_live_video_hash" ON "photo" ("live_video_hash") WHERE "live_video_hash" IS NOT NULL;
@@ -0,0 +1,35 @@
+-- 照片的拍摄参数，从 EXIF 中读取
+CREATE TABLE "photo_metadata" (
+    "photo_id" UUID PRIMARY KEY REFERENCES "photo"("id") ON DELETE CASCADE,
+    "camera_make" TEXT,
+    "camera_model" TEXT,
+    "lens_make" TEXT,
+    "lens_model" TEXT,
+    "focal_length" DOUBLE PRECISION, -- mm
+    "focal_length_35mm" INTEGER,
+    "aperture" DOUBLE PRECISION, -- F 值
+    "exposure_time" DOUBLE PRECISION, -- 秒
+    "iso" INTEGER,
+    "orientation" SMALLINT, -- EXIF Orientation 1 ~ 8
+    "flash" INTEGER, -- EXIF Flash 原始值
+    "offset_time" TEXT, -- OffsetTimeOriginal，例如 +08:00
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER set_updated_at_column
+BEFORE UPDATE ON "photo_metadata"
+FOR EACH ROW
+EXECUTE FUNCTION set_updated_at_column();
+
+-- 相机和镜头信息移到 photo_metadata
+INSERT INTO "photo_metadata" ("photo_id", "camera_make", "camera_model", "lens_make", "lens_model")
+SELECT "id", "camera_make", "camera_model", "lens_make", "lens_model"
+FROM "photo"
+WHERE COALESCE("camera_make", "camera_model", "lens_make", "lens_model") IS NOT NULL;
+
+ALTER TABLE "photo"
+    DROP COLUMN "camera_make",
+    DROP COLUMN "camera_model",
+    DROP COLUMN "lens_make",
+    DROP COLUMN "lens_model";
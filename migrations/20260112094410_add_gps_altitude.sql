@@ -0,0 +1,5 @@
+-- 海拔，单位 m，海平面以下为负
+ALTER TABLE "photo" ADD COLUMN "altitude" DOUBLE PRECISION;
+
+-- GPS 记录的 UTC 时间
+ALTER TABLE "photo_metadata" ADD COLUMN "gps_timestamp" TIMESTAMPTZ;
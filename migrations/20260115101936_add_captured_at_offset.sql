@@ -0,0 +1,20 @@
+-- 拍摄地相对 UTC 的偏移，单位秒，以及偏移的来源：exif、gps_time 或 longitude
+ALTER TABLE "photo"
+    ADD COLUMN "captured_at_offset" INTEGER,
+    ADD COLUMN "captured_at_offset_source" TEXT,
+    ADD CONSTRAINT "photo_captured_at_offset_check" CHECK (
+        ("captured_at_offset" IS NULL) = ("captured_at_offset_source" IS NULL)
+    );
+
+-- 已有照片只能使用 EXIF 中记录的 OffsetTimeOriginal
+UPDATE "photo" SET
+    "captured_at_offset" = EXTRACT(EPOCH FROM "photo_metadata"."offset_time"::INTERVAL)::INTEGER,
+    "captured_at_offset_source" = 'exif'
+FROM "photo_metadata"
+WHERE "photo_metadata"."photo_id" = "photo"."id"
+    AND "photo"."captured_at" IS NOT NULL
+    AND "photo_metadata"."offset_time" ~ '^[+-][0-9]{2}:[0-9]{2}$';
+
+-- 原来保存的是本地时间，换算成 UTC 时刻；没有偏移的按 UTC 处理
+ALTER TABLE "photo" ALTER COLUMN "captured_at" TYPE TIMESTAMPTZ
+    USING ("captured_at" AT TIME ZONE 'UTC') - make_interval(secs => COALESCE("captured_at_offset", 0));
@@ -0,0 +1,22 @@
+-- 用户设置的额外旋转，顺时针角度，在 EXIF Orientation 之后应用
+ALTER TABLE "photo" ADD COLUMN "rotation" SMALLINT NOT NULL DEFAULT 0
+    CHECK ("rotation" IN (0, 90, 180, 270));
+
+-- 缩略图按旋转角度分别缓存
+ALTER TABLE "rendition" ADD COLUMN "rotation" SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE "rendition" DROP CONSTRAINT "rendition_pkey";
+ALTER TABLE "rendition" ADD PRIMARY KEY ("image_hash", "size", "format", "rotation");
+
+-- 已有图片的尺寸和缩略图没有应用 EXIF Orientation；HEIF 的方向由 irot 决定，不受影响
+UPDATE "image" SET "width" = "height", "height" = "width"
+WHERE "extension" NOT IN ('heic', 'heif', 'avif') AND "hash" IN (
+    SELECT "photo"."image_hash" FROM "photo"
+    JOIN "photo_metadata" ON "photo"."id" = "photo_metadata"."photo_id"
+    WHERE "photo_metadata"."orientation" >= 5
+);
+
+DELETE FROM "rendition" WHERE "image_hash" IN (
+    SELECT "photo"."image_hash" FROM "photo"
+    JOIN "photo_metadata" ON "photo"."id" = "photo_metadata"."photo_id"
+    WHERE "photo_metadata"."orientation" >= 2
+);
@@ -0,0 +1,8 @@
+-- 时间线的排序时间：拍摄时间，没有时使用上传时间
+ALTER TABLE "photo" ADD COLUMN "timeline_at" TIMESTAMPTZ
+    GENERATED ALWAYS AS (COALESCE("captured_at", "uploaded_at")) STORED;
+ALTER TABLE "photo" ALTER COLUMN "timeline_at" SET NOT NULL;
+
+-- 时间线按 (timeline_at, id) 分页
+CREATE INDEX "idx_photo_timeline" ON "photo" ("user_id", "timeline_at" DESC, "id" DESC)
+    WHERE "deleted_at" IS NULL;
@@ -0,0 +1,66 @@
+-- 用户填写的标题和描述
+ALTER TABLE "photo"
+    ADD COLUMN "caption" TEXT,
+    ADD COLUMN "description" TEXT,
+    ADD COLUMN "search_vector" TSVECTOR NOT NULL DEFAULT ''::TSVECTOR;
+
+-- 全文搜索的文档：标题和标签权重最高，其次是地点，最后是描述。
+-- 使用 simple 配置，不做词干提取，对地名和多语言内容更稳妥；
+-- 地点字符串开头的坐标 "(31.2, 121.4): " 不参与搜索
+CREATE OR REPLACE FUNCTION photo_search_vector(
+    p_photo_id UUID, p_location TEXT, p_caption TEXT, p_description TEXT
+)
+RETURNS TSVECTOR AS $$
+    SELECT
+        setweight(to_tsvector('simple', COALESCE(p_caption, '')), 'A')
+        || setweight(to_tsvector('simple', COALESCE((
+            SELECT string_agg("tag"."name", ' ')
+            FROM "photo_tag"
+            JOIN "tag" ON "photo_tag"."tag_id" = "tag"."id"
+            WHERE "photo_tag"."photo_id" = p_photo_id
+        ), '')), 'A')
+        || setweight(to_tsvector('simple',
+            regexp_replace(COALESCE(p_location, ''), '^\([^)]*\):\s*', '')), 'B')
+        || setweight(to_tsvector('simple', COALESCE(p_description, '')), 'C');
+$$ LANGUAGE sql STABLE;
+
+CREATE OR REPLACE FUNCTION update_photo_search_vector()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW."search_vector" = photo_search_vector(NEW."id", NEW."location", NEW."caption", NEW."description");
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER update_photo_search_vector
+BEFORE INSERT OR UPDATE OF "location", "caption", "description" ON "photo"
+FOR EACH ROW
+EXECUTE FUNCTION update_photo_search_vector();
+
+-- 标签变化时更新对应照片
+CREATE OR REPLACE FUNCTION update_photo_tag_search_vector()
+RETURNS TRIGGER AS $$
+DECLARE
+    v_photo_id UUID;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        v_photo_id = OLD."photo_id";
+    ELSE
+        v_photo_id = NEW."photo_id";
+    END IF;
+    UPDATE "photo"
+    SET "search_vector" = photo_search_vector("id", "location", "caption", "description")
+    WHERE "id" = v_photo_id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER update_photo_tag_search_vector
+AFTER INSERT OR DELETE ON "photo_tag"
+FOR EACH ROW
+EXECUTE FUNCTION update_photo_tag_search_vector();
+
+UPDATE "photo"
+SET "search_vector" = photo_search_vector("id", "location", "caption", "description");
+
+CREATE INDEX "idx_photo_search_vector" ON "photo" USING GIN ("search_vector");
@@ -0,0 +1,6 @@
+-- 收藏
+ALTER TABLE "photo" ADD COLUMN "is_favorite" BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- 收藏页按时间线分页
+CREATE INDEX "idx_photo_favorite_timeline" ON "photo" ("user_id", "timeline_at" DESC, "id" DESC)
+    WHERE "is_favorite" AND "deleted_at" IS NULL;
@@ -0,0 +1,31 @@
+-- 相册
+CREATE TABLE "album" (
+    "id" UUID PRIMARY KEY,
+    "user_id" UUID NOT NULL REFERENCES "user"("id") ON DELETE CASCADE,
+    "name" TEXT NOT NULL,
+    "description" TEXT,
+    -- 用户选择的封面，为空时使用相册中的第一张照片
+    "cover_photo_id" UUID REFERENCES "photo"("id") ON DELETE SET NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER set_updated_at_column
+BEFORE UPDATE ON "album"
+FOR EACH ROW
+EXECUTE FUNCTION set_updated_at_column();
+
+CREATE INDEX "idx_album_user_id" ON "album" ("user_id", "created_at" DESC);
+
+-- 相册中的照片，按 position 升序排列
+-- 移入回收站的照片仍保留在相册中但不显示，恢复后回到原位置；永久删除时随之删除
+CREATE TABLE "album_photo" (
+    "album_id" UUID NOT NULL REFERENCES "album"("id") ON DELETE CASCADE,
+    "photo_id" UUID NOT NULL REFERENCES "photo"("id") ON DELETE CASCADE,
+    "position" BIGINT NOT NULL,
+    "added_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY ("album_id", "photo_id")
+);
+
+CREATE INDEX "idx_album_photo_position" ON "album_photo" ("album_id", "position", "photo_id");
+CREATE INDEX "idx_album_photo_photo_id" ON "album_photo" ("photo_id");
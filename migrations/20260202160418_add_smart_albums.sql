@@ -0,0 +1,17 @@
+-- 智能相册：保存的筛选条件，列出时动态计算
+CREATE TABLE "smart_album" (
+    "id" UUID PRIMARY KEY,
+    "user_id" UUID NOT NULL REFERENCES "user"("id") ON DELETE CASCADE,
+    "name" TEXT NOT NULL,
+    -- 筛选条件的 JSON，结构见 smart_albums::SmartFilter，新增条件时不需要迁移
+    "filter" JSONB NOT NULL CHECK (jsonb_typeof("filter") = 'object'),
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER set_updated_at_column
+BEFORE UPDATE ON "smart_album"
+FOR EACH ROW
+EXECUTE FUNCTION set_updated_at_column();
+
+CREATE INDEX "idx_smart_album_user_id" ON "smart_album" ("user_id", "created_at" DESC);
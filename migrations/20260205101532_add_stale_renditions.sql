@@ -0,0 +1,18 @@
+-- 记录已删除、文件等待 GC 删除的缩略图
+CREATE TABLE "stale_rendition" (
+    "storage_key" TEXT PRIMARY KEY,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- 旧版本 key 的缩略图可能没有应用 EXIF Orientation，删除后以新版本的 key 重新生成；
+-- HEIF 的方向由 irot 决定，不受影响
+WITH "deleted" AS (
+    DELETE FROM "rendition"
+    WHERE "image_hash" IN (
+        SELECT "hash" FROM "image" WHERE "extension" NOT IN ('heic', 'heif', 'avif')
+    )
+    RETURNING "storage_key"
+)
+INSERT INTO "stale_rendition" ("storage_key")
+SELECT "storage_key" FROM "deleted"
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,13 @@
+-- 旧图片记录的尺寸可能没有应用 EXIF Orientation，由后台任务读取原图修正
+ALTER TABLE "image" ADD COLUMN "orientation_checked" BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- 记录了 EXIF Orientation 的图片已经由 add_photo_rotation 或上传时修正
+UPDATE "image" SET "orientation_checked" = TRUE
+WHERE "hash" IN (
+    SELECT "photo"."image_hash" FROM "photo"
+    JOIN "photo_metadata" ON "photo"."id" = "photo_metadata"."photo_id"
+    WHERE "photo_metadata"."orientation" IS NOT NULL
+);
+
+-- 新上传的图片在上传时已经应用了 EXIF Orientation
+ALTER TABLE "image" ALTER COLUMN "orientation_checked" SET DEFAULT TRUE;
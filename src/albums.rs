@@ -0,0 +1,511 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::AuthUser;
+
+fn internal_error(e: impl std::fmt::Debug) -> (StatusCode, String) {
+    tracing::error!(error = ?e, "Album operation failed");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error".to_string(),
+    )
+}
+
+pub fn not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Album not found".to_string())
+}
+
+/// 相册内分页的位置：上一页最后一张照片的 `(position, photo_id)`
+#[derive(Debug, Clone, Copy)]
+pub struct PositionCursor {
+    pub position: i64,
+    pub id: Uuid,
+}
+
+impl PositionCursor {
+    pub fn encode(&self) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(format!("{}:{}", self.position, self.id))
+    }
+
+    pub fn decode(value: &str) -> Option<Self> {
+        let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(value)
+            .ok()?;
+        let value = String::from_utf8(bytes).ok()?;
+        let (position, id) = value.split_once(':')?;
+        Some(Self {
+            position: position.parse().ok()?,
+            id: Uuid::parse_str(id).ok()?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Album {
+    id: String,
+    name: String,
+    description: Option<String>,
+    /// 不含回收站中的照片
+    photo_count: i64,
+    /// 用户选择的封面；未选择或封面已移入回收站时为相册中的第一张照片，空相册为空
+    cover_photo_id: Option<String>,
+    created_at: i64,
+    updated_at: i64,
+}
+
+/// 查询用户的相册，`album_id` 为空时返回全部相册，按创建时间从新到旧排列
+async fn fetch_albums(
+    db: &PgPool,
+    user_id: Uuid,
+    album_id: Option<Uuid>,
+) -> Result<Vec<Album>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            "album"."id",
+            "album"."name",
+            "album"."description",
+            "album"."created_at",
+            "album"."updated_at",
+            (
+                SELECT COUNT(*) FROM "album_photo"
+                JOIN "photo" ON "album_photo"."photo_id" = "photo"."id"
+                WHERE "album_photo"."album_id" = "album"."id" AND "photo"."deleted_at" IS NULL
+            ) as "photo_count!",
+            COALESCE(
+                (
+                    SELECT "photo"."id" FROM "photo"
+                    WHERE "photo"."id" = "album"."cover_photo_id" AND "photo"."deleted_at" IS NULL
+                ),
+                (
+                    SELECT "album_photo"."photo_id" FROM "album_photo"
+                    JOIN "photo" ON "album_photo"."photo_id" = "photo"."id"
+                    WHERE "album_photo"."album_id" = "album"."id" AND "photo"."deleted_at" IS NULL
+                    ORDER BY "album_photo"."position", "album_photo"."photo_id"
+                    LIMIT 1
+                )
+            ) as "cover_photo_id"
+        FROM "album"
+        WHERE "album"."user_id" = $1 AND ($2::UUID IS NULL OR "album"."id" = $2)
+        ORDER BY "album"."created_at" DESC, "album"."id" DESC
+        "#,
+        user_id,
+        album_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|v| Album {
+            id: v.id.to_string(),
+            name: v.name,
+            description: v.description,
+            photo_count: v.photo_count,
+            cover_photo_id: v.cover_photo_id.map(|id| id.to_string()),
+            created_at: v.created_at.unix_timestamp(),
+            updated_at: v.updated_at.unix_timestamp(),
+        })
+        .collect())
+}
+
+async fn get_album(
+    db: &PgPool,
+    user_id: Uuid,
+    album_id: Uuid,
+) -> Result<Album, (StatusCode, String)> {
+    fetch_albums(db, user_id, Some(album_id))
+        .await
+        .map_err(internal_error)?
+        .pop()
+        .ok_or_else(not_found)
+}
+
+/// 确认相册属于当前用户
+pub async fn check_album(
+    db: &PgPool,
+    user_id: Uuid,
+    album_id: Uuid,
+) -> Result<(), (StatusCode, String)> {
+    sqlx::query!(
+        r#"SELECT "id" FROM "album" WHERE "id" = $1 AND "user_id" = $2"#,
+        album_id,
+        user_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(internal_error)?
+    .ok_or_else(not_found)?;
+    Ok(())
+}
+
+/// 解析照片 id 列表，与标签的批量接口一样忽略无效的 id，并去除重复
+fn parse_photo_ids(ids: &[String]) -> Vec<Uuid> {
+    let mut uuids: Vec<Uuid> = Vec::new();
+    for id in ids {
+        if let Ok(uuid) = Uuid::parse_str(id)
+            && !uuids.contains(&uuid)
+        {
+            uuids.push(uuid);
+        }
+    }
+    uuids
+}
+
+#[derive(Serialize)]
+pub struct ListAlbumsResponse {
+    albums: Vec<Album>,
+}
+
+pub async fn list_albums_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Response, (StatusCode, String)> {
+    let albums = fetch_albums(&db, user_id, None)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(ListAlbumsResponse { albums }).into_response())
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CreateAlbumPayload {
+    #[validate(length(
+        min = 1,
+        max = 200,
+        message = "Name must be between 1 and 200 characters"
+    ))]
+    name: String,
+    #[validate(length(max = 5000, message = "Description must be at most 5000 characters"))]
+    description: Option<String>,
+}
+
+pub async fn create_album_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Json(mut payload): Json<CreateAlbumPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    payload.name = payload.name.trim().to_string();
+    if let Err(e) = payload.validate() {
+        let body = Json(json!({
+            "details": e
+        }));
+        return Ok((StatusCode::BAD_REQUEST, body).into_response());
+    }
+
+    let album_id = sqlx::query!(
+        r#"
+        INSERT INTO "album" ("id", "user_id", "name", "description")
+        VALUES ($1, $2, $3, NULLIF(btrim($4), ''))
+        RETURNING "id"
+        "#,
+        Uuid::now_v7(),
+        user_id,
+        payload.name,
+        payload.description
+    )
+    .fetch_one(&db)
+    .await
+    .map_err(internal_error)?
+    .id;
+
+    let album = get_album(&db, user_id, album_id).await?;
+    Ok((StatusCode::CREATED, Json(album)).into_response())
+}
+
+pub async fn get_album_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(album_id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let album = get_album(&db, user_id, album_id).await?;
+    Ok(Json(album).into_response())
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateAlbumPayload {
+    #[validate(length(
+        min = 1,
+        max = 200,
+        message = "Name must be between 1 and 200 characters"
+    ))]
+    name: Option<String>,
+    #[validate(length(max = 5000, message = "Description must be at most 5000 characters"))]
+    description: Option<String>,
+}
+
+/// 修改相册的名称和描述：省略的字段保持不变，描述为空字符串表示清除
+pub async fn update_album_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(album_id): Path<Uuid>,
+    Json(mut payload): Json<UpdateAlbumPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    payload.name = payload.name.map(|v| v.trim().to_string());
+    if let Err(e) = payload.validate() {
+        let body = Json(json!({
+            "details": e
+        }));
+        return Ok((StatusCode::BAD_REQUEST, body).into_response());
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE "album" SET
+            "name" = COALESCE($3, "name"),
+            "description" = CASE WHEN $4::TEXT IS NULL THEN "description" ELSE NULLIF(btrim($4), '') END
+        WHERE "id" = $1 AND "user_id" = $2
+        RETURNING "id"
+        "#,
+        album_id,
+        user_id,
+        payload.name,
+        payload.description
+    )
+    .fetch_optional(&db)
+    .await
+    .map_err(internal_error)?
+    .ok_or_else(not_found)?;
+
+    let album = get_album(&db, user_id, album_id).await?;
+    Ok(Json(album).into_response())
+}
+
+/// 删除相册，照片本身不受影响
+pub async fn delete_album_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(album_id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let result = sqlx::query!(
+        r#"DELETE FROM "album" WHERE "id" = $1 AND "user_id" = $2"#,
+        album_id,
+        user_id
+    )
+    .execute(&db)
+    .await
+    .map_err(internal_error)?;
+    if result.rows_affected() == 0 {
+        return Err(not_found());
+    }
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[derive(Deserialize)]
+pub struct AlbumPhotosPayload {
+    photo_ids: Vec<String>,
+}
+
+/// 按给定顺序把照片添加到相册末尾，已在相册中的照片保持原位置
+pub async fn add_photos_batch_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(album_id): Path<Uuid>,
+    Json(payload): Json<AlbumPhotosPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    let photo_uuids = parse_photo_ids(&payload.photo_ids);
+    if photo_uuids.is_empty() {
+        check_album(&db, user_id, album_id).await?;
+        return Ok(Json(json!({"success": true})).into_response());
+    }
+
+    let mut tx = db.begin().await.map_err(internal_error)?;
+    // 锁住相册，避免并发添加时读到相同的最大位置
+    sqlx::query!(
+        r#"SELECT "id" FROM "album" WHERE "id" = $1 AND "user_id" = $2 FOR UPDATE"#,
+        album_id,
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal_error)?
+    .ok_or_else(not_found)?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO "album_photo" ("album_id", "photo_id", "position")
+        SELECT
+            $1,
+            "photo"."id",
+            (SELECT COALESCE(MAX("position"), 0) FROM "album_photo" WHERE "album_id" = $1)
+                + ROW_NUMBER() OVER (ORDER BY "ids"."ord")
+        FROM UNNEST($2::UUID[]) WITH ORDINALITY AS "ids"("id", "ord")
+        JOIN "photo" ON "photo"."id" = "ids"."id"
+        WHERE "photo"."user_id" = $3 AND "photo"."deleted_at" IS NULL
+            AND NOT EXISTS (
+                SELECT 1 FROM "album_photo" WHERE "album_id" = $1 AND "photo_id" = "photo"."id"
+            )
+        ON CONFLICT DO NOTHING
+        "#,
+        album_id,
+        &photo_uuids,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(json!({
+        "success": true
+    }))
+    .into_response())
+}
+
+pub async fn remove_photos_batch_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(album_id): Path<Uuid>,
+    Json(payload): Json<AlbumPhotosPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    check_album(&db, user_id, album_id).await?;
+
+    let photo_uuids = parse_photo_ids(&payload.photo_ids);
+    if photo_uuids.is_empty() {
+        return Ok(Json(json!({"success": true})).into_response());
+    }
+
+    let mut tx = db.begin().await.map_err(internal_error)?;
+    sqlx::query!(
+        r#"DELETE FROM "album_photo" WHERE "album_id" = $1 AND "photo_id" = ANY($2)"#,
+        album_id,
+        &photo_uuids
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    // 移出相册的照片不能再作为封面
+    sqlx::query!(
+        r#"UPDATE "album" SET "cover_photo_id" = NULL WHERE "id" = $1 AND "cover_photo_id" = ANY($2)"#,
+        album_id,
+        &photo_uuids
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(json!({
+        "success": true
+    }))
+    .into_response())
+}
+
+/// 调整相册中照片的顺序：列出的照片按给定顺序排在最前面，其余照片保持原来的相对顺序排在后面
+pub async fn reorder_photos_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(album_id): Path<Uuid>,
+    Json(payload): Json<AlbumPhotosPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    check_album(&db, user_id, album_id).await?;
+
+    let photo_uuids = parse_photo_ids(&payload.photo_ids);
+    sqlx::query!(
+        r#"
+        WITH "ordered" AS (
+            SELECT
+                "album_photo"."photo_id",
+                ROW_NUMBER() OVER (
+                    ORDER BY "ids"."ord" NULLS LAST, "album_photo"."position", "album_photo"."photo_id"
+                ) as "position"
+            FROM "album_photo"
+            LEFT JOIN UNNEST($2::UUID[]) WITH ORDINALITY AS "ids"("id", "ord")
+                ON "ids"."id" = "album_photo"."photo_id"
+            WHERE "album_photo"."album_id" = $1
+        )
+        UPDATE "album_photo" SET "position" = "ordered"."position"
+        FROM "ordered"
+        WHERE "album_photo"."album_id" = $1 AND "album_photo"."photo_id" = "ordered"."photo_id"
+        "#,
+        album_id,
+        &photo_uuids
+    )
+    .execute(&db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(json!({
+        "success": true
+    }))
+    .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SetCoverPayload {
+    /// 为空时恢复默认封面（相册中的第一张照片）
+    photo_id: Option<Uuid>,
+}
+
+pub async fn set_cover_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(album_id): Path<Uuid>,
+    Json(payload): Json<SetCoverPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    check_album(&db, user_id, album_id).await?;
+
+    if let Some(photo_id) = payload.photo_id {
+        sqlx::query!(
+            r#"
+            SELECT "album_photo"."photo_id" FROM "album_photo"
+            JOIN "photo" ON "album_photo"."photo_id" = "photo"."id"
+            WHERE "album_photo"."album_id" = $1 AND "album_photo"."photo_id" = $2
+                AND "photo"."deleted_at" IS NULL
+            "#,
+            album_id,
+            photo_id
+        )
+        .fetch_optional(&db)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "Photo not found in album".to_string(),
+            )
+        })?;
+    }
+
+    sqlx::query!(
+        r#"UPDATE "album" SET "cover_photo_id" = $2 WHERE "id" = $1"#,
+        album_id,
+        payload.photo_id
+    )
+    .execute(&db)
+    .await
+    .map_err(internal_error)?;
+
+    let album = get_album(&db, user_id, album_id).await?;
+    Ok(Json(album).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_cursor() {
+        let id = Uuid::parse_str("0190a6f2-3c4d-7e8f-9a0b-1c2d3e4f5a6b").unwrap();
+        for position in [0, 1, 42, i64::MAX] {
+            let cursor = PositionCursor { position, id };
+            let decoded = PositionCursor::decode(&cursor.encode()).unwrap();
+            assert_eq!(decoded.position, position);
+            assert_eq!(decoded.id, id);
+        }
+
+        for value in ["", "not base64!", "MTI", "YWJjOnh5eg"] {
+            assert!(PositionCursor::decode(value).is_none(), "{value}");
+        }
+    }
+}
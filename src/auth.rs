@@ -1,4 +1,6 @@
 pub mod jwt;
+pub mod password;
+pub mod session;
 use std::str::FromStr;
 
 use axum::{
@@ -8,6 +10,7 @@ use axum::{
     response::{IntoResponse, Response},
 };
 pub use jwt::JwtService;
+use password::{PasswordCheck, hash_password, verify_dummy_password, verify_password};
 use serde::Deserialize;
 use serde_json::json;
 use sqlx::PgPool;
@@ -18,6 +21,7 @@ impl<S> FromRequestParts<S> for AuthUser
 where
     S: Send + Sync,
     JwtService: FromRef<S>,
+    PgPool: FromRef<S>,
 {
     type Rejection = (StatusCode, String);
     async fn from_request_parts(
@@ -47,18 +51,30 @@ where
 
         let jwt_service = JwtService::from_ref(state);
 
-        let user_id = jwt_service.verify(token)?.sub;
+        let claims = jwt_service.verify(token)?;
 
-        let user_id = Uuid::from_str(&user_id)
+        let user_id = Uuid::from_str(&claims.sub)
             .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))?;
+        let session_id = Uuid::from_str(&claims.sid)
+            .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid session id".to_string()))?;
 
-        Ok(AuthUser { user_id })
+        // 会话被吊销或过期后，未过期的 access token 也随之失效
+        let db = PgPool::from_ref(state);
+        if !session::is_session_active(&db, session_id, user_id).await? {
+            return Err((StatusCode::UNAUTHORIZED, "Session revoked".to_string()));
+        }
+
+        Ok(AuthUser {
+            user_id,
+            session_id,
+        })
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct AuthUser {
     pub user_id: Uuid,
+    pub session_id: Uuid,
 }
 
 #[derive(Deserialize, Validate)]
@@ -113,12 +129,14 @@ pub async fn register_handler(
         return Err((StatusCode::CONFLICT, "User already exists".to_string()));
     }
 
+    let password_hash = hash_password(payload.password).await?;
+
     let user = sqlx::query!(
         r#"INSERT INTO "user" (id, name, email, password) VALUES ($1, $2, $3, $4) RETURNING id, name, email"#,
         uuid::Uuid::now_v7(),
         payload.name,
         payload.email,
-        payload.password
+        password_hash
     )
     .fetch_one(&db)
     .await
@@ -127,17 +145,16 @@ pub async fn register_handler(
         (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string())
     })?;
 
-    let user_id_str = user.id.to_string();
-
-    let token = jwt_service.sign(user_id_str.clone(), time::Duration::days(2))?;
+    let tokens = session::create_session(&db, &jwt_service, user.id).await?;
 
     Ok(Json(json!({
         "user": {
-            "id": user_id_str,
+            "id": user.id.to_string(),
             "name": user.name,
             "email": user.email,
         },
-        "token": token
+        "token": tokens.access_token,
+        "refresh_token": tokens.refresh_token
     }))
     .into_response())
 }
@@ -154,9 +171,8 @@ pub async fn login_handler(
     Json(payload): Json<LoginPayload>,
 ) -> Result<Response, (StatusCode, String)> {
     let user = sqlx::query!(
-        r#"SELECT id, name, email FROM "user" WHERE email = $1 AND password = $2"#,
-        payload.email,
-        payload.password
+        r#"SELECT id, name, email, password FROM "user" WHERE email = $1"#,
+        payload.email
     )
     .fetch_optional(&db)
     .await
@@ -166,23 +182,95 @@ pub async fn login_handler(
             StatusCode::INTERNAL_SERVER_ERROR,
             "Internal server error".to_string(),
         )
-    })?
-    .ok_or((
-        StatusCode::BAD_REQUEST,
-        "Invalid email or password".to_string(),
-    ))?;
+    })?;
+    let Some(user) = user else {
+        verify_dummy_password(payload.password).await?;
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Invalid email or password".to_string(),
+        ));
+    };
 
-    let user_id_str = user.id.to_string();
+    match verify_password(payload.password.clone(), user.password).await? {
+        PasswordCheck::Invalid => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Invalid email or password".to_string(),
+            ));
+        }
+        PasswordCheck::Valid => {}
+        PasswordCheck::ValidNeedsRehash => {
+            // 旧的明文密码在登录成功后迁移为 Argon2id 哈希，失败不影响本次登录
+            let password_hash = hash_password(payload.password).await?;
+            if let Err(e) = sqlx::query!(
+                r#"UPDATE "user" SET password = $1 WHERE id = $2"#,
+                password_hash,
+                user.id
+            )
+            .execute(&db)
+            .await
+            {
+                tracing::warn!(error = ?e, user_id = %user.id, "Failed to rehash password");
+            }
+        }
+    }
 
-    let token = jwt_service.sign(user_id_str.clone(), time::Duration::days(2))?;
+    let tokens = session::create_session(&db, &jwt_service, user.id).await?;
 
     Ok(Json(json!({
         "user": {
-            "id": user_id_str,
+            "id": user.id.to_string(),
             "name": user.name,
             "email": user.email,
         },
-        "token": token
+        "token": tokens.access_token,
+        "refresh_token": tokens.refresh_token
+    }))
+    .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct RefreshPayload {
+    refresh_token: String,
+}
+
+pub async fn refresh_handler(
+    State(db): State<PgPool>,
+    State(jwt_service): State<JwtService>,
+    Json(payload): Json<RefreshPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    let tokens = session::rotate_session(&db, &jwt_service, &payload.refresh_token).await?;
+
+    Ok(Json(json!({
+        "token": tokens.access_token,
+        "refresh_token": tokens.refresh_token
+    }))
+    .into_response())
+}
+
+pub async fn logout_handler(
+    State(db): State<PgPool>,
+    AuthUser {
+        user_id,
+        session_id,
+    }: AuthUser,
+) -> Result<Response, (StatusCode, String)> {
+    session::revoke_session(&db, session_id, user_id).await?;
+
+    Ok(Json(json!({
+        "success": true
+    }))
+    .into_response())
+}
+
+pub async fn logout_all_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Response, (StatusCode, String)> {
+    let revoked_count = session::revoke_all_sessions(&db, user_id).await?;
+
+    Ok(Json(json!({
+        "revoked_count": revoked_count
     }))
     .into_response())
 }
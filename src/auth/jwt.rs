@@ -7,6 +7,8 @@ use tracing::error;
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthClaims {
     pub sub: String,
+    /// 签发该 token 的会话 id
+    pub sid: String,
     pub iat: i64,
     pub exp: i64,
 }
@@ -25,12 +27,17 @@ impl JwtService {
         }
     }
 
-    pub fn sign(&self, sub: String, duration: Duration) -> Result<String, (StatusCode, String)> {
+    pub fn sign(
+        &self,
+        sub: String,
+        sid: String,
+        duration: Duration,
+    ) -> Result<String, (StatusCode, String)> {
         let now = OffsetDateTime::now_utc();
         let iat = now.unix_timestamp();
         let exp = (now + duration).unix_timestamp();
 
-        let claims = AuthClaims { sub, iat, exp };
+        let claims = AuthClaims { sub, sid, iat, exp };
 
         encode(&Header::default(), &claims, &self.encoding_key).map_err(|e| {
             error!(error = e.to_string(), "Failed to sign claims");
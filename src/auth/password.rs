@@ -0,0 +1,177 @@
+use argon2::{
+    Algorithm, Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use axum::http::StatusCode;
+use std::sync::LazyLock;
+use tracing::error;
+
+/// 用户不存在时用于校验的哈希，使登录耗时与用户存在时一致，避免通过响应时间判断邮箱是否已注册。
+/// 生成哈希需要一次完整的 Argon2id 计算，启动时由 `init_dummy_hash` 预先生成
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(b"dummy password", &salt)
+        .map(|hash| hash.to_string())
+        .unwrap_or_default()
+});
+
+#[derive(Debug, PartialEq)]
+pub enum PasswordCheck {
+    Invalid,
+    Valid,
+    /// 密码正确，但存储的是旧格式（明文或非 Argon2id），需要重新哈希
+    ValidNeedsRehash,
+}
+
+/// 使用 Argon2id 和随机盐生成 PHC 格式的哈希字符串
+pub async fn hash_password(password: String) -> Result<String, (StatusCode, String)> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    })
+    .await
+    .map_err(|e| {
+        error!(error = ?e, "Password hashing task failed");
+        internal_error()
+    })?
+    .map_err(|e| {
+        error!(error = ?e, "Failed to hash password");
+        internal_error()
+    })
+}
+
+/// 在阻塞线程池中生成 `DUMMY_HASH`，避免第一次用不存在的邮箱登录时阻塞运行时并耗时更长
+pub async fn init_dummy_hash() -> Result<(), (StatusCode, String)> {
+    tokio::task::spawn_blocking(|| {
+        LazyLock::force(&DUMMY_HASH);
+    })
+    .await
+    .map_err(|e| {
+        error!(error = ?e, "Dummy hash task failed");
+        internal_error()
+    })
+}
+
+/// 校验密码，兼容迁移前以明文存储的密码
+pub async fn verify_password(
+    password: String,
+    stored: String,
+) -> Result<PasswordCheck, (StatusCode, String)> {
+    tokio::task::spawn_blocking(move || check_password(&password, &stored))
+        .await
+        .map_err(|e| {
+            error!(error = ?e, "Password verification task failed");
+            internal_error()
+        })
+}
+
+/// 对不存在的用户执行一次同样代价的校验，结果总是无效
+pub async fn verify_dummy_password(password: String) -> Result<(), (StatusCode, String)> {
+    tokio::task::spawn_blocking(move || check_password(&password, &DUMMY_HASH))
+        .await
+        .map_err(|e| {
+            error!(error = ?e, "Password verification task failed");
+            internal_error()
+        })?;
+    Ok(())
+}
+
+fn check_password(password: &str, stored: &str) -> PasswordCheck {
+    let Ok(hash) = PasswordHash::new(stored) else {
+        // 无法解析为 PHC 字符串，视为旧的明文密码
+        return if constant_time_eq(password.as_bytes(), stored.as_bytes()) {
+            PasswordCheck::ValidNeedsRehash
+        } else {
+            PasswordCheck::Invalid
+        };
+    };
+
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_err()
+    {
+        return PasswordCheck::Invalid;
+    }
+
+    if hash.algorithm == Algorithm::Argon2id.ident() {
+        PasswordCheck::Valid
+    } else {
+        PasswordCheck::ValidNeedsRehash
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn internal_error() -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::{Params, Version};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn argon2id() {
+        let hash = hash_password("correct horse".to_string()).await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert_eq!(
+            verify_password("correct horse".to_string(), hash.clone())
+                .await
+                .unwrap(),
+            PasswordCheck::Valid
+        );
+        assert_eq!(
+            verify_password("wrong horse".to_string(), hash)
+                .await
+                .unwrap(),
+            PasswordCheck::Invalid
+        );
+    }
+
+    #[test]
+    fn legacy_plaintext() {
+        assert_eq!(
+            check_password("secret1", "secret1"),
+            PasswordCheck::ValidNeedsRehash
+        );
+        assert_eq!(check_password("secret2", "secret1"), PasswordCheck::Invalid);
+        assert_eq!(check_password("", "secret1"), PasswordCheck::Invalid);
+    }
+
+    #[test]
+    fn other_phc_algorithm() {
+        let salt = SaltString::generate(&mut OsRng);
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, Params::default())
+            .hash_password(b"secret1", &salt)
+            .unwrap()
+            .to_string();
+        assert!(argon2i.starts_with("$argon2i$"));
+        assert_eq!(
+            check_password("secret1", &argon2i),
+            PasswordCheck::ValidNeedsRehash
+        );
+        assert_eq!(check_password("secret2", &argon2i), PasswordCheck::Invalid);
+    }
+
+    #[tokio::test]
+    async fn dummy() {
+        init_dummy_hash().await.unwrap();
+        assert!(DUMMY_HASH.starts_with("$argon2id$"));
+        verify_dummy_password("dummy password".to_string())
+            .await
+            .unwrap();
+    }
+}
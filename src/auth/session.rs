@@ -0,0 +1,279 @@
+use axum::http::StatusCode;
+use base64::Engine;
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use super::JwtService;
+
+/// access token 有效期较短，依赖 refresh token 续期
+pub const ACCESS_TOKEN_TTL: Duration = Duration::minutes(15);
+pub const REFRESH_TOKEN_TTL: Duration = Duration::days(30);
+
+pub struct SessionTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+fn generate_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// 数据库中只保存 refresh token 的哈希
+fn hash_refresh_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn db_error(e: sqlx::Error, message: &str) -> (StatusCode, String) {
+    tracing::error!(error = ?e, "{}", message);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error".to_string(),
+    )
+}
+
+fn sign_access_token(
+    jwt_service: &JwtService,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<String, (StatusCode, String)> {
+    jwt_service.sign(
+        user_id.to_string(),
+        session_id.to_string(),
+        ACCESS_TOKEN_TTL,
+    )
+}
+
+pub async fn create_session(
+    db: &PgPool,
+    jwt_service: &JwtService,
+    user_id: Uuid,
+) -> Result<SessionTokens, (StatusCode, String)> {
+    let session_id = Uuid::now_v7();
+    let refresh_token = generate_refresh_token();
+    let expires_at = OffsetDateTime::now_utc() + REFRESH_TOKEN_TTL;
+
+    sqlx::query!(
+        r#"INSERT INTO "session" (id, user_id, refresh_token_hash, expires_at) VALUES ($1, $2, $3, $4)"#,
+        session_id,
+        user_id,
+        hash_refresh_token(&refresh_token),
+        expires_at
+    )
+    .execute(db)
+    .await
+    .map_err(|e| db_error(e, "Failed to create session"))?;
+
+    Ok(SessionTokens {
+        access_token: sign_access_token(jwt_service, user_id, session_id)?,
+        refresh_token,
+    })
+}
+
+/// 用旧的 refresh token 换取新的 token 对，旧 token 立即失效。
+/// 如果提交的是已经轮换过的 token，说明 token 可能被盗用，直接吊销整个会话。
+pub async fn rotate_session(
+    db: &PgPool,
+    jwt_service: &JwtService,
+    refresh_token: &str,
+) -> Result<SessionTokens, (StatusCode, String)> {
+    let old_hash = hash_refresh_token(refresh_token);
+    let new_refresh_token = generate_refresh_token();
+    let expires_at = OffsetDateTime::now_utc() + REFRESH_TOKEN_TTL;
+
+    let session = sqlx::query!(
+        r#"
+        UPDATE "session"
+        SET refresh_token_hash = $2, previous_refresh_token_hash = refresh_token_hash, expires_at = $3
+        WHERE refresh_token_hash = $1 AND revoked_at IS NULL AND expires_at > NOW()
+        RETURNING id, user_id
+        "#,
+        old_hash,
+        hash_refresh_token(&new_refresh_token),
+        expires_at
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| db_error(e, "Failed to rotate session"))?;
+
+    let Some(session) = session else {
+        let reused = sqlx::query!(
+            r#"
+            UPDATE "session" SET revoked_at = NOW()
+            WHERE previous_refresh_token_hash = $1 AND revoked_at IS NULL
+            RETURNING id
+            "#,
+            old_hash
+        )
+        .fetch_optional(db)
+        .await
+        .map_err(|e| db_error(e, "Failed to revoke session"))?;
+
+        if let Some(reused) = reused {
+            tracing::warn!(session_id = %reused.id, "Refresh token reuse detected, session revoked");
+        }
+
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Invalid refresh token".to_string(),
+        ));
+    };
+
+    Ok(SessionTokens {
+        access_token: sign_access_token(jwt_service, session.user_id, session.id)?,
+        refresh_token: new_refresh_token,
+    })
+}
+
+pub async fn is_session_active(
+    db: &PgPool,
+    session_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, (StatusCode, String)> {
+    let session = sqlx::query!(
+        r#"
+        SELECT id FROM "session"
+        WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL AND expires_at > NOW()
+        "#,
+        session_id,
+        user_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| db_error(e, "Failed to fetch session"))?;
+
+    Ok(session.is_some())
+}
+
+pub async fn revoke_session(
+    db: &PgPool,
+    session_id: Uuid,
+    user_id: Uuid,
+) -> Result<(), (StatusCode, String)> {
+    sqlx::query!(
+        r#"UPDATE "session" SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL"#,
+        session_id,
+        user_id
+    )
+    .execute(db)
+    .await
+    .map_err(|e| db_error(e, "Failed to revoke session"))?;
+
+    Ok(())
+}
+
+/// 吊销用户的全部会话，返回被吊销的数量
+pub async fn revoke_all_sessions(db: &PgPool, user_id: Uuid) -> Result<u64, (StatusCode, String)> {
+    let result = sqlx::query!(
+        r#"UPDATE "session" SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL"#,
+        user_id
+    )
+    .execute(db)
+    .await
+    .map_err(|e| db_error(e, "Failed to revoke sessions"))?;
+
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn create_user(db: &PgPool) -> Uuid {
+        let user_id = Uuid::now_v7();
+        sqlx::query(r#"INSERT INTO "user" (id, name, email, password) VALUES ($1, $2, $3, $4)"#)
+            .bind(user_id)
+            .bind(user_id.to_string())
+            .bind(format!("{}@example.com", user_id))
+            .bind("password")
+            .execute(db)
+            .await
+            .unwrap();
+        user_id
+    }
+
+    fn session_id(jwt_service: &JwtService, access_token: &str) -> Uuid {
+        Uuid::parse_str(&jwt_service.verify(access_token).unwrap().sid).unwrap()
+    }
+
+    #[sqlx::test]
+    async fn rotate(db: PgPool) {
+        let jwt_service = JwtService::new(b"secret");
+        let user_id = create_user(&db).await;
+        let first = create_session(&db, &jwt_service, user_id).await.unwrap();
+        let session = session_id(&jwt_service, &first.access_token);
+
+        let second = rotate_session(&db, &jwt_service, &first.refresh_token)
+            .await
+            .unwrap();
+        assert_ne!(second.refresh_token, first.refresh_token);
+        assert_eq!(session_id(&jwt_service, &second.access_token), session);
+        assert!(is_session_active(&db, session, user_id).await.unwrap());
+
+        let third = rotate_session(&db, &jwt_service, &second.refresh_token)
+            .await
+            .unwrap();
+        assert_eq!(session_id(&jwt_service, &third.access_token), session);
+    }
+
+    #[sqlx::test]
+    async fn reuse_revokes_session(db: PgPool) {
+        let jwt_service = JwtService::new(b"secret");
+        let user_id = create_user(&db).await;
+        let first = create_session(&db, &jwt_service, user_id).await.unwrap();
+        let session = session_id(&jwt_service, &first.access_token);
+        let second = rotate_session(&db, &jwt_service, &first.refresh_token)
+            .await
+            .unwrap();
+
+        let (status, _) = rotate_session(&db, &jwt_service, &first.refresh_token)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(!is_session_active(&db, session, user_id).await.unwrap());
+
+        // 会话被吊销后，最新的 refresh token 也不能再使用
+        let (status, _) = rotate_session(&db, &jwt_service, &second.refresh_token)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test]
+    async fn expired_session(db: PgPool) {
+        let jwt_service = JwtService::new(b"secret");
+        let user_id = create_user(&db).await;
+        let tokens = create_session(&db, &jwt_service, user_id).await.unwrap();
+        let session = session_id(&jwt_service, &tokens.access_token);
+        sqlx::query(
+            r#"UPDATE "session" SET expires_at = NOW() - INTERVAL '1 minute' WHERE id = $1"#,
+        )
+        .bind(session)
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let (status, _) = rotate_session(&db, &jwt_service, &tokens.refresh_token)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(!is_session_active(&db, session, user_id).await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn unknown_token(db: PgPool) {
+        let jwt_service = JwtService::new(b"secret");
+        let (status, _) = rotate_session(&db, &jwt_service, &generate_refresh_token())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
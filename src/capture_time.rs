@@ -0,0 +1,313 @@
+use time::{Duration, OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+/// 时区偏移的来源，保存在 `photo.captured_at_offset_source`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetSource {
+    /// EXIF OffsetTimeOriginal 或视频元数据中记录的偏移
+    Exif,
+    /// GPS 记录的 UTC 时间与本地时间之差
+    GpsTime,
+    /// 按经度推算的航海时区，只是近似值
+    Longitude,
+}
+
+impl OffsetSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Exif => "exif",
+            Self::GpsTime => "gps_time",
+            Self::Longitude => "longitude",
+        }
+    }
+}
+
+/// 拍摄时间，`offset` 为空时无法确定时区，本地时间按 UTC 保存
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureTime {
+    pub at: OffsetDateTime,
+    pub offset: Option<(UtcOffset, OffsetSource)>,
+}
+
+impl CaptureTime {
+    pub fn offset_seconds(&self) -> Option<i32> {
+        self.offset.map(|(offset, _)| offset.whole_seconds())
+    }
+
+    pub fn offset_source(&self) -> Option<&'static str> {
+        self.offset.map(|(_, source)| source.as_str())
+    }
+}
+
+/// 实际使用的时区偏移范围是 -12:00 ~ +14:00
+const MAX_OFFSET_HOURS: i8 = 14;
+
+/// 解析 `+08:00`、`+0800`、`+08` 和 `Z` 形式的偏移，`-00:00` 按惯例表示时区未知
+pub fn parse_offset(value: &str) -> Option<UtcOffset> {
+    let value = value.trim();
+    if value == "Z" {
+        return Some(UtcOffset::UTC);
+    }
+    if matches!(value, "-00:00" | "-0000" | "-00") {
+        return None;
+    }
+    let sign = match value.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits: String = value[1..].chars().filter(|c| *c != ':').collect();
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i8>().ok()?, 0),
+        4 => (digits[..2].parse().ok()?, digits[2..].parse().ok()?),
+        _ => return None,
+    };
+    if hours > MAX_OFFSET_HOURS {
+        return None;
+    }
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()
+}
+
+/// GPS 的 UTC 时间与本地时间之差，按 15 分钟取整；相差超过 ±14 小时说明其中一个时间有误
+fn offset_from_gps_time(local: PrimitiveDateTime, gps_time: OffsetDateTime) -> Option<UtcOffset> {
+    let diff = (local.assume_utc() - gps_time).whole_seconds();
+    let quarters = (diff as f64 / 900.0).round() as i32;
+    if quarters.abs() > MAX_OFFSET_HOURS as i32 * 4 {
+        return None;
+    }
+    UtcOffset::from_whole_seconds(quarters * 900).ok()
+}
+
+/// 航海时区：每 15° 经度一个小时
+fn offset_from_longitude(longitude: f64) -> Option<UtcOffset> {
+    let hours = (longitude / 15.0).round() as i8;
+    UtcOffset::from_hms(hours.clamp(-12, 12), 0, 0).ok()
+}
+
+/// 由本地时间确定拍摄时刻，依次使用记录的偏移、GPS 时间和经度推算时区
+pub fn from_local(
+    local: PrimitiveDateTime,
+    offset: Option<UtcOffset>,
+    gps_time: Option<OffsetDateTime>,
+    coordinates: Option<(f64, f64)>,
+) -> CaptureTime {
+    let offset = offset
+        .map(|o| (o, OffsetSource::Exif))
+        .or_else(|| {
+            gps_time
+                .and_then(|t| offset_from_gps_time(local, t))
+                .map(|o| (o, OffsetSource::GpsTime))
+        })
+        .or_else(|| {
+            coordinates
+                .and_then(|(_, longitude)| offset_from_longitude(longitude))
+                .map(|o| (o, OffsetSource::Longitude))
+        });
+    CaptureTime {
+        at: local.assume_offset(offset.map_or(UtcOffset::UTC, |(o, _)| o)),
+        offset,
+    }
+}
+
+/// 由 UTC 时刻（例如视频的 `mvhd` 创建时间）确定拍摄时间，只能按经度推算本地时区
+pub fn from_utc(at: OffsetDateTime, coordinates: Option<(f64, f64)>) -> CaptureTime {
+    let offset = coordinates
+        .and_then(|(_, longitude)| offset_from_longitude(longitude))
+        .map(|o| (o, OffsetSource::Longitude));
+    CaptureTime {
+        at: offset.map_or(at, |(o, _)| at.to_offset(o)),
+        offset,
+    }
+}
+
+/// SubSecTimeOriginal 是秒的小数部分的数字，例如 `45` 表示 0.45 秒
+pub fn add_subsec(local: PrimitiveDateTime, subsec: &str) -> PrimitiveDateTime {
+    let digits: String = subsec
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .take(9)
+        .collect();
+    if digits.is_empty() {
+        return local;
+    }
+    let nanos: i64 = format!("{:0<9}", digits).parse().unwrap_or(0);
+    local + Duration::nanoseconds(nanos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{datetime, offset};
+
+    #[test]
+    fn offsets() {
+        let cases = [
+            ("+08:00", Some(offset!(+8))),
+            ("+0800", Some(offset!(+8))),
+            ("+08", Some(offset!(+8))),
+            (" -05:00 ", Some(offset!(-5))),
+            ("+05:45", Some(offset!(+5:45))),
+            ("-03:30", Some(offset!(-3:30))),
+            ("+14:00", Some(offset!(+14))),
+            ("Z", Some(UtcOffset::UTC)),
+            ("+00:00", Some(UtcOffset::UTC)),
+            // 时区未知
+            ("-00:00", None),
+            ("-0000", None),
+            ("+15:00", None),
+            ("+24:00", None),
+            ("+05:60", None),
+            ("+5:00", None),
+            ("08:00", None),
+            ("+08:0a", None),
+            ("+", None),
+            ("", None),
+            ("   :  ", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_offset(value), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn exif_offset_takes_precedence() {
+        let time = from_local(
+            datetime!(2024-05-01 10:20:30),
+            Some(offset!(+5:45)),
+            Some(datetime!(2024-05-01 2:20:30 UTC)),
+            Some((27.7, 85.3)),
+        );
+        assert_eq!(time.at, datetime!(2024-05-01 10:20:30 +5:45));
+        assert_eq!(time.offset_seconds(), Some(5 * 3600 + 45 * 60));
+        assert_eq!(time.offset_source(), Some("exif"));
+    }
+
+    #[test]
+    fn gps_time_offsets() {
+        let cases = [
+            (
+                datetime!(2024-05-01 10:20:30),
+                datetime!(2024-05-01 2:20:30 UTC),
+                Some(offset!(+8)),
+            ),
+            // GPS 时间与相机时钟有几分钟误差
+            (
+                datetime!(2024-05-01 10:20:30),
+                datetime!(2024-05-01 4:38:10 UTC),
+                Some(offset!(+5:45)),
+            ),
+            // 本地时间已经是第二天
+            (
+                datetime!(2024-01-01 0:30:00),
+                datetime!(2023-12-31 16:30:00 UTC),
+                Some(offset!(+8)),
+            ),
+            // 本地时间还是前一天
+            (
+                datetime!(2023-12-31 23:10:00),
+                datetime!(2024-01-01 10:10:00 UTC),
+                Some(offset!(-11)),
+            ),
+            (
+                datetime!(2024-05-01 10:20:30),
+                datetime!(2024-05-01 10:20:30 UTC),
+                Some(UtcOffset::UTC),
+            ),
+            // 相差一天以上，其中一个时间有误
+            (
+                datetime!(2024-05-03 10:20:30),
+                datetime!(2024-05-01 10:20:30 UTC),
+                None,
+            ),
+        ];
+        for (local, gps_time, expected) in cases {
+            let time = from_local(local, None, Some(gps_time), None);
+            assert_eq!(
+                time.offset.map(|(o, _)| o),
+                expected,
+                "{} / {}",
+                local,
+                gps_time
+            );
+            match expected {
+                Some(offset) => {
+                    assert_eq!(time.offset_source(), Some("gps_time"));
+                    assert_eq!(time.at, local.assume_offset(offset));
+                }
+                None => assert_eq!(time.at, local.assume_utc()),
+            }
+        }
+    }
+
+    #[test]
+    fn longitude_offsets() {
+        let cases = [
+            (121.47, offset!(+8)),
+            (-73.99, offset!(-5)),
+            (85.3, offset!(+6)),
+            // 正好在两个时区中间时远离 0 取整
+            (7.5, offset!(+1)),
+            (-7.5, offset!(-1)),
+            (7.49, UtcOffset::UTC),
+            (172.5, offset!(+12)),
+            (180.0, offset!(+12)),
+            (-180.0, offset!(-12)),
+        ];
+        for (longitude, expected) in cases {
+            let local = datetime!(2024-05-01 10:20:30);
+            let time = from_local(local, None, None, Some((0.0, longitude)));
+            assert_eq!(
+                time.offset,
+                Some((expected, OffsetSource::Longitude)),
+                "{}",
+                longitude
+            );
+            assert_eq!(time.at, local.assume_offset(expected));
+        }
+
+        // GPS 时间不可用时使用经度
+        let time = from_local(
+            datetime!(2024-05-03 10:20:30),
+            None,
+            Some(datetime!(2024-05-01 10:20:30 UTC)),
+            Some((31.2, 121.5)),
+        );
+        assert_eq!(time.offset_source(), Some("longitude"));
+
+        let time = from_local(datetime!(2024-05-01 10:20:30), None, None, None);
+        assert_eq!(time.at, datetime!(2024-05-01 10:20:30 UTC));
+        assert_eq!(time.offset, None);
+    }
+
+    #[test]
+    fn utc_times() {
+        let at = datetime!(2024-05-01 2:20:30 UTC);
+        let time = from_utc(at, Some((31.2, 121.5)));
+        assert_eq!(time.at, at);
+        assert_eq!(time.at.offset(), offset!(+8));
+        assert_eq!(time.offset_source(), Some("longitude"));
+
+        let time = from_utc(at, None);
+        assert_eq!(time.at.offset(), UtcOffset::UTC);
+        assert_eq!(time.offset, None);
+    }
+
+    #[test]
+    fn subseconds() {
+        let local = datetime!(2024-05-01 10:20:30);
+        assert_eq!(add_subsec(local, "45"), datetime!(2024-05-01 10:20:30.45));
+        assert_eq!(
+            add_subsec(local, " 007 "),
+            datetime!(2024-05-01 10:20:30.007)
+        );
+        assert_eq!(
+            add_subsec(local, "1234567891"),
+            datetime!(2024-05-01 10:20:30.123456789)
+        );
+        assert_eq!(add_subsec(local, ""), local);
+        assert_eq!(add_subsec(local, "abc"), local);
+    }
+}
@@ -4,11 +4,42 @@ use std::{borrow::Cow, env, fs, path::Path};
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
     pub address: String,
-    pub storage_dir: String,
+    pub storage: StorageConfig,
 
     pub database_url: String,
     pub jwt_secret: String,
     pub ai: AiConfig,
+    /// 升级前的配置文件没有这些段落，缺省时使用默认值
+    #[serde(default)]
+    pub trash: TrashConfig,
+    #[serde(default)]
+    pub gc: GcConfig,
+    #[serde(default)]
+    pub renditions: RenditionConfig,
+    #[serde(default)]
+    pub uploads: UploadConfig,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum StorageConfig {
+    Local {
+        dir: String,
+    },
+    S3(S3Config),
+    /// 仅用于开发和测试，重启后数据丢失
+    Memory,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// 使用 `endpoint/bucket/key` 形式的地址，MinIO 等自建服务通常需要开启
+    pub path_style: bool,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -19,18 +50,150 @@ pub struct AiConfig {
     pub api_key: String,
 }
 
+/// 回收站保留天数的上限（100 年），清理时的时间计算不会溢出
+pub const MAX_RETENTION_DAYS: u32 = 36_500;
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// 照片在回收站中保留的天数，超过后被永久删除
+    pub retention_days: u32,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self { retention_days: 30 }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct GcConfig {
+    /// 两次回收孤立 image 之间的间隔
+    pub interval_hours: u32,
+    /// 最近被上传过的 image 在宽限期内不会被回收
+    pub grace_period_minutes: u32,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            interval_hours: 24,
+            grace_period_minutes: 60,
+        }
+    }
+}
+
+/// 上传会话保留小时数的上限（30 天）
+pub const MAX_SESSION_TTL_HOURS: u32 = 720;
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct UploadConfig {
+    /// 分块上传会话在最后一次活动后保留的小时数，过期后已上传的分块被删除
+    pub session_ttl_hours: u32,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            session_ttl_hours: 24,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RenditionConfig {
+    /// 可用的缩略图尺寸（长边像素数）
+    pub sizes: Vec<u32>,
+    pub format: RenditionFormat,
+    /// JPEG 编码质量，1-100
+    pub quality: u8,
+}
+
+impl Default for RenditionConfig {
+    fn default() -> Self {
+        Self {
+            sizes: vec![256, 1024, 2048],
+            format: RenditionFormat::Jpeg,
+            quality: 82,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RenditionFormat {
+    Jpeg,
+    /// `image` 只提供无损的 WebP 编码器，忽略 `quality`，文件通常比 JPEG 大得多
+    Webp,
+}
+
 impl AppConfig {
     pub fn new(toml_path: &Path) -> Self {
         tracing::info!("Loading config from file: {}", toml_path.display());
         let config_content = fs::read_to_string(toml_path)
             .unwrap_or_else(|_| panic!("Failed to read config file at {:?}", toml_path));
         let processed_content = Self::expand_env_vars(&config_content);
-        let config: AppConfig = toml::from_str(&processed_content)
+        let mut table: toml::Table = toml::from_str(&processed_content)
+            .expect("Failed to parse config file after env injection");
+        Self::migrate_legacy_keys(&mut table);
+        let config: AppConfig = table
+            .try_into()
             .expect("Failed to parse config file after env injection");
 
+        if let Err(message) = config.validate() {
+            panic!("Invalid config: {}", message);
+        }
+        if config.renditions.format == RenditionFormat::Webp {
+            tracing::warn!(
+                "WebP renditions are lossless and usually much larger than JPEG, `renditions.quality` is ignored"
+            );
+        }
         config
     }
 
+    /// 兼容旧版配置：顶层的 `storage_dir` 等价于 `[storage] backend = "local"`
+    fn migrate_legacy_keys(table: &mut toml::Table) {
+        let Some(dir) = table.remove("storage_dir") else {
+            return;
+        };
+        if table.contains_key("storage") {
+            tracing::warn!("Both `storage_dir` and `[storage]` are set, ignoring `storage_dir`");
+            return;
+        }
+        tracing::warn!(
+            "`storage_dir` is deprecated, use `[storage]` with `backend = \"local\"` and `dir` instead"
+        );
+        let mut storage = toml::Table::new();
+        storage.insert("backend".to_string(), "local".into());
+        storage.insert("dir".to_string(), dir);
+        table.insert("storage".to_string(), storage.into());
+    }
+
+    /// 检查反序列化无法表达的约束，避免在后台任务中才出错
+    fn validate(&self) -> Result<(), String> {
+        if self.gc.interval_hours == 0 {
+            return Err("gc.interval_hours must be greater than 0".to_string());
+        }
+        if self.trash.retention_days > MAX_RETENTION_DAYS {
+            return Err(format!(
+                "trash.retention_days must be at most {}",
+                MAX_RETENTION_DAYS
+            ));
+        }
+        if self.uploads.session_ttl_hours == 0
+            || self.uploads.session_ttl_hours > MAX_SESSION_TTL_HOURS
+        {
+            return Err(format!(
+                "uploads.session_ttl_hours must be between 1 and {}",
+                MAX_SESSION_TTL_HOURS
+            ));
+        }
+        Ok(())
+    }
+
     /// 手动解析并替换 {{ $VAR }}
     fn expand_env_vars(input: &'_ str) -> Cow<'_, str> {
         if !input.contains("{{") {
@@ -91,3 +254,88 @@ impl AppConfig {
         Cow::Owned(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage_of(content: &str) -> StorageConfig {
+        let mut table: toml::Table = toml::from_str(content).unwrap();
+        AppConfig::migrate_legacy_keys(&mut table);
+        table["storage"].clone().try_into().unwrap()
+    }
+
+    fn config_of(extra: &str) -> AppConfig {
+        let content = format!(
+            r#"
+            address = "0.0.0.0:8080"
+            database_url = "postgres://localhost/moments_aura"
+            jwt_secret = "secret"
+            [storage]
+            backend = "memory"
+            [ai]
+            enable = false
+            model = ""
+            base_url = ""
+            api_key = ""
+            {}
+            "#,
+            extra
+        );
+        toml::from_str(&content).unwrap()
+    }
+
+    #[test]
+    fn validate() {
+        assert!(config_of("").validate().is_ok());
+        assert!(
+            config_of("[trash]\nretention_days = 36500")
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            config_of("[trash]\nretention_days = 36501")
+                .validate()
+                .is_err()
+        );
+        assert!(
+            config_of("[trash]\nretention_days = 4294967295")
+                .validate()
+                .is_err()
+        );
+        assert!(config_of("[gc]\ninterval_hours = 0").validate().is_err());
+        assert!(
+            config_of("[uploads]\nsession_ttl_hours = 720")
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            config_of("[uploads]\nsession_ttl_hours = 0")
+                .validate()
+                .is_err()
+        );
+        assert!(
+            config_of("[uploads]\nsession_ttl_hours = 4294967295")
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn legacy_storage_dir() {
+        let storage = storage_of(r#"storage_dir = "./data""#);
+        assert!(matches!(storage, StorageConfig::Local { dir } if dir == "./data"));
+    }
+
+    #[test]
+    fn storage_section_wins_over_storage_dir() {
+        let storage = storage_of(
+            r#"
+            storage_dir = "./data"
+            [storage]
+            backend = "memory"
+            "#,
+        );
+        assert!(matches!(storage, StorageConfig::Memory));
+    }
+}
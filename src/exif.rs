@@ -1,74 +1,663 @@
-use std::io::Cursor;
+use std::io::{BufRead, Seek};
 
 use exif::{Exif, Tag};
-use time::{PrimitiveDateTime, macros::format_description};
+use serde::Serialize;
+use time::{Date, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset, macros::format_description};
 
-pub fn get_image_exif<B: AsRef<[u8]>>(image_bytes: B) -> Option<Exif> {
-    let mut cursor = Cursor::new(image_bytes);
-    let reader = exif::Reader::new();
-    reader.read_from_container(&mut cursor).ok()
+use crate::capture_time;
+
+pub fn get_image_exif<R: BufRead + Seek>(mut reader: R) -> Option<Exif> {
+    exif::Reader::new().read_from_container(&mut reader).ok()
+}
+
+/// 拍摄参数，保存在 `photo_metadata` 表中
+#[derive(Debug, Default, Serialize)]
+pub struct PhotoMetadata {
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens_make: Option<String>,
+    pub lens_model: Option<String>,
+    /// 焦距，单位 mm
+    pub focal_length: Option<f64>,
+    pub focal_length_35mm: Option<i32>,
+    /// 光圈 F 值
+    pub aperture: Option<f64>,
+    /// 快门时间，单位秒
+    pub exposure_time: Option<f64>,
+    pub iso: Option<i32>,
+    /// EXIF Orientation，1 ~ 8
+    pub orientation: Option<i16>,
+    /// EXIF Flash 原始值，最低位表示是否闪光
+    pub flash: Option<i32>,
+    /// OffsetTimeOriginal，例如 `+08:00`
+    pub offset_time: Option<String>,
+    /// GPS 记录的 UTC 时间
+    #[serde(with = "time::serde::timestamp::option")]
+    pub gps_timestamp: Option<OffsetDateTime>,
 }
 
 pub struct ParseExifResult {
+    /// DateTimeOriginal 加上 SubSecTimeOriginal，拍摄地的本地时间
     pub date_time: Option<PrimitiveDateTime>,
+    /// OffsetTimeOriginal
+    pub offset: Option<UtcOffset>,
+    /// (纬度, 经度)，南纬和西经为负
     pub coordinates: Option<(f64, f64)>,
+    /// 海拔，单位 m，海平面以下为负
+    pub altitude: Option<f64>,
+    pub metadata: PhotoMetadata,
+}
+
+/// 读取 ASCII 字段，去掉末尾的 NUL 和空格，空字符串视为不存在
+fn ascii_value(value: &exif::Value) -> Option<String> {
+    if let exif::Value::Ascii(ref v) = *value {
+        let v = String::from_utf8_lossy(v.first()?);
+        let v = v.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        if !v.is_empty() {
+            return Some(v.to_string());
+        }
+    }
+    None
+}
+
+/// 读取第一个有理数，分母为 0 时视为不存在
+fn rational_value(value: &exif::Value) -> Option<f64> {
+    match *value {
+        exif::Value::Rational(ref v) => v.first().filter(|r| r.denom != 0).map(|r| r.to_f64()),
+        _ => None,
+    }
+}
+
+/// 度/分/秒三个有理数转换为十进制度数，部分设备只写度和带小数的分。
+/// 缺失的秒常被写成 `0/0`，按 0 处理，其他分母为 0 的值视为无效
+fn dms_to_degrees(value: &exif::Value) -> Option<f64> {
+    let exif::Value::Rational(ref v) = *value else {
+        return None;
+    };
+    if v.is_empty() || v.len() > 3 {
+        return None;
+    }
+    let mut degrees = 0.0;
+    for (r, unit) in v.iter().zip([1.0, 60.0, 3600.0]) {
+        match (r.num, r.denom) {
+            (0, 0) => {}
+            (_, 0) => return None,
+            _ => degrees += r.to_f64() / unit,
+        }
+    }
+    degrees.is_finite().then_some(degrees)
+}
+
+/// 参考方向的第一个字符，例如 `N` / `S`
+fn ref_value(value: &exif::Value) -> Option<u8> {
+    ascii_value(value).and_then(|v| v.bytes().next().map(|b| b.to_ascii_uppercase()))
+}
+
+/// GPSDateStamp（`YYYY:MM:DD`）和 GPSTimeStamp（时/分/秒三个有理数）组成的 UTC 时间
+fn gps_timestamp(date: &exif::Value, time: &exif::Value) -> Option<OffsetDateTime> {
+    let date = Date::parse(
+        &ascii_value(date)?,
+        format_description!("[year]:[month]:[day]"),
+    )
+    .ok()?;
+    let exif::Value::Rational(ref v) = *time else {
+        return None;
+    };
+    let [hour, minute, second] = v.as_slice() else {
+        return None;
+    };
+    if hour.denom == 0 || minute.denom == 0 || second.denom == 0 {
+        return None;
+    }
+    let second = second.to_f64();
+    let time = Time::from_hms_milli(
+        u8::try_from(hour.num / hour.denom).ok()?,
+        u8::try_from(minute.num / minute.denom).ok()?,
+        second.trunc() as u8,
+        (second.fract() * 1000.0) as u16,
+    )
+    .ok()?;
+    Some(PrimitiveDateTime::new(date, time).assume_utc())
 }
 
 pub fn parse_exif(exif: &Exif) -> ParseExifResult {
     let mut date_time: Option<PrimitiveDateTime> = None;
+    let mut subsec: Option<String> = None;
     let mut latitude: Option<f64> = None;
     let mut longitude: Option<f64> = None;
-    let mut latitude_sign: f64 = 1.0;
-    let mut longitude_sign: f64 = 1.0;
+    let mut latitude_ref: Option<u8> = None;
+    let mut longitude_ref: Option<u8> = None;
+    let mut altitude: Option<f64> = None;
+    let mut below_sea_level = false;
+    let mut gps_date: Option<&exif::Value> = None;
+    let mut gps_time: Option<&exif::Value> = None;
+    let mut metadata = PhotoMetadata::default();
     for field in exif.fields() {
+        // RAW 的缩略图 IFD 中也可能有这些字段，只使用主图像的
+        if field.ifd_num != exif::In::PRIMARY {
+            continue;
+        }
         match field.tag {
             Tag::DateTimeOriginal => {
-                if let exif::Value::Ascii(ref v) = field.value {
-                    let v = &v[0];
-                    date_time = match time::PrimitiveDateTime::parse(
-                        &String::from_utf8_lossy(v),
+                date_time = ascii_value(&field.value).and_then(|v| {
+                    PrimitiveDateTime::parse(
+                        &v,
                         format_description!("[year]:[month]:[day] [hour]:[minute]:[second]"),
-                    ) {
-                        Ok(t) => Some(t),
-                        Err(_) => None,
-                    }
-                }
+                    )
+                    .ok()
+                })
             }
-            Tag::GPSLatitude => {
-                if let exif::Value::Rational(ref v) = field.value {
-                    let v = &v[0];
-                    latitude = Some(v.num as f64 / v.denom as f64);
-                }
+            Tag::SubSecTimeOriginal => subsec = ascii_value(&field.value),
+            Tag::GPSLatitude => latitude = dms_to_degrees(&field.value),
+            Tag::GPSLongitude => longitude = dms_to_degrees(&field.value),
+            Tag::GPSLatitudeRef => latitude_ref = ref_value(&field.value),
+            Tag::GPSLongitudeRef => longitude_ref = ref_value(&field.value),
+            Tag::GPSAltitude => altitude = rational_value(&field.value),
+            // 0 为海平面以上，1 为海平面以下
+            Tag::GPSAltitudeRef => below_sea_level = field.value.get_uint(0) == Some(1),
+            Tag::GPSDateStamp => gps_date = Some(&field.value),
+            Tag::GPSTimeStamp => gps_time = Some(&field.value),
+            Tag::Make => metadata.camera_make = ascii_value(&field.value),
+            Tag::Model => metadata.camera_model = ascii_value(&field.value),
+            Tag::LensMake => metadata.lens_make = ascii_value(&field.value),
+            Tag::LensModel => metadata.lens_model = ascii_value(&field.value),
+            Tag::FocalLength => metadata.focal_length = rational_value(&field.value),
+            Tag::FocalLengthIn35mmFilm => {
+                metadata.focal_length_35mm = field.value.get_uint(0).map(|v| v as i32)
             }
-            Tag::GPSLongitude => {
-                if let exif::Value::Rational(ref v) = field.value {
-                    let v = &v[0];
-                    longitude = Some(v.num as f64 / v.denom as f64);
-                }
+            Tag::FNumber => metadata.aperture = rational_value(&field.value),
+            Tag::ExposureTime => metadata.exposure_time = rational_value(&field.value),
+            Tag::PhotographicSensitivity => {
+                metadata.iso = field.value.get_uint(0).map(|v| v as i32)
             }
-            Tag::GPSLatitudeRef => {
-                if let exif::Value::Ascii(ref v) = field.value {
-                    let v = &v[0];
-                    latitude_sign = if v[0] == b'S' { -1.0 } else { 1.0 };
-                }
-            }
-            Tag::GPSLongitudeRef => {
-                if let exif::Value::Ascii(ref v) = field.value {
-                    let v = &v[0];
-                    longitude_sign = if v[0] == b'W' { -1.0 } else { 1.0 };
-                }
+            Tag::Orientation => {
+                metadata.orientation = field
+                    .value
+                    .get_uint(0)
+                    .filter(|v| (1..=8).contains(v))
+                    .map(|v| v as i16)
             }
+            Tag::Flash => metadata.flash = field.value.get_uint(0).map(|v| v as i32),
+            Tag::OffsetTimeOriginal => metadata.offset_time = ascii_value(&field.value),
             _ => {}
         }
     }
     let coordinates = match (latitude, longitude) {
-        (Some(latitude), Some(longitude)) => {
+        (Some(latitude), Some(longitude))
+            if latitude <= 90.0
+                && longitude <= 180.0
+                // 没有定位时部分设备会写入全 0 的坐标
+                && (latitude, longitude) != (0.0, 0.0) =>
+        {
+            let latitude_sign = if latitude_ref == Some(b'S') {
+                -1.0
+            } else {
+                1.0
+            };
+            let longitude_sign = if longitude_ref == Some(b'W') {
+                -1.0
+            } else {
+                1.0
+            };
             Some((latitude_sign * latitude, longitude_sign * longitude))
         }
         _ => None,
     };
+    let altitude = altitude.map(|v| if below_sea_level { -v } else { v });
+    let date_time = match (date_time, subsec) {
+        (Some(date_time), Some(subsec)) => Some(capture_time::add_subsec(date_time, &subsec)),
+        (date_time, _) => date_time,
+    };
+    let offset = metadata
+        .offset_time
+        .as_deref()
+        .and_then(capture_time::parse_offset);
+    metadata.gps_timestamp = gps_date
+        .zip(gps_time)
+        .and_then(|(date, time)| gps_timestamp(date, time));
     ParseExifResult {
         date_time,
+        offset,
         coordinates,
+        altitude,
+        metadata,
+    }
+}
+
+/// EXIF Orientation，缺失或无效时为 1（不旋转）
+pub fn orientation(exif: &Exif) -> u8 {
+    exif.get_field(Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        .filter(|v| (1..=8).contains(v))
+        .map_or(1, |v| v as u8)
+}
+
+/// Apple MakerNote 中的 ContentIdentifier（tag 0x11），Live Photo 的图片和视频共享这个标识
+pub fn apple_content_identifier(exif: &Exif) -> Option<String> {
+    let field = exif.get_field(Tag::MakerNote, exif::In::PRIMARY)?;
+    let exif::Value::Undefined(ref note, _) = field.value else {
+        return None;
+    };
+    // "Apple iOS\0"、2 字节版本号、"MM"，然后是 IFD，偏移量相对于 MakerNote 开头
+    if !note.starts_with(b"Apple iOS\0") || note.get(12..14) != Some(b"MM") {
+        return None;
+    }
+    let read_u16 = |offset: usize| {
+        Some(u16::from_be_bytes(
+            note.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    };
+    let read_u32 = |offset: usize| {
+        Some(u32::from_be_bytes(
+            note.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+
+    let count = read_u16(14)? as usize;
+    (0..count).find_map(|i| {
+        let entry = 16 + i * 12;
+        // 类型 2 为 ASCII
+        if read_u16(entry)? != 0x11 || read_u16(entry + 2)? != 2 {
+            return None;
+        }
+        let len = read_u32(entry + 4)? as usize;
+        let value = if len <= 4 {
+            note.get(entry + 8..entry + 8 + len)?
+        } else {
+            let offset = read_u32(entry + 8)? as usize;
+            note.get(offset..offset + len)?
+        };
+        let value = String::from_utf8_lossy(value);
+        let value = value.trim_end_matches('\0');
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    const TYPE_BYTE: u16 = 1;
+    const TYPE_ASCII: u16 = 2;
+    const TYPE_RATIONAL: u16 = 5;
+
+    const EXIF_IFD_POINTER: u16 = 0x8769;
+    const GPS_IFD_POINTER: u16 = 0x8825;
+    const DATE_TIME_ORIGINAL: u16 = 0x9003;
+    const GPS_LATITUDE_REF: u16 = 1;
+    const GPS_LATITUDE: u16 = 2;
+    const GPS_LONGITUDE_REF: u16 = 3;
+    const GPS_LONGITUDE: u16 = 4;
+    const GPS_ALTITUDE_REF: u16 = 5;
+    const GPS_ALTITUDE: u16 = 6;
+    const GPS_TIME_STAMP: u16 = 7;
+    const GPS_DATE_STAMP: u16 = 29;
+
+    struct Entry {
+        tag: u16,
+        kind: u16,
+        count: u32,
+        data: Vec<u8>,
+    }
+
+    fn ascii(tag: u16, value: &str) -> Entry {
+        let mut data = value.as_bytes().to_vec();
+        data.push(0);
+        Entry {
+            tag,
+            kind: TYPE_ASCII,
+            count: data.len() as u32,
+            data,
+        }
+    }
+
+    fn rationals(tag: u16, values: &[(u32, u32)]) -> Entry {
+        Entry {
+            tag,
+            kind: TYPE_RATIONAL,
+            count: values.len() as u32,
+            data: values
+                .iter()
+                .flat_map(|(num, denom)| [num.to_le_bytes(), denom.to_le_bytes()].concat())
+                .collect(),
+        }
+    }
+
+    fn byte(tag: u16, value: u8) -> Entry {
+        Entry {
+            tag,
+            kind: TYPE_BYTE,
+            count: 1,
+            data: vec![value],
+        }
+    }
+
+    fn pointer(tag: u16, offset: u32) -> Entry {
+        Entry {
+            tag,
+            kind: 4,
+            count: 1,
+            data: offset.to_le_bytes().to_vec(),
+        }
+    }
+
+    /// 写入 IFD 及其数据区，超过 4 字节的值放在 IFD 之后
+    fn write_ifd(buf: &mut Vec<u8>, mut entries: Vec<Entry>) {
+        entries.sort_by_key(|e| e.tag);
+        let data_start = buf.len() + 2 + entries.len() * 12 + 4;
+        let mut data = Vec::new();
+        buf.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for entry in &entries {
+            buf.extend_from_slice(&entry.tag.to_le_bytes());
+            buf.extend_from_slice(&entry.kind.to_le_bytes());
+            buf.extend_from_slice(&entry.count.to_le_bytes());
+            if entry.data.len() <= 4 {
+                let mut value = entry.data.clone();
+                value.resize(4, 0);
+                buf.extend_from_slice(&value);
+            } else {
+                buf.extend_from_slice(&((data_start + data.len()) as u32).to_le_bytes());
+                data.extend_from_slice(&entry.data);
+                if data.len() % 2 == 1 {
+                    data.push(0);
+                }
+            }
+        }
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(&data);
+    }
+
+    /// 构造只包含 Exif IFD 和 GPS IFD 的小端 TIFF，然后用 kamadak-exif 解析
+    fn build(exif: Vec<Entry>, gps: Vec<Entry>) -> Exif {
+        let size = |entries: &[Entry]| {
+            2 + entries.len() * 12
+                + 4
+                + entries
+                    .iter()
+                    .filter(|e| e.data.len() > 4)
+                    .map(|e| e.data.len().div_ceil(2) * 2)
+                    .sum::<usize>()
+        };
+        let ifd0_len = 2 + 2 * 12 + 4;
+        let exif_offset = 8 + ifd0_len;
+        let gps_offset = exif_offset + size(&exif);
+
+        let mut buf = b"II*\0".to_vec();
+        buf.extend_from_slice(&8u32.to_le_bytes());
+        write_ifd(
+            &mut buf,
+            vec![
+                pointer(EXIF_IFD_POINTER, exif_offset as u32),
+                pointer(GPS_IFD_POINTER, gps_offset as u32),
+            ],
+        );
+        write_ifd(&mut buf, exif);
+        assert_eq!(buf.len(), gps_offset);
+        write_ifd(&mut buf, gps);
+        exif::Reader::new().read_raw(buf).expect("valid fixture")
+    }
+
+    fn gps(lat: &[(u32, u32)], lat_ref: &str, lon: &[(u32, u32)], lon_ref: &str) -> Vec<Entry> {
+        vec![
+            ascii(GPS_LATITUDE_REF, lat_ref),
+            rationals(GPS_LATITUDE, lat),
+            ascii(GPS_LONGITUDE_REF, lon_ref),
+            rationals(GPS_LONGITUDE, lon),
+        ]
+    }
+
+    fn assert_coordinates(actual: Option<(f64, f64)>, expected: (f64, f64)) {
+        let (lat, lon) = actual.expect("coordinates");
+        assert!(
+            (lat - expected.0).abs() < 1e-6,
+            "latitude {lat} != {}",
+            expected.0
+        );
+        assert!(
+            (lon - expected.1).abs() < 1e-6,
+            "longitude {lon} != {}",
+            expected.1
+        );
+    }
+
+    #[test]
+    fn iphone_dms_with_fractional_seconds() {
+        // iPhone 15 Pro，上海外滩
+        let mut entries = gps(
+            &[(31, 1), (13, 1), (5226, 100)],
+            "N",
+            &[(121, 1), (28, 1), (2556, 100)],
+            "E",
+        );
+        entries.push(byte(GPS_ALTITUDE_REF, 0));
+        entries.push(rationals(GPS_ALTITUDE, &[(1234, 100)]));
+        entries.push(ascii(GPS_DATE_STAMP, "2024:05:01"));
+        entries.push(rationals(GPS_TIME_STAMP, &[(2, 1), (20, 1), (3050, 100)]));
+        let exif = build(
+            vec![ascii(DATE_TIME_ORIGINAL, "2024:05:01 10:20:30")],
+            entries,
+        );
+
+        let result = parse_exif(&exif);
+        assert_coordinates(
+            result.coordinates,
+            (
+                31.0 + 13.0 / 60.0 + 52.26 / 3600.0,
+                121.0 + 28.0 / 60.0 + 25.56 / 3600.0,
+            ),
+        );
+        assert_eq!(result.altitude, Some(12.34));
+        assert_eq!(result.date_time, Some(datetime!(2024-05-01 10:20:30)));
+        assert_eq!(
+            result.metadata.gps_timestamp,
+            Some(datetime!(2024-05-01 02:20:30.5 UTC))
+        );
+    }
+
+    #[test]
+    fn android_west_longitude_with_large_denominators() {
+        // Pixel 8，纽约
+        let exif = build(
+            vec![],
+            gps(
+                &[(40, 1), (44, 1), (296880, 10000)],
+                "N",
+                &[(73, 1), (59, 1), (12000, 10000)],
+                "W",
+            ),
+        );
+        assert_coordinates(
+            parse_exif(&exif).coordinates,
+            (
+                40.0 + 44.0 / 60.0 + 29.688 / 3600.0,
+                -(73.0 + 59.0 / 60.0 + 1.2 / 3600.0),
+            ),
+        );
+    }
+
+    #[test]
+    fn degrees_and_decimal_minutes() {
+        // Garmin / 部分单反的 GPS 模块：秒为 0，分带小数
+        let exif = build(
+            vec![],
+            gps(
+                &[(51, 1), (30123, 1000), (0, 1)],
+                "N",
+                &[(0, 1), (7567, 1000), (0, 1)],
+                "W",
+            ),
+        );
+        assert_coordinates(
+            parse_exif(&exif).coordinates,
+            (51.0 + 30.123 / 60.0, -(7.567 / 60.0)),
+        );
+    }
+
+    #[test]
+    fn southern_hemisphere_with_lowercase_ref() {
+        // 悉尼，部分应用写入小写的参考方向
+        let exif = build(
+            vec![],
+            gps(
+                &[(33, 1), (51, 1), (5400, 100)],
+                "s",
+                &[(151, 1), (12, 1), (3600, 100)],
+                "e",
+            ),
+        );
+        assert_coordinates(
+            parse_exif(&exif).coordinates,
+            (
+                -(33.0 + 51.0 / 60.0 + 54.0 / 3600.0),
+                151.0 + 12.0 / 60.0 + 36.0 / 3600.0,
+            ),
+        );
+    }
+
+    #[test]
+    fn altitude_below_sea_level() {
+        // 死海
+        let mut entries = gps(
+            &[(31, 1), (30, 1), (0, 1)],
+            "N",
+            &[(35, 1), (30, 1), (0, 1)],
+            "E",
+        );
+        entries.push(byte(GPS_ALTITUDE_REF, 1));
+        entries.push(rationals(GPS_ALTITUDE, &[(430, 1)]));
+        let result = parse_exif(&build(vec![], entries));
+        assert_coordinates(result.coordinates, (31.5, 35.5));
+        assert_eq!(result.altitude, Some(-430.0));
+    }
+
+    #[test]
+    fn missing_seconds_written_as_zero_over_zero() {
+        let exif = build(
+            vec![],
+            gps(
+                &[(48, 1), (51, 1), (0, 0)],
+                "N",
+                &[(2, 1), (21, 1), (0, 0)],
+                "E",
+            ),
+        );
+        assert_coordinates(
+            parse_exif(&exif).coordinates,
+            (48.0 + 51.0 / 60.0, 2.0 + 21.0 / 60.0),
+        );
+    }
+
+    #[test]
+    fn single_decimal_degree_component() {
+        let exif = build(
+            vec![],
+            gps(&[(3523456, 100000)], "N", &[(13974321, 100000)], "E"),
+        );
+        assert_coordinates(parse_exif(&exif).coordinates, (35.23456, 139.74321));
+    }
+
+    #[test]
+    fn missing_ref_defaults_to_north_east() {
+        let exif = build(
+            vec![],
+            vec![
+                rationals(GPS_LATITUDE, &[(22, 1), (30, 1), (0, 1)]),
+                rationals(GPS_LONGITUDE, &[(114, 1), (15, 1), (0, 1)]),
+            ],
+        );
+        assert_coordinates(parse_exif(&exif).coordinates, (22.5, 114.25));
+    }
+
+    #[test]
+    fn malformed_zero_denominator_is_rejected() {
+        let exif = build(
+            vec![],
+            gps(
+                &[(31, 0), (13, 1), (0, 1)],
+                "N",
+                &[(121, 1), (28, 1), (0, 1)],
+                "E",
+            ),
+        );
+        assert_eq!(parse_exif(&exif).coordinates, None);
+    }
+
+    #[test]
+    fn malformed_missing_longitude() {
+        let exif = build(
+            vec![],
+            vec![
+                ascii(GPS_LATITUDE_REF, "N"),
+                rationals(GPS_LATITUDE, &[(31, 1), (13, 1), (0, 1)]),
+            ],
+        );
+        assert_eq!(parse_exif(&exif).coordinates, None);
+    }
+
+    #[test]
+    fn malformed_all_zero_coordinates() {
+        // 没有定位时写入的占位坐标
+        let exif = build(
+            vec![],
+            gps(
+                &[(0, 1), (0, 1), (0, 1)],
+                "N",
+                &[(0, 1), (0, 1), (0, 1)],
+                "E",
+            ),
+        );
+        assert_eq!(parse_exif(&exif).coordinates, None);
+    }
+
+    #[test]
+    fn malformed_out_of_range_latitude() {
+        let exif = build(
+            vec![],
+            gps(
+                &[(95, 1), (0, 1), (0, 1)],
+                "N",
+                &[(10, 1), (0, 1), (0, 1)],
+                "E",
+            ),
+        );
+        assert_eq!(parse_exif(&exif).coordinates, None);
+    }
+
+    #[test]
+    fn malformed_gps_timestamp() {
+        let mut entries = vec![
+            ascii(GPS_DATE_STAMP, "2024:05:01"),
+            rationals(GPS_TIME_STAMP, &[(25, 1), (0, 1), (0, 1)]),
+        ];
+        assert_eq!(
+            parse_exif(&build(vec![], entries)).metadata.gps_timestamp,
+            None
+        );
+
+        // 只有时间没有日期
+        entries = vec![rationals(GPS_TIME_STAMP, &[(2, 1), (20, 1), (30, 1)])];
+        assert_eq!(
+            parse_exif(&build(vec![], entries)).metadata.gps_timestamp,
+            None
+        );
+
+        entries = vec![
+            ascii(GPS_DATE_STAMP, "    :  :  "),
+            rationals(GPS_TIME_STAMP, &[(2, 1), (20, 1), (30, 1)]),
+        ];
+        assert_eq!(
+            parse_exif(&build(vec![], entries)).metadata.gps_timestamp,
+            None
+        );
+    }
+
+    #[test]
+    fn malformed_date_time_original() {
+        // 未设置时间的相机会写入空格或全 0
+        for value in ["", "    :  :     :  :  ", "0000:00:00 00:00:00"] {
+            let exif = build(vec![ascii(DATE_TIME_ORIGINAL, value)], vec![]);
+            assert_eq!(parse_exif(&exif).date_time, None, "{value:?}");
+        }
     }
 }
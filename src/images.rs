@@ -1,12 +1,32 @@
+pub mod backfill;
+pub mod bmff;
+pub mod format;
+pub mod gc;
+pub mod heif;
+pub mod raw;
+pub mod scrub;
+pub mod video;
 use axum::http::StatusCode;
 use bytes::Bytes;
 use exif::Exif;
+use futures_util::{Stream, TryStreamExt};
 use image::ImageReader;
 use sha2::{Digest, Sha256};
 use sqlx::PgPool;
-use std::io::Cursor;
+use std::{
+    fs::File,
+    io::{BufReader, Seek, SeekFrom},
+    path::Path,
+};
+use tempfile::TempPath;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
 
-use crate::{exif::get_image_exif, infra::storage::LocalStorage};
+use crate::{
+    exif::{apple_content_identifier, get_image_exif, orientation},
+    images::format::MediaFormat,
+    infra::storage::{Storage, StorageError},
+};
 
 pub fn get_image_hash<B: AsRef<[u8]>>(image_bytes: B) -> String {
     let hash = Sha256::digest(image_bytes);
@@ -14,6 +34,64 @@ pub fn get_image_hash<B: AsRef<[u8]>>(image_bytes: B) -> String {
     hash_str
 }
 
+#[derive(Debug, Error)]
+pub enum StageError {
+    #[error("File exceeds the size limit of {0} bytes")]
+    TooLarge(u64),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// 暂存在本地临时目录中的上传文件，写入时增量计算 hash，drop 时删除
+pub struct StagedFile {
+    pub path: TempPath,
+    pub hash: String,
+    pub size: u64,
+}
+
+impl StagedFile {
+    /// 将数据流写入临时文件，内存占用只与单个数据块的大小有关，
+    /// 超过 `max_size` 时立即停止并返回 `StageError::TooLarge`
+    pub async fn from_stream<S, E>(stream: S, max_size: u64) -> Result<Self, StageError>
+    where
+        S: Stream<Item = Result<Bytes, E>>,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Self::from_stream_in(&std::env::temp_dir(), stream, max_size).await
+    }
+
+    async fn from_stream_in<S, E>(dir: &Path, stream: S, max_size: u64) -> Result<Self, StageError>
+    where
+        S: Stream<Item = Result<Bytes, E>>,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let path = tempfile::Builder::new()
+            .prefix("moments-aura-upload-")
+            .tempfile_in(dir)?
+            .into_temp_path();
+        let mut file = tokio::fs::File::create(&path).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+
+        let mut stream = std::pin::pin!(stream.map_err(std::io::Error::other));
+        while let Some(chunk) = stream.try_next().await? {
+            hasher.update(&chunk);
+            size += chunk.len() as u64;
+            if size > max_size {
+                return Err(StageError::TooLarge(max_size));
+            }
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+
+        Ok(Self {
+            path,
+            hash: format!("{:x}", hasher.finalize()),
+            size,
+        })
+    }
+}
+
 pub struct ImageInfo {
     pub hash: String,
     pub size: u64,
@@ -21,97 +99,250 @@ pub struct ImageInfo {
     pub width: u32,
     pub height: u32,
     pub exif: Option<Exif>,
+    /// 仅视频有值
+    pub video: Option<video::VideoInfo>,
+    /// Live Photo 的配对标识，图片来自 Apple MakerNote，视频来自 QuickTime 元数据
+    pub content_identifier: Option<String>,
 }
 
-pub fn get_image_info<B: AsRef<[u8]>>(image_bytes: B) -> Result<ImageInfo, (StatusCode, String)> {
-    let hash = get_image_hash(&image_bytes);
-    let size = image_bytes.as_ref().len() as u64;
-    // meta data
-    let cursor = Cursor::new(&image_bytes);
-    let reader = ImageReader::new(cursor)
-        .with_guessed_format()
-        .map_err(|e| {
-            tracing::warn!(error = ?e, "Failed to read image");
-            (StatusCode::BAD_REQUEST, "Invalid image format".to_string())
+/// 从磁盘读取图片格式、尺寸和 EXIF，只会读取文件头部，不会解码整张图片；
+/// 视频只读取 `moov` box 中的元数据
+pub fn get_image_info(
+    path: &Path,
+    hash: String,
+    size: u64,
+) -> Result<ImageInfo, (StatusCode, String)> {
+    let invalid_format = || (StatusCode::BAD_REQUEST, "Invalid image format".to_string());
+    let read_error = |e: std::io::Error| {
+        tracing::warn!(error = ?e, "Failed to read image");
+        invalid_format()
+    };
+    let mut file = BufReader::new(File::open(path).map_err(read_error)?);
+
+    // 按文件内容识别格式，不信任客户端提供的 content type
+    let format = MediaFormat::detect(&mut file).ok_or_else(|| {
+        tracing::info!("Unrecognized image format");
+        invalid_format()
+    })?;
+
+    file.seek(SeekFrom::Start(0)).map_err(read_error)?;
+    if format.is_video() {
+        let video = video::probe(&mut file).ok_or_else(|| {
+            tracing::warn!(?format, "Failed to read video metadata");
+            invalid_format()
         })?;
-    let format = reader
-        .format()
-        .ok_or((StatusCode::BAD_REQUEST, "Invalid image format".to_string()))?;
-    let extension = match format {
-        image::ImageFormat::Jpeg => "jpeg",
-        image::ImageFormat::Png => "png",
-        image::ImageFormat::Gif => "gif",
-        image::ImageFormat::WebP => "webp",
-        _ => {
-            tracing::info!("Invalid image format: {:?}", format);
-            return Err((StatusCode::BAD_REQUEST, "Invalid image format".to_string()));
-        }
+        return Ok(ImageInfo {
+            hash,
+            size,
+            extension: format.extension().to_string(),
+            width: video.width,
+            height: video.height,
+            exif: None,
+            content_identifier: video.content_identifier.clone(),
+            video: Some(video),
+        });
+    }
+
+    let dimensions = match format.image_format() {
+        Some(image_format) => ImageReader::with_format(&mut file, image_format)
+            .into_dimensions()
+            .map_err(|e| tracing::warn!(error = ?e, "Failed to read image dimensions"))
+            .ok(),
+        None if format.is_raw() => raw::dimensions(&mut file),
+        None => heif::dimensions(&mut file),
     };
-    let (width, height) = reader.into_dimensions().map_err(|e| {
-        tracing::warn!(error = ?e, "Failed to read image dimensions");
-        (StatusCode::BAD_REQUEST, "Invalid image format".to_string())
+    let (width, height) = dimensions.ok_or_else(|| {
+        tracing::warn!(?format, "Failed to read image dimensions");
+        invalid_format()
     })?;
-    let exif = get_image_exif(image_bytes.as_ref());
+
+    // kamadak-exif 可以直接读取 JPEG、PNG、WebP、HEIF/AVIF 容器和 TIFF 结构的 RAW 中的 EXIF
+    file.seek(SeekFrom::Start(0)).map_err(read_error)?;
+    let exif = get_image_exif(&mut file);
+
+    // 记录显示方向的尺寸，Orientation 5 ~ 8 需要旋转 90°。
+    // HEIF 的旋转由 irot 属性决定，EXIF 中的 Orientation 只是参考
+    let (width, height) = match &exif {
+        Some(exif) if !format.is_heif() && orientation(exif) >= 5 => (height, width),
+        _ => (width, height),
+    };
     Ok(ImageInfo {
         hash,
         size,
-        extension: extension.to_string(),
+        extension: format.extension().to_string(),
         width,
         height,
+        content_identifier: exif.as_ref().and_then(apple_content_identifier),
         exif,
+        video: None,
     })
 }
 
+/// 在事务内对某个 hash 加锁，上传与 GC 通过它互斥，避免 GC 删除正在被上传的对象
+pub async fn lock_image_hash(conn: &mut sqlx::PgConnection, hash: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+        .bind(hash)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
 pub async fn save_image(
-    image_bytes: Bytes,
-    storage: &LocalStorage,
+    file: &StagedFile,
+    storage: &dyn Storage,
     db: &PgPool,
 ) -> Result<ImageInfo, (StatusCode, String)> {
-    let info = get_image_info(&image_bytes)?;
-    let exists = storage.exists(&info.hash).map_err(|e| {
-        tracing::error!(
-            error = ?e,
-            object = info.hash,
-            "Fail to check existence of object"
-        );
+    let path = file.path.to_path_buf();
+    let (hash, size) = (file.hash.clone(), file.size);
+    let info = tokio::task::spawn_blocking(move || get_image_info(&path, hash, size))
+        .await
+        .map_err(|e| {
+            tracing::error!(error = ?e, "Failed to read image info");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal server error".to_string(),
+            )
+        })??;
+
+    let db_error = |e: sqlx::Error| {
+        tracing::error!(error = ?e, object = info.hash, "Failed to save image record");
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             "Internal server error".to_string(),
         )
-    })?;
+    };
 
-    if !exists {
-        // save to object storage
-        storage.save(&info.hash, image_bytes).map_err(|e| {
+    let mut tx = db.begin().await.map_err(db_error)?;
+    lock_image_hash(&mut tx, &info.hash)
+        .await
+        .map_err(db_error)?;
+
+    // 大小不一致说明是旧版本写入时被截断的文件，需要重新写入
+    let exists = match storage.size(&info.hash).await {
+        Ok(size) => size == info.size,
+        Err(StorageError::NotFound(_)) => false,
+        Err(e) => {
             tracing::error!(
                 error = ?e,
                 object = info.hash,
-                "Failed to save object"
+                "Fail to check existence of object"
             );
-            (
+            return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Internal server error".to_string(),
-            )
-        })?;
+            ));
+        }
+    };
 
-        // save to database
-        sqlx::query!(
-            r#"INSERT INTO "image" ("hash", "size", "extension", "width", "height") VALUES ($1, $2, $3, $4, $5)"#,
-            info.hash,
-            info.size as i64,
-            info.extension,
-            info.width as i64,
-            info.height as i64
-        )
-        .execute(db)
-        .await
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Interval server error".to_string(),
-            )
-        })?;
+    if !exists {
+        // save to object storage
+        storage
+            .save_file(&info.hash, &file.path)
+            .await
+            .map_err(|e| {
+                tracing::error!(
+                    error = ?e,
+                    object = info.hash,
+                    "Failed to save object"
+                );
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal server error".to_string(),
+                )
+            })?;
     }
 
+    // save to database
+    // 已存在时刷新 updated_at，GC 在宽限期内不会回收刚被上传过的对象
+    sqlx::query!(
+        r#"
+        INSERT INTO "image" ("hash", "size", "extension", "width", "height", "duration_ms") VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT ("hash") DO UPDATE SET "updated_at" = NOW()
+        "#,
+        info.hash,
+        info.size as i64,
+        info.extension,
+        info.width as i64,
+        info.height as i64,
+        info.video.as_ref().map(|v| v.duration_ms)
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+
     Ok(info)
 }
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+
+    use super::*;
+
+    fn chunks(data: &[u8], size: usize) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+        stream::iter(
+            data.chunks(size)
+                .map(|c| Ok(Bytes::copy_from_slice(c)))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn is_empty(dir: &Path) -> bool {
+        std::fs::read_dir(dir).unwrap().next().is_none()
+    }
+
+    #[tokio::test]
+    async fn stage() {
+        let dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+
+        let file = StagedFile::from_stream_in(dir.path(), chunks(&data, 3000), 10_000)
+            .await
+            .unwrap();
+        assert_eq!(file.size, 10_000);
+        assert_eq!(file.hash, format!("{:x}", Sha256::digest(&data)));
+        assert_eq!(std::fs::read(&file.path).unwrap(), data);
+
+        drop(file);
+        assert!(is_empty(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn too_large() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = vec![7u8; 101];
+
+        // 恰好等于上限时可以暂存
+        let file = StagedFile::from_stream_in(dir.path(), chunks(&data[..100], 7), 100)
+            .await
+            .unwrap();
+        assert_eq!(file.size, 100);
+        drop(file);
+
+        // 多一个字节就拒绝，并删除临时文件
+        for chunk_size in [1, 7, 100, 101] {
+            let result =
+                StagedFile::from_stream_in(dir.path(), chunks(&data, chunk_size), 100).await;
+            assert!(
+                matches!(result, Err(StageError::TooLarge(100))),
+                "{chunk_size}"
+            );
+            assert!(is_empty(dir.path()), "{chunk_size}");
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let items: Vec<Result<Bytes, std::io::Error>> = vec![
+            Ok(Bytes::from_static(b"partial")),
+            Err(std::io::Error::other("connection reset")),
+        ];
+
+        let result = StagedFile::from_stream_in(dir.path(), stream::iter(items), 100).await;
+        assert!(matches!(result, Err(StageError::Io(_))));
+        assert!(is_empty(dir.path()));
+    }
+}
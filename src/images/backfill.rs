@@ -0,0 +1,97 @@
+use sqlx::PgPool;
+use std::io::Cursor;
+use thiserror::Error;
+
+use crate::{
+    exif::{get_image_exif, orientation},
+    images::format::MediaFormat,
+    infra::storage::{SharedStorage, Storage, StorageError},
+};
+
+/// 每批检查的 image 数量
+const BATCH_SIZE: i64 = 100;
+
+#[derive(Debug, Error)]
+pub enum BackfillError {
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+/// 原图的 EXIF Orientation 是否需要旋转 90°，HEIF 的尺寸已经由 irot 决定，视频没有 EXIF
+async fn is_transposed(
+    storage: &dyn Storage,
+    hash: &str,
+    extension: &str,
+) -> Result<bool, BackfillError> {
+    if MediaFormat::from_extension(extension).is_none_or(|f| f.is_heif() || f.is_video()) {
+        return Ok(false);
+    }
+    let data = match storage.get(hash).await {
+        Ok(data) => data,
+        Err(StorageError::NotFound(_)) => {
+            tracing::warn!(object = hash, "Image content is missing");
+            return Ok(false);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let orientation = tokio::task::spawn_blocking(move || {
+        get_image_exif(Cursor::new(data)).map(|e| orientation(&e))
+    })
+    .await
+    .ok()
+    .flatten();
+    Ok(orientation.is_some_and(|o| o >= 5))
+}
+
+/// 旧版本记录的图片尺寸没有应用 EXIF Orientation，读取原图修正尚未检查过的 image，
+/// 返回交换了宽高的数量
+pub async fn fix_image_orientation(
+    db: &PgPool,
+    storage: &dyn Storage,
+) -> Result<u64, BackfillError> {
+    let mut fixed = 0;
+    loop {
+        let images = sqlx::query!(
+            r#"SELECT "hash", "extension" FROM "image" WHERE NOT "orientation_checked" ORDER BY "hash" LIMIT $1"#,
+            BATCH_SIZE
+        )
+        .fetch_all(db)
+        .await?;
+        if images.is_empty() {
+            return Ok(fixed);
+        }
+
+        for image in images {
+            let transposed = is_transposed(storage, &image.hash, &image.extension).await?;
+            sqlx::query!(
+                r#"
+                UPDATE "image" SET
+                    "orientation_checked" = TRUE,
+                    "width" = CASE WHEN $2 THEN "height" ELSE "width" END,
+                    "height" = CASE WHEN $2 THEN "width" ELSE "height" END
+                WHERE "hash" = $1
+                "#,
+                image.hash,
+                transposed
+            )
+            .execute(db)
+            .await?;
+            if transposed {
+                fixed += 1;
+            }
+        }
+    }
+}
+
+/// 启动时在后台修正旧图片的尺寸，完成后退出
+pub fn spawn_backfill_task(db: PgPool, storage: SharedStorage) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        match fix_image_orientation(&db, storage.as_ref()).await {
+            Ok(0) => {}
+            Ok(fixed) => tracing::info!(fixed, "Fixed dimensions of rotated images"),
+            Err(e) => tracing::error!(error = ?e, "Failed to fix dimensions of rotated images"),
+        }
+    })
+}
@@ -0,0 +1,163 @@
+use std::io::{Read, Seek, SeekFrom};
+
+/// ISO BMFF（HEIF / MP4 / QuickTime）的 box 头部
+pub struct BoxHeader {
+    pub box_type: [u8; 4],
+    /// 不含头部的内容长度，`None` 表示一直延伸到文件末尾
+    pub content_size: Option<u64>,
+}
+
+pub fn read_box_header<R: Read>(reader: &mut R) -> Option<BoxHeader> {
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header).ok()?;
+    let size = u32::from_be_bytes(header[0..4].try_into().ok()?) as u64;
+    let box_type = header[4..8].try_into().ok()?;
+    let content_size = match size {
+        0 => None,
+        1 => {
+            let mut large = [0u8; 8];
+            reader.read_exact(&mut large).ok()?;
+            Some(u64::from_be_bytes(large).checked_sub(16)?)
+        }
+        _ => Some(size.checked_sub(8)?),
+    };
+    Some(BoxHeader {
+        box_type,
+        content_size,
+    })
+}
+
+/// 在内存中的 box 列表里依次迭代 `(type, content)`
+pub fn boxes(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 8 {
+            return None;
+        }
+        let size = u32::from_be_bytes(data[0..4].try_into().ok()?) as usize;
+        let (header_len, size) = match size {
+            0 => (8, data.len()),
+            1 => (
+                16,
+                u64::from_be_bytes(data.get(8..16)?.try_into().ok()?) as usize,
+            ),
+            _ => (8, size),
+        };
+        if size < header_len || size > data.len() {
+            return None;
+        }
+        let item = (&data[4..8], &data[header_len..size]);
+        data = &data[size..];
+        Some(item)
+    })
+}
+
+pub fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// 跳过其他顶层 box，读取第一个指定类型的顶层 box 的内容，超过 `max_size` 时返回 `None`
+pub fn read_top_level<R: Read + Seek>(
+    reader: &mut R,
+    box_type: &[u8; 4],
+    max_size: u64,
+) -> Option<Vec<u8>> {
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    loop {
+        let header = read_box_header(reader)?;
+        if &header.box_type == box_type {
+            let size = header.content_size?;
+            if size > max_size {
+                return None;
+            }
+            let mut content = vec![0u8; size as usize];
+            reader.read_exact(&mut content).ok()?;
+            return Some(content);
+        }
+        reader
+            .seek(SeekFrom::Current(i64::try_from(header.content_size?).ok()?))
+            .ok()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn make_box(box_type: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = (8 + content.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(content);
+        data
+    }
+
+    #[test]
+    fn box_headers() {
+        let header = read_box_header(&mut Cursor::new(make_box(b"free", b"abc"))).unwrap();
+        assert_eq!(&header.box_type, b"free");
+        assert_eq!(header.content_size, Some(3));
+
+        // 64 位长度
+        let mut large = 1u32.to_be_bytes().to_vec();
+        large.extend_from_slice(b"mdat");
+        large.extend_from_slice(&20u64.to_be_bytes());
+        let header = read_box_header(&mut Cursor::new(large)).unwrap();
+        assert_eq!(&header.box_type, b"mdat");
+        assert_eq!(header.content_size, Some(4));
+
+        // 延伸到文件末尾
+        let header = read_box_header(&mut Cursor::new(b"\0\0\0\0mdat")).unwrap();
+        assert_eq!(header.content_size, None);
+
+        assert!(read_box_header(&mut Cursor::new(b"\0\0\0\x04free")).is_none());
+        assert!(read_box_header(&mut Cursor::new(b"\0\0\0")).is_none());
+    }
+
+    #[test]
+    fn box_iteration() {
+        let mut data = make_box(b"pitm", &[0, 0, 0, 0, 0, 1]);
+        data.extend(make_box(b"iinf", b""));
+        // 超出剩余长度的 box 结束迭代
+        data.extend_from_slice(b"\0\0\0\x20iloc");
+        let items: Vec<_> = boxes(&data).collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0], (&b"pitm"[..], &[0, 0, 0, 0, 0, 1][..]));
+        assert_eq!(items[1], (&b"iinf"[..], &b""[..]));
+
+        let to_end = b"\0\0\0\0mdatpayload";
+        assert_eq!(
+            boxes(to_end).collect::<Vec<_>>(),
+            [(&b"mdat"[..], &b"payload"[..])]
+        );
+    }
+
+    #[test]
+    fn top_level_boxes() {
+        let mut data = make_box(b"ftyp", b"heic\0\0\0\0");
+        data.extend(make_box(b"free", &[0; 100]));
+        data.extend(make_box(b"meta", b"content"));
+        let mut reader = Cursor::new(data);
+        assert_eq!(
+            read_top_level(&mut reader, b"meta", 1024).as_deref(),
+            Some(&b"content"[..])
+        );
+        assert_eq!(read_top_level(&mut reader, b"meta", 6), None);
+        assert_eq!(read_top_level(&mut reader, b"moov", 1024), None);
+    }
+
+    #[test]
+    fn integers() {
+        let data = [0x12, 0x34, 0x56, 0x78];
+        assert_eq!(read_u16(&data, 2), Some(0x5678));
+        assert_eq!(read_u32(&data, 0), Some(0x12345678));
+        assert_eq!(read_u32(&data, 1), None);
+    }
+}
@@ -0,0 +1,255 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use serde::Serialize;
+
+use crate::images::raw;
+
+/// 原图的格式，通过文件头部字节识别，不信任客户端提供的 content type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaFormat {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+    Heic,
+    Heif,
+    Avif,
+    Dng,
+    Cr2,
+    Nef,
+    Arw,
+    Mp4,
+    Mov,
+}
+
+/// 识别格式需要读取的文件头部长度
+const SNIFF_LEN: usize = 64;
+
+impl MediaFormat {
+    /// 保存在 `image.extension` 中的名称
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpeg",
+            Self::Png => "png",
+            Self::Gif => "gif",
+            Self::Webp => "webp",
+            Self::Heic => "heic",
+            Self::Heif => "heif",
+            Self::Avif => "avif",
+            Self::Dng => "dng",
+            Self::Cr2 => "cr2",
+            Self::Nef => "nef",
+            Self::Arw => "arw",
+            Self::Mp4 => "mp4",
+            Self::Mov => "mov",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "jpeg" | "jpg" => Some(Self::Jpeg),
+            "png" => Some(Self::Png),
+            "gif" => Some(Self::Gif),
+            "webp" => Some(Self::Webp),
+            "heic" => Some(Self::Heic),
+            "heif" => Some(Self::Heif),
+            "avif" => Some(Self::Avif),
+            "dng" => Some(Self::Dng),
+            "cr2" => Some(Self::Cr2),
+            "nef" => Some(Self::Nef),
+            "arw" => Some(Self::Arw),
+            "mp4" => Some(Self::Mp4),
+            "mov" => Some(Self::Mov),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Gif => "image/gif",
+            Self::Webp => "image/webp",
+            Self::Heic => "image/heic",
+            Self::Heif => "image/heif",
+            Self::Avif => "image/avif",
+            Self::Dng => "image/x-adobe-dng",
+            Self::Cr2 => "image/x-canon-cr2",
+            Self::Nef => "image/x-nikon-nef",
+            Self::Arw => "image/x-sony-arw",
+            Self::Mp4 => "video/mp4",
+            Self::Mov => "video/quicktime",
+        }
+    }
+
+    /// 所有浏览器都能直接显示的格式，其他格式需要按 `Accept` 协商，否则返回缩略图
+    pub fn is_web_safe(&self) -> bool {
+        matches!(self, Self::Jpeg | Self::Png | Self::Gif | Self::Webp)
+    }
+
+    /// 基于 ISO BMFF 容器（HEIF / AVIF）的格式
+    pub fn is_heif(&self) -> bool {
+        matches!(self, Self::Heic | Self::Heif | Self::Avif)
+    }
+
+    /// 基于 TIFF 的相机 RAW 格式，缩略图使用其中嵌入的 JPEG 预览图
+    pub fn is_raw(&self) -> bool {
+        matches!(self, Self::Dng | Self::Cr2 | Self::Nef | Self::Arw)
+    }
+
+    /// 视频直接返回原文件，不生成缩略图
+    pub fn is_video(&self) -> bool {
+        matches!(self, Self::Mp4 | Self::Mov)
+    }
+
+    /// 由 `image` crate 解码的格式
+    pub fn image_format(&self) -> Option<image::ImageFormat> {
+        match self {
+            Self::Jpeg => Some(image::ImageFormat::Jpeg),
+            Self::Png => Some(image::ImageFormat::Png),
+            Self::Gif => Some(image::ImageFormat::Gif),
+            Self::Webp => Some(image::ImageFormat::WebP),
+            _ => None,
+        }
+    }
+
+    /// 识别文件格式，RAW 格式需要进一步读取 TIFF 的 IFD0
+    pub fn detect<R: Read + Seek>(reader: &mut R) -> Option<Self> {
+        let mut header = Vec::with_capacity(SNIFF_LEN);
+        reader.seek(SeekFrom::Start(0)).ok()?;
+        reader
+            .take(SNIFF_LEN as u64)
+            .read_to_end(&mut header)
+            .ok()?;
+        match Self::sniff(&header) {
+            Some(format) => Some(format),
+            None if raw::is_tiff(&header) => raw::identify(reader),
+            None => None,
+        }
+    }
+
+    /// 根据文件头部的 magic number 识别格式
+    fn sniff(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            return Some(Self::Jpeg);
+        }
+        if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+            return Some(Self::Png);
+        }
+        if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+            return Some(Self::Gif);
+        }
+        if header.len() >= 12 && &header[0..4] == b"RIFF" && &header[8..12] == b"WEBP" {
+            return Some(Self::Webp);
+        }
+        if header.len() >= 12 && &header[4..8] == b"ftyp" {
+            return sniff_ftyp(header);
+        }
+        None
+    }
+}
+
+/// 根据 `ftyp` box 的主品牌和兼容品牌区分 HEIC、AVIF、通用 HEIF 和视频
+fn sniff_ftyp(header: &[u8]) -> Option<MediaFormat> {
+    let size = u32::from_be_bytes(header[0..4].try_into().ok()?) as usize;
+    let end = size.min(header.len());
+    let major = &header[8..12];
+    // 主品牌之后是 4 字节的 minor version，然后是兼容品牌列表
+    let compatible = header.get(16..end).unwrap_or_default().chunks_exact(4);
+    let brands: Vec<&[u8]> = std::iter::once(major).chain(compatible).collect();
+
+    let has = |candidates: &[&[u8]]| brands.iter().any(|b| candidates.contains(b));
+    if has(&[b"avif", b"avis"]) {
+        Some(MediaFormat::Avif)
+    } else if has(&[b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx"]) {
+        Some(MediaFormat::Heic)
+    } else if has(&[b"mif1", b"msf1"]) {
+        Some(MediaFormat::Heif)
+    } else if major == b"qt  " {
+        Some(MediaFormat::Mov)
+    } else if has(&[
+        b"isom", b"iso2", b"iso4", b"iso5", b"iso6", b"mp41", b"mp42", b"avc1", b"M4V ", b"dash",
+    ]) {
+        Some(MediaFormat::Mp4)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let size = 16 + 4 * compatible.len() as u32;
+        let mut data = size.to_be_bytes().to_vec();
+        data.extend_from_slice(b"ftyp");
+        data.extend_from_slice(major);
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        for brand in compatible {
+            data.extend_from_slice(*brand);
+        }
+        // 紧随其后的 box 不应被当作兼容品牌
+        data.extend_from_slice(b"\0\0\0\x10metaheic\0\0\0\0");
+        data
+    }
+
+    fn detect(data: &[u8]) -> Option<MediaFormat> {
+        MediaFormat::detect(&mut Cursor::new(data))
+    }
+
+    #[test]
+    fn magic_numbers() {
+        use MediaFormat::*;
+        let cases: [(&[u8], Option<MediaFormat>); 9] = [
+            (b"\xFF\xD8\xFF\xE0\0\x10JFIF", Some(Jpeg)),
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", Some(Png)),
+            (b"GIF87a", Some(Gif)),
+            (b"GIF89a\x01\0", Some(Gif)),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", Some(Webp)),
+            (b"RIFF\x24\0\0\0WAVEfmt ", None),
+            (b"II*\0\x08\0\0\0CR\x02\0", Some(Cr2)),
+            (b"\xFF\xD8", None),
+            (b"", None),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(detect(data), expected, "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn ftyp_brands() {
+        use MediaFormat::*;
+        let cases = [
+            (ftyp(b"heic", &[b"mif1", b"heic"]), Some(Heic)),
+            (ftyp(b"mif1", &[b"mif1", b"heic"]), Some(Heic)),
+            (ftyp(b"heix", &[]), Some(Heic)),
+            (ftyp(b"avif", &[b"mif1", b"miaf"]), Some(Avif)),
+            (ftyp(b"mif1", &[b"avif", b"mif1"]), Some(Avif)),
+            (ftyp(b"avis", &[b"msf1"]), Some(Avif)),
+            (ftyp(b"mif1", &[b"mif1", b"miaf"]), Some(Heif)),
+            (ftyp(b"qt  ", &[b"qt  "]), Some(Mov)),
+            (ftyp(b"isom", &[b"isom", b"avc1"]), Some(Mp4)),
+            (ftyp(b"mp42", &[]), Some(Mp4)),
+            (ftyp(b"crx ", &[b"crx "]), None),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(detect(&data), expected, "{:?}", &data[..16]);
+        }
+    }
+
+    #[test]
+    fn extensions() {
+        for extension in [
+            "jpeg", "png", "gif", "webp", "heic", "heif", "avif", "dng", "cr2", "nef", "arw",
+            "mp4", "mov",
+        ] {
+            let format = MediaFormat::from_extension(extension).unwrap();
+            assert_eq!(format.extension(), extension);
+        }
+        assert_eq!(MediaFormat::from_extension("jpg"), Some(MediaFormat::Jpeg));
+        assert_eq!(MediaFormat::from_extension("tiff"), None);
+    }
+}
@@ -0,0 +1,279 @@
+use serde::Serialize;
+use sqlx::PgPool;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::{
+    config::GcConfig,
+    images::lock_image_hash,
+    infra::storage::{SharedStorage, Storage, StorageError},
+};
+
+#[derive(Debug, Error)]
+pub enum GcError {
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrphanedImage {
+    pub hash: String,
+    pub size: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GcReport {
+    pub dry_run: bool,
+    /// 没有任何 photo 引用的 image
+    pub orphaned: Vec<OrphanedImage>,
+    pub deleted_count: u64,
+    pub reclaimed_bytes: i64,
+    /// 记录已删除、等待删除文件的缩略图
+    pub stale_renditions: Vec<String>,
+}
+
+/// 回收不再被任何 photo 引用（包括 Live Photo 的视频）的 image 记录及其文件，
+/// 并删除 `stale_rendition` 中登记的缩略图文件。
+///
+/// 最近 `grace_period_minutes` 内被上传过的 image 不会被回收，
+/// 每个 hash 的删除都在 advisory lock 下进行，与 `save_image` 互斥。
+pub async fn collect_garbage(
+    db: &PgPool,
+    storage: &dyn Storage,
+    grace_period_minutes: u32,
+    dry_run: bool,
+) -> Result<GcReport, GcError> {
+    let orphaned = sqlx::query_as!(
+        OrphanedImage,
+        r#"
+        SELECT "image"."hash", "image"."size"
+        FROM "image"
+        WHERE "image"."updated_at" < NOW() - $1::integer * INTERVAL '1 minute'
+        AND NOT EXISTS (
+            SELECT 1 FROM "photo"
+            WHERE "photo"."image_hash" = "image"."hash" OR "photo"."live_video_hash" = "image"."hash"
+        )
+        ORDER BY "image"."hash"
+        "#,
+        grace_period_minutes as i32
+    )
+    .fetch_all(db)
+    .await?;
+
+    let stale_renditions = sqlx::query_scalar!(
+        r#"SELECT "storage_key" FROM "stale_rendition" ORDER BY "storage_key""#
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut report = GcReport {
+        dry_run,
+        orphaned,
+        deleted_count: 0,
+        reclaimed_bytes: 0,
+        stale_renditions,
+    };
+
+    if dry_run {
+        return Ok(report);
+    }
+
+    for image in &report.orphaned {
+        let mut tx = db.begin().await?;
+        lock_image_hash(&mut tx, &image.hash).await?;
+
+        // rendition 记录随 image 级联删除，需要在删除前记下文件
+        let renditions = sqlx::query!(
+            r#"SELECT "storage_key" FROM "rendition" WHERE "image_hash" = $1"#,
+            image.hash
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        // 加锁后重新确认没有被引用，期间可能有新的上传
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM "image"
+            WHERE "hash" = $1
+            AND "updated_at" < NOW() - $2::integer * INTERVAL '1 minute'
+            AND NOT EXISTS (
+                SELECT 1 FROM "photo" WHERE "photo"."image_hash" = $1 OR "photo"."live_video_hash" = $1
+            )
+            RETURNING "hash"
+            "#,
+            image.hash,
+            grace_period_minutes as i32
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if deleted.is_none() {
+            continue;
+        }
+        tx.commit().await?;
+
+        report.deleted_count += 1;
+        report.reclaimed_bytes += image.size;
+
+        // 记录删除提交后再删文件，保证记录存在则文件存在；文件删除失败只会留下孤立的文件，
+        // 由之后的 scrub 处理。删除时重新加锁，期间重新上传了同一个 hash 时保留文件
+        let mut tx = db.begin().await?;
+        lock_image_hash(&mut tx, &image.hash).await?;
+        let reuploaded = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM "image" WHERE "hash" = $1) as "exists!""#,
+            image.hash
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if !reuploaded {
+            let keys = renditions.iter().map(|r| r.storage_key.as_str());
+            for key in keys.chain([image.hash.as_str()]) {
+                if let Err(e) = storage.delete(key).await {
+                    tracing::warn!(error = ?e, object = key, "Failed to delete collected file");
+                }
+            }
+        }
+        tx.commit().await?;
+    }
+
+    // 缩略图的 key 带有版本号，新生成的缩略图不会复用这些 key
+    for key in &report.stale_renditions {
+        storage.delete(key).await?;
+        sqlx::query!(
+            r#"DELETE FROM "stale_rendition" WHERE "storage_key" = $1"#,
+            key
+        )
+        .execute(db)
+        .await?;
+    }
+
+    Ok(report)
+}
+
+/// 启动后台任务，定期回收孤立的 image
+pub fn spawn_gc_task(
+    db: PgPool,
+    storage: SharedStorage,
+    config: GcConfig,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(config.interval_hours as u64 * 60 * 60));
+        loop {
+            interval.tick().await;
+            match collect_garbage(&db, storage.as_ref(), config.grace_period_minutes, false).await {
+                Ok(report) if report.deleted_count == 0 && report.stale_renditions.is_empty() => {}
+                Ok(report) => tracing::info!(
+                    deleted_count = report.deleted_count,
+                    reclaimed_bytes = report.reclaimed_bytes,
+                    stale_renditions = report.stale_renditions.len(),
+                    "Collected orphaned images"
+                ),
+                Err(e) => tracing::error!(error = ?e, "Failed to collect orphaned images"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::infra::storage::memory::MemoryStorage;
+
+    const HASH: &str = "a44d40843a066defac3a94cffca5f67e021e8e2293878565ea2f526eef52db7e";
+
+    async fn insert_image(db: &PgPool, storage: &dyn Storage) -> String {
+        let rendition_key = format!("{}_256_v2.jpeg", HASH);
+        sqlx::query(
+            r#"INSERT INTO "image" ("hash", "size", "extension", "width", "height") VALUES ($1, 4, 'jpeg', 2, 2)"#,
+        )
+        .bind(HASH)
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO "rendition" ("image_hash", "size", "format", "storage_key", "width", "height", "byte_size")
+            VALUES ($1, 256, 'jpeg', $2, 2, 2, 4)
+            "#,
+        )
+        .bind(HASH)
+        .bind(&rendition_key)
+        .execute(db)
+        .await
+        .unwrap();
+        storage
+            .save(HASH, Bytes::from_static(b"data"))
+            .await
+            .unwrap();
+        storage
+            .save(&rendition_key, Bytes::from_static(b"jpeg"))
+            .await
+            .unwrap();
+        rendition_key
+    }
+
+    async fn image_exists(db: &PgPool) -> bool {
+        sqlx::query_scalar(r#"SELECT EXISTS (SELECT 1 FROM "image" WHERE "hash" = $1)"#)
+            .bind(HASH)
+            .fetch_one(db)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn collect(db: PgPool) {
+        let storage = MemoryStorage::new();
+        let rendition_key = insert_image(&db, &storage).await;
+
+        let report = collect_garbage(&db, &storage, 0, true).await.unwrap();
+        assert_eq!(report.orphaned.len(), 1);
+        assert_eq!(report.deleted_count, 0);
+        assert!(image_exists(&db).await);
+        assert!(storage.exists(HASH).await.unwrap());
+
+        let report = collect_garbage(&db, &storage, 0, false).await.unwrap();
+        assert_eq!(report.deleted_count, 1);
+        assert_eq!(report.reclaimed_bytes, 4);
+        assert!(!image_exists(&db).await);
+        assert!(!storage.exists(HASH).await.unwrap());
+        assert!(!storage.exists(&rendition_key).await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn grace_period(db: PgPool) {
+        let storage = MemoryStorage::new();
+        insert_image(&db, &storage).await;
+
+        let report = collect_garbage(&db, &storage, 60, false).await.unwrap();
+        assert!(report.orphaned.is_empty());
+        assert!(image_exists(&db).await);
+        assert!(storage.exists(HASH).await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn stale_renditions(db: PgPool) {
+        let storage = MemoryStorage::new();
+        storage
+            .save("old_256.jpeg", Bytes::from_static(b"jpeg"))
+            .await
+            .unwrap();
+        sqlx::query(r#"INSERT INTO "stale_rendition" ("storage_key") VALUES ('old_256.jpeg')"#)
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let report = collect_garbage(&db, &storage, 0, false).await.unwrap();
+        assert_eq!(report.stale_renditions, vec!["old_256.jpeg".to_string()]);
+        assert!(!storage.exists("old_256.jpeg").await.unwrap());
+        let remaining: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM "stale_rendition""#)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+}
@@ -0,0 +1,238 @@
+use std::io::{Read, Seek};
+
+use crate::images::bmff::{boxes, read_top_level, read_u16, read_u32};
+
+/// `meta` box 的大小上限，正常文件只有几 KB
+const MAX_META_SIZE: u64 = 16 * 1024 * 1024;
+
+/// 读取顶层的 `meta` box 内容（不含 full box 的 version/flags）
+fn read_meta<R: Read + Seek>(reader: &mut R) -> Option<Vec<u8>> {
+    let mut content = read_top_level(reader, b"meta", MAX_META_SIZE)?;
+    if content.len() < 4 {
+        return None;
+    }
+    Some(content.split_off(4))
+}
+
+/// 主图像的 item id，来自 `pitm` box
+fn primary_item_id(meta: &[u8]) -> Option<u32> {
+    let (_, pitm) = boxes(meta).find(|(t, _)| *t == b"pitm")?;
+    match pitm.first()? {
+        0 => read_u16(pitm, 4).map(u32::from),
+        _ => read_u32(pitm, 4),
+    }
+}
+
+/// `ipma` 中某个 item 关联的属性序号（从 1 开始）
+fn item_properties(ipma: &[u8], item_id: u32) -> Option<Vec<u16>> {
+    let version = *ipma.first()?;
+    let large_index = ipma.get(3)? & 1 == 1;
+    let entry_count = read_u32(ipma, 4)?;
+    let mut offset = 8;
+    for _ in 0..entry_count {
+        let id = if version < 1 {
+            let id = read_u16(ipma, offset)? as u32;
+            offset += 2;
+            id
+        } else {
+            let id = read_u32(ipma, offset)?;
+            offset += 4;
+            id
+        };
+        let count = *ipma.get(offset)? as usize;
+        offset += 1;
+        let mut properties = Vec::with_capacity(count);
+        for _ in 0..count {
+            // 最高位是 essential 标记
+            let index = if large_index {
+                let v = read_u16(ipma, offset)? & 0x7FFF;
+                offset += 2;
+                v
+            } else {
+                let v = (*ipma.get(offset)? & 0x7F) as u16;
+                offset += 1;
+                v
+            };
+            properties.push(index);
+        }
+        if id == item_id {
+            return Some(properties);
+        }
+    }
+    None
+}
+
+/// 从 `ispe` 属性读取主图像的显示尺寸，`irot` 旋转 90° 或 270° 时交换宽高，
+/// 只读取文件头部的 `meta` box
+pub fn dimensions<R: Read + Seek>(reader: &mut R) -> Option<(u32, u32)> {
+    let meta = read_meta(reader)?;
+    let (_, iprp) = boxes(&meta).find(|(t, _)| *t == b"iprp")?;
+    let (_, ipco) = boxes(iprp).find(|(t, _)| *t == b"ipco")?;
+    let properties: Vec<(&[u8], &[u8])> = boxes(ipco).collect();
+
+    let ispe = |content: &[u8]| Some((read_u32(content, 4)?, read_u32(content, 8)?));
+
+    // 优先使用与主图像关联的 ispe，网格图像的每个分块也有自己的 ispe
+    let primary: Vec<(&[u8], &[u8])> = primary_item_id(&meta)
+        .and_then(|id| {
+            let (_, ipma) = boxes(iprp).find(|(t, _)| *t == b"ipma")?;
+            item_properties(ipma, id)
+        })
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|index| properties.get((index as usize).checked_sub(1)?).copied())
+        .collect();
+
+    let (width, height) = primary
+        .iter()
+        .find(|(t, _)| *t == b"ispe")
+        .and_then(|(_, content)| ispe(content))
+        .or_else(|| {
+            properties
+                .iter()
+                .filter(|(t, _)| *t == b"ispe")
+                .filter_map(|(_, content)| ispe(content))
+                .max_by_key(|(w, h)| *w as u64 * *h as u64)
+        })?;
+
+    // irot：低 2 位是逆时针旋转的 90° 倍数
+    let rotated = primary
+        .iter()
+        .find(|(t, _)| *t == b"irot")
+        .and_then(|(_, content)| content.first())
+        .is_some_and(|angle| angle & 1 == 1);
+    if rotated {
+        Some((height, width))
+    } else {
+        Some((width, height))
+    }
+}
+
+/// 使用 libheif 解码主图像，会应用文件中的旋转、镜像和裁剪
+#[cfg(feature = "heif")]
+pub fn decode(data: &[u8]) -> Result<image::DynamicImage, String> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let lib_heif = LibHeif::new();
+    let context = HeifContext::read_from_bytes(data).map_err(|e| e.to_string())?;
+    let handle = context.primary_image_handle().map_err(|e| e.to_string())?;
+    let decoded = lib_heif
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)
+        .map_err(|e| e.to_string())?;
+    let plane = decoded
+        .planes()
+        .interleaved
+        .ok_or_else(|| "decoded image has no interleaved plane".to_string())?;
+
+    // 每行末尾可能有填充字节
+    let row_len = plane.width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_len * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_len]);
+    }
+    image::RgbaImage::from_raw(plane.width, plane.height, pixels)
+        .map(image::DynamicImage::ImageRgba8)
+        .ok_or_else(|| "invalid decoded image buffer".to_string())
+}
+
+#[cfg(not(feature = "heif"))]
+pub fn decode(_data: &[u8]) -> Result<image::DynamicImage, String> {
+    Err("HEIF/AVIF decoding requires the `heif` feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn make_box(box_type: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = (8 + content.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn full_box(box_type: &[u8; 4], version: u8, flags: u8, content: &[u8]) -> Vec<u8> {
+        let mut data = vec![version, 0, 0, flags];
+        data.extend_from_slice(content);
+        make_box(box_type, &data)
+    }
+
+    fn ispe(width: u32, height: u32) -> Vec<u8> {
+        let mut content = width.to_be_bytes().to_vec();
+        content.extend_from_slice(&height.to_be_bytes());
+        full_box(b"ispe", 0, 0, &content)
+    }
+
+    /// 由 `ipco` 中的属性和 `ipma` 的内容组成的 HEIF 文件
+    fn heif(pitm: Option<Vec<u8>>, properties: &[Vec<u8>], ipma: Option<Vec<u8>>) -> Vec<u8> {
+        let mut iprp = make_box(b"ipco", &properties.concat());
+        iprp.extend(ipma.unwrap_or_default());
+
+        let mut meta = vec![0, 0, 0, 0];
+        meta.extend(full_box(b"hdlr", 0, 0, b"\0\0\0\0pict"));
+        meta.extend(pitm.unwrap_or_default());
+        meta.extend(make_box(b"iprp", &iprp));
+
+        let mut data = make_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+        data.extend(make_box(b"meta", &meta));
+        data.extend(make_box(b"mdat", &[0; 16]));
+        data
+    }
+
+    fn dimensions_of(data: Vec<u8>) -> Option<(u32, u32)> {
+        dimensions(&mut Cursor::new(data))
+    }
+
+    /// 网格图像：item 1 是主图像，item 2 是 512x512 的分块
+    fn grid(irot: u8) -> Vec<u8> {
+        let pitm = full_box(b"pitm", 0, 0, &1u16.to_be_bytes());
+        let properties = [ispe(512, 512), ispe(4032, 3024), make_box(b"irot", &[irot])];
+        // version 0：16 位 item id 和 7 位属性序号，最高位是 essential 标记
+        let ipma = full_box(
+            b"ipma",
+            0,
+            0,
+            &[0, 0, 0, 2, 0, 2, 1, 0x81, 0, 1, 2, 0x02, 0x83],
+        );
+        heif(Some(pitm), &properties, Some(ipma))
+    }
+
+    #[test]
+    fn primary_item() {
+        assert_eq!(dimensions_of(grid(0)), Some((4032, 3024)));
+        assert_eq!(dimensions_of(grid(1)), Some((3024, 4032)));
+        assert_eq!(dimensions_of(grid(2)), Some((4032, 3024)));
+        assert_eq!(dimensions_of(grid(3)), Some((3024, 4032)));
+    }
+
+    #[test]
+    fn large_item_ids_and_property_indices() {
+        // version 1 使用 32 位 item id，flags 的最低位表示 15 位属性序号
+        let pitm = full_box(b"pitm", 1, 0, &70000u32.to_be_bytes());
+        let properties = [ispe(640, 480), make_box(b"irot", &[1]), ispe(8000, 6000)];
+        let mut ipma = vec![0, 0, 0, 2];
+        ipma.extend_from_slice(&1u32.to_be_bytes());
+        ipma.extend_from_slice(&[1, 0x80, 0x03]);
+        ipma.extend_from_slice(&70000u32.to_be_bytes());
+        ipma.extend_from_slice(&[2, 0x00, 0x01, 0x80, 0x02]);
+        let ipma = full_box(b"ipma", 1, 1, &ipma);
+        assert_eq!(
+            dimensions_of(heif(Some(pitm), &properties, Some(ipma))),
+            Some((480, 640))
+        );
+    }
+
+    #[test]
+    fn without_associations() {
+        // 无法确定主图像时使用面积最大的 ispe，也不应用旋转
+        let properties = [ispe(512, 512), ispe(1920, 1080), make_box(b"irot", &[1])];
+        assert_eq!(
+            dimensions_of(heif(None, &properties, None)),
+            Some((1920, 1080))
+        );
+
+        assert_eq!(dimensions_of(heif(None, &[], None)), None);
+        assert_eq!(dimensions_of(make_box(b"ftyp", b"heic\0\0\0\0")), None);
+    }
+}
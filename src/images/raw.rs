@@ -0,0 +1,592 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::images::format::MediaFormat;
+
+/// 最多解析的 IFD 数量，避免损坏文件中的环形链表
+const MAX_IFDS: usize = 32;
+/// 单个 IFD 的条目数上限
+const MAX_ENTRIES: u16 = 1024;
+/// 嵌入预览图的大小上限
+const MAX_PREVIEW_SIZE: u64 = 64 * 1024 * 1024;
+
+const TAG_NEW_SUBFILE_TYPE: u16 = 0x00FE;
+const TAG_IMAGE_WIDTH: u16 = 0x0100;
+const TAG_IMAGE_LENGTH: u16 = 0x0101;
+const TAG_COMPRESSION: u16 = 0x0103;
+const TAG_MAKE: u16 = 0x010F;
+const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_SUB_IFDS: u16 = 0x014A;
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+const TAG_DNG_VERSION: u16 = 0xC612;
+
+/// TIFF 的字节序标记和 magic number
+pub fn is_tiff(header: &[u8]) -> bool {
+    header.starts_with(b"II*\0") || header.starts_with(b"MM\0*")
+}
+
+/// CR2 在 TIFF 头之后有 `CR` 标记和主版本号
+fn is_cr2(header: &[u8]) -> bool {
+    header.starts_with(b"II*\0") && header.get(8..11) == Some(b"CR\x02")
+}
+
+struct Entry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    /// 值本身（不超过 4 字节时）或者值所在的偏移量
+    value: [u8; 4],
+}
+
+struct Ifd {
+    entries: Vec<Entry>,
+}
+
+struct TiffReader<'a, R> {
+    reader: &'a mut R,
+    little_endian: bool,
+}
+
+impl<R: Read + Seek> TiffReader<'_, R> {
+    fn u16(&self, bytes: [u8; 2]) -> u16 {
+        if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32(&self, bytes: [u8; 4]) -> u32 {
+        if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    fn read_bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.reader.read_exact(&mut buf).ok()?;
+        Some(buf)
+    }
+
+    fn read_u16(&mut self) -> Option<u16> {
+        let bytes = self.read_bytes()?;
+        Some(self.u16(bytes))
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        let bytes = self.read_bytes()?;
+        Some(self.u32(bytes))
+    }
+
+    /// 读取 IFD，返回条目和下一个 IFD 的偏移量
+    fn read_ifd(&mut self, offset: u32) -> Option<(Ifd, u32)> {
+        self.reader.seek(SeekFrom::Start(offset as u64)).ok()?;
+        let count = self.read_u16()?;
+        if count > MAX_ENTRIES {
+            return None;
+        }
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let tag = self.read_u16()?;
+            let field_type = self.read_u16()?;
+            let count = self.read_u32()?;
+            let value = self.read_bytes()?;
+            entries.push(Entry {
+                tag,
+                field_type,
+                count,
+                value,
+            });
+        }
+        let next = self.read_u32()?;
+        Some((Ifd { entries }, next))
+    }
+
+    /// 读取整数类型（BYTE / SHORT / LONG）的条目值
+    fn values(&mut self, entry: &Entry) -> Option<Vec<u32>> {
+        let size = match entry.field_type {
+            1 => 1,
+            3 => 2,
+            4 | 13 => 4,
+            _ => return None,
+        };
+        let count = entry.count as usize;
+        let bytes = if size * count <= 4 {
+            entry.value[..size * count].to_vec()
+        } else {
+            if count > MAX_ENTRIES as usize {
+                return None;
+            }
+            self.reader
+                .seek(SeekFrom::Start(self.u32(entry.value) as u64))
+                .ok()?;
+            let mut buf = vec![0u8; size * count];
+            self.reader.read_exact(&mut buf).ok()?;
+            buf
+        };
+        Some(
+            bytes
+                .chunks_exact(size)
+                .map(|b| match size {
+                    1 => b[0] as u32,
+                    2 => self.u16([b[0], b[1]]) as u32,
+                    _ => self.u32([b[0], b[1], b[2], b[3]]),
+                })
+                .collect(),
+        )
+    }
+
+    fn ascii(&mut self, entry: &Entry) -> Option<String> {
+        if entry.field_type != 2 || entry.count as usize > MAX_ENTRIES as usize {
+            return None;
+        }
+        let count = entry.count as usize;
+        let bytes = if count <= 4 {
+            entry.value[..count].to_vec()
+        } else {
+            self.reader
+                .seek(SeekFrom::Start(self.u32(entry.value) as u64))
+                .ok()?;
+            let mut buf = vec![0u8; count];
+            self.reader.read_exact(&mut buf).ok()?;
+            buf
+        };
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        Some(String::from_utf8_lossy(&bytes[..end]).trim().to_string())
+    }
+}
+
+impl Ifd {
+    fn get(&self, tag: u16) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.tag == tag)
+    }
+}
+
+/// 读取 TIFF 中的所有图像 IFD：IFD0 链以及其中 `SubIFDs` 指向的子 IFD
+fn read_ifds<R: Read + Seek>(reader: &mut R) -> Option<(TiffReader<'_, R>, Vec<Ifd>)> {
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header).ok()?;
+    if !is_tiff(&header) {
+        return None;
+    }
+    let mut tiff = TiffReader {
+        reader,
+        little_endian: header[0] == b'I',
+    };
+
+    let mut ifds = Vec::new();
+    let mut pending = vec![tiff.u32(header[4..8].try_into().ok()?)];
+    let mut visited = Vec::new();
+    while let Some(offset) = pending.pop() {
+        if offset == 0 || visited.contains(&offset) || visited.len() >= MAX_IFDS {
+            continue;
+        }
+        visited.push(offset);
+        let Some((ifd, next)) = tiff.read_ifd(offset) else {
+            continue;
+        };
+        pending.push(next);
+        if let Some(entry) = ifd.get(TAG_SUB_IFDS)
+            && let Some(offsets) = tiff.values(entry)
+        {
+            pending.extend(offsets);
+        }
+        ifds.push(ifd);
+    }
+    Some((tiff, ifds))
+}
+
+/// 识别基于 TIFF 的 RAW 格式，普通 TIFF 返回 `None`
+pub fn identify<R: Read + Seek>(reader: &mut R) -> Option<MediaFormat> {
+    let mut header = [0u8; 12];
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    reader.read_exact(&mut header).ok()?;
+    if is_cr2(&header) {
+        return Some(MediaFormat::Cr2);
+    }
+
+    let (mut tiff, ifds) = read_ifds(reader)?;
+    let ifd0 = ifds.first()?;
+    if ifd0.get(TAG_DNG_VERSION).is_some() {
+        return Some(MediaFormat::Dng);
+    }
+    let make = tiff.ascii(ifd0.get(TAG_MAKE)?)?.to_uppercase();
+    if make.starts_with("NIKON") {
+        Some(MediaFormat::Nef)
+    } else if make.starts_with("SONY") {
+        Some(MediaFormat::Arw)
+    } else {
+        None
+    }
+}
+
+/// 图像尺寸，优先使用全尺寸图像（`NewSubfileType` 为 0）的 IFD，
+/// 例如 DNG / NEF / ARW 中的 RAW 数据，CR2 的 IFD0 是全尺寸 JPEG
+pub fn dimensions<R: Read + Seek>(reader: &mut R) -> Option<(u32, u32)> {
+    let (mut tiff, ifds) = read_ifds(reader)?;
+    let mut candidates = Vec::new();
+    for ifd in &ifds {
+        let value = |tiff: &mut TiffReader<'_, R>, tag| {
+            ifd.get(tag)
+                .and_then(|e| tiff.values(e))
+                .and_then(|v| v.first().copied())
+        };
+        let (Some(width), Some(height)) = (
+            value(&mut tiff, TAG_IMAGE_WIDTH),
+            value(&mut tiff, TAG_IMAGE_LENGTH),
+        ) else {
+            continue;
+        };
+        let full_size = value(&mut tiff, TAG_NEW_SUBFILE_TYPE).unwrap_or(0) & 1 == 0;
+        candidates.push((full_size, width as u64 * height as u64, (width, height)));
+    }
+    candidates
+        .into_iter()
+        .max_by_key(|(full_size, area, _)| (*full_size, *area))
+        .map(|(_, _, dimensions)| dimensions)
+}
+
+/// 嵌入预览图在文件中的位置 `(offset, length)`，按大小降序
+fn preview_ranges<R: Read + Seek>(reader: &mut R) -> Vec<(u64, u64)> {
+    let Some((mut tiff, ifds)) = read_ifds(reader) else {
+        return Vec::new();
+    };
+    let mut ranges = Vec::new();
+    for ifd in &ifds {
+        let single = |tiff: &mut TiffReader<'_, R>, tag| {
+            ifd.get(tag)
+                .and_then(|e| tiff.values(e))
+                .filter(|v| v.len() == 1)
+                .map(|v| v[0] as u64)
+        };
+        // JPEGInterchangeFormat 指向的预览图
+        if let (Some(offset), Some(length)) = (
+            single(&mut tiff, TAG_JPEG_OFFSET),
+            single(&mut tiff, TAG_JPEG_LENGTH),
+        ) {
+            ranges.push((offset, length));
+        }
+        // 以单个 JPEG 条带存储的图像，例如 CR2 的 IFD0 和 DNG 的预览 IFD
+        if matches!(single(&mut tiff, TAG_COMPRESSION), Some(6 | 7))
+            && let (Some(offset), Some(length)) = (
+                single(&mut tiff, TAG_STRIP_OFFSETS),
+                single(&mut tiff, TAG_STRIP_BYTE_COUNTS),
+            )
+        {
+            ranges.push((offset, length));
+        }
+    }
+    ranges.retain(|(_, length)| (1..=MAX_PREVIEW_SIZE).contains(length));
+    ranges.sort_unstable_by_key(|(_, length)| std::cmp::Reverse(*length));
+    ranges.dedup();
+    ranges
+}
+
+/// 是否为可以解码的 JPEG，RAW 数据常用的无损 JPEG（SOF3）无法解码
+fn is_decodable_jpeg(data: &[u8]) -> bool {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return false;
+    }
+    let mut offset = 2;
+    while let Some(&[0xFF, marker, high, low]) = data.get(offset..offset + 4) {
+        match marker {
+            // baseline、extended sequential 和 progressive
+            0xC0..=0xC2 => return true,
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return false,
+            _ => offset += 2 + u16::from_be_bytes([high, low]) as usize,
+        }
+    }
+    false
+}
+
+/// 解码 RAW 文件中最大的嵌入预览图，用于生成缩略图
+pub fn decode_preview(data: &[u8]) -> Result<image::DynamicImage, String> {
+    let mut cursor = std::io::Cursor::new(data);
+    for (offset, length) in preview_ranges(&mut cursor) {
+        let Some(preview) = data.get(offset as usize..(offset + length) as usize) else {
+            continue;
+        };
+        if !is_decodable_jpeg(preview) {
+            continue;
+        }
+        match image::load_from_memory_with_format(preview, image::ImageFormat::Jpeg) {
+            Ok(image) => return Ok(image),
+            Err(e) => tracing::debug!(error = ?e, offset, "Failed to decode embedded preview"),
+        }
+    }
+    Err("RAW file has no decodable embedded preview".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+
+    /// 按顺序追加 IFD 和数据的 TIFF 构造器
+    struct TiffBuilder {
+        data: Vec<u8>,
+        little_endian: bool,
+    }
+
+    impl TiffBuilder {
+        fn new(little_endian: bool) -> Self {
+            let mut builder = Self {
+                data: Vec::new(),
+                little_endian,
+            };
+            builder
+                .data
+                .extend_from_slice(if little_endian { b"II" } else { b"MM" });
+            builder.push_u16(42);
+            builder.push_u32(0);
+            builder
+        }
+
+        fn u16(&self, v: u16) -> [u8; 2] {
+            if self.little_endian {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        }
+
+        fn u32(&self, v: u32) -> [u8; 4] {
+            if self.little_endian {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        }
+
+        fn push_u16(&mut self, v: u16) {
+            let bytes = self.u16(v);
+            self.data.extend_from_slice(&bytes);
+        }
+
+        fn push_u32(&mut self, v: u32) {
+            let bytes = self.u32(v);
+            self.data.extend_from_slice(&bytes);
+        }
+
+        fn patch_u32(&mut self, offset: u32, v: u32) {
+            let bytes = self.u32(v);
+            self.data[offset as usize..offset as usize + 4].copy_from_slice(&bytes);
+        }
+
+        fn blob(&mut self, bytes: &[u8]) -> u32 {
+            let offset = self.data.len() as u32;
+            self.data.extend_from_slice(bytes);
+            offset
+        }
+
+        fn short(&self, tag: u16, v: u16) -> (u16, u16, u32, [u8; 4]) {
+            let [a, b] = self.u16(v);
+            (tag, SHORT, 1, [a, b, 0, 0])
+        }
+
+        fn long(&self, tag: u16, v: u32) -> (u16, u16, u32, [u8; 4]) {
+            (tag, LONG, 1, self.u32(v))
+        }
+
+        fn ascii(&mut self, tag: u16, v: &str) -> (u16, u16, u32, [u8; 4]) {
+            let mut bytes = v.as_bytes().to_vec();
+            bytes.push(0);
+            let offset = self.blob(&bytes);
+            (tag, 2, bytes.len() as u32, self.u32(offset))
+        }
+
+        /// 追加一个 IFD，下一个 IFD 的偏移量为 0，返回 IFD 的偏移量
+        fn ifd(&mut self, entries: &[(u16, u16, u32, [u8; 4])]) -> u32 {
+            let offset = self.data.len() as u32;
+            self.push_u16(entries.len() as u16);
+            for (tag, field_type, count, value) in entries {
+                self.push_u16(*tag);
+                self.push_u16(*field_type);
+                self.push_u32(*count);
+                self.data.extend_from_slice(value);
+            }
+            self.push_u32(0);
+            offset
+        }
+
+        fn set_first_ifd(&mut self, offset: u32) {
+            self.patch_u32(4, offset);
+        }
+
+        fn set_next_ifd(&mut self, ifd: u32, next: u32) {
+            let count = self.read_u16(ifd) as u32;
+            self.patch_u32(ifd + 2 + count * 12, next);
+        }
+
+        fn read_u16(&self, offset: u32) -> u16 {
+            let bytes = [self.data[offset as usize], self.data[offset as usize + 1]];
+            if self.little_endian {
+                u16::from_le_bytes(bytes)
+            } else {
+                u16::from_be_bytes(bytes)
+            }
+        }
+    }
+
+    /// 只有 IFD0 的 TIFF，IFD0 中包含 `Make`
+    fn with_make(make: &str) -> Vec<u8> {
+        let mut tiff = TiffBuilder::new(true);
+        let make = tiff.ascii(TAG_MAKE, make);
+        let ifd0 = tiff.ifd(&[make]);
+        tiff.set_first_ifd(ifd0);
+        tiff.data
+    }
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        image::RgbImage::new(width, height)
+            .write_to(&mut data, image::ImageFormat::Jpeg)
+            .unwrap();
+        data.into_inner()
+    }
+
+    /// DNG 结构：IFD0 是小尺寸预览图，SubIFD 是全尺寸 RAW 数据，IFD1 是缩略图
+    fn dng(little_endian: bool) -> Vec<u8> {
+        let mut tiff = TiffBuilder::new(little_endian);
+        // 无损 JPEG 编码的 RAW 数据，比预览图大但无法解码
+        let mut lossless = vec![0xFF, 0xD8, 0xFF, 0xC3, 0x00, 0x0B];
+        lossless.resize(20_000, 0);
+        let raw_data = tiff.blob(&lossless);
+        let preview = jpeg(64, 48);
+        let preview_offset = tiff.blob(&preview);
+        let thumbnail = jpeg(16, 12);
+        let thumbnail_offset = tiff.blob(&thumbnail);
+
+        let sub_ifd = tiff.ifd(&[
+            tiff.long(TAG_NEW_SUBFILE_TYPE, 0),
+            tiff.long(TAG_IMAGE_WIDTH, 6016),
+            tiff.long(TAG_IMAGE_LENGTH, 4016),
+            tiff.short(TAG_COMPRESSION, 7),
+            tiff.long(TAG_STRIP_OFFSETS, raw_data),
+            tiff.long(TAG_STRIP_BYTE_COUNTS, lossless.len() as u32),
+        ]);
+        let ifd1 = tiff.ifd(&[
+            tiff.long(TAG_JPEG_OFFSET, thumbnail_offset),
+            tiff.long(TAG_JPEG_LENGTH, thumbnail.len() as u32),
+        ]);
+        let make = tiff.ascii(TAG_MAKE, "Canon");
+        let ifd0 = tiff.ifd(&[
+            tiff.long(TAG_NEW_SUBFILE_TYPE, 1),
+            tiff.short(TAG_IMAGE_WIDTH, 64),
+            tiff.short(TAG_IMAGE_LENGTH, 48),
+            tiff.short(TAG_COMPRESSION, 6),
+            make,
+            tiff.long(TAG_STRIP_OFFSETS, preview_offset),
+            tiff.long(TAG_STRIP_BYTE_COUNTS, preview.len() as u32),
+            tiff.long(TAG_SUB_IFDS, sub_ifd),
+            (TAG_DNG_VERSION, 1, 4, [1, 4, 0, 0]),
+        ]);
+        tiff.set_next_ifd(ifd0, ifd1);
+        tiff.set_first_ifd(ifd0);
+        tiff.data
+    }
+
+    fn identify_bytes(data: &[u8]) -> Option<MediaFormat> {
+        identify(&mut Cursor::new(data))
+    }
+
+    #[test]
+    fn identify_formats() {
+        assert_eq!(identify_bytes(&dng(true)), Some(MediaFormat::Dng));
+        assert_eq!(identify_bytes(&dng(false)), Some(MediaFormat::Dng));
+        assert_eq!(
+            identify_bytes(&with_make("NIKON CORPORATION")),
+            Some(MediaFormat::Nef)
+        );
+        assert_eq!(identify_bytes(&with_make("SONY")), Some(MediaFormat::Arw));
+        // 普通 TIFF
+        assert_eq!(identify_bytes(&with_make("Canon")), None);
+        assert_eq!(identify_bytes(&with_make("")), None);
+
+        let mut cr2 = b"II*\0\x10\0\0\0CR\x02\0\0\0\0\0".to_vec();
+        cr2.extend_from_slice(&[0; 6]);
+        assert_eq!(identify_bytes(&cr2), Some(MediaFormat::Cr2));
+
+        assert_eq!(identify_bytes(b"\xFF\xD8\xFF\xE0"), None);
+        assert_eq!(identify_bytes(b"II*\0\xFF\xFF\0\0\0\0\0\0"), None);
+    }
+
+    #[test]
+    fn full_size_dimensions() {
+        for little_endian in [true, false] {
+            assert_eq!(
+                dimensions(&mut Cursor::new(dng(little_endian))),
+                Some((6016, 4016))
+            );
+        }
+
+        // 没有全尺寸图像时使用面积最大的
+        let mut tiff = TiffBuilder::new(true);
+        let ifd1 = tiff.ifd(&[
+            tiff.long(TAG_NEW_SUBFILE_TYPE, 1),
+            tiff.short(TAG_IMAGE_WIDTH, 1024),
+            tiff.short(TAG_IMAGE_LENGTH, 768),
+        ]);
+        let ifd0 = tiff.ifd(&[
+            tiff.long(TAG_NEW_SUBFILE_TYPE, 1),
+            tiff.short(TAG_IMAGE_WIDTH, 160),
+            tiff.short(TAG_IMAGE_LENGTH, 120),
+        ]);
+        tiff.set_next_ifd(ifd0, ifd1);
+        tiff.set_first_ifd(ifd0);
+        assert_eq!(dimensions(&mut Cursor::new(tiff.data)), Some((1024, 768)));
+    }
+
+    #[test]
+    fn ifd_cycles() {
+        let mut tiff = TiffBuilder::new(true);
+        let ifd0 = tiff.ifd(&[
+            tiff.short(TAG_IMAGE_WIDTH, 100),
+            tiff.short(TAG_IMAGE_LENGTH, 50),
+        ]);
+        let sub_ifd = tiff.ifd(&[tiff.long(TAG_SUB_IFDS, ifd0)]);
+        tiff.set_next_ifd(ifd0, sub_ifd);
+        tiff.set_next_ifd(sub_ifd, ifd0);
+        tiff.set_first_ifd(ifd0);
+        assert_eq!(dimensions(&mut Cursor::new(tiff.data)), Some((100, 50)));
+    }
+
+    #[test]
+    fn largest_decodable_preview() {
+        let image = decode_preview(&dng(true)).unwrap();
+        assert_eq!((image.width(), image.height()), (64, 48));
+
+        // 超出文件范围的预览图会被跳过
+        let mut tiff = TiffBuilder::new(true);
+        let thumbnail = jpeg(16, 12);
+        let thumbnail_offset = tiff.blob(&thumbnail);
+        let ifd0 = tiff.ifd(&[
+            tiff.short(TAG_COMPRESSION, 6),
+            tiff.long(TAG_STRIP_OFFSETS, 8),
+            tiff.long(TAG_STRIP_BYTE_COUNTS, 1_000_000),
+            tiff.long(TAG_JPEG_OFFSET, thumbnail_offset),
+            tiff.long(TAG_JPEG_LENGTH, thumbnail.len() as u32),
+        ]);
+        tiff.set_first_ifd(ifd0);
+        let image = decode_preview(&tiff.data).unwrap();
+        assert_eq!((image.width(), image.height()), (16, 12));
+
+        assert!(decode_preview(&with_make("NIKON")).is_err());
+        assert!(decode_preview(b"not a tiff").is_err());
+    }
+
+    #[test]
+    fn decodable_jpeg() {
+        assert!(is_decodable_jpeg(&jpeg(8, 8)));
+        assert!(!is_decodable_jpeg(&[0xFF, 0xD8, 0xFF, 0xC3, 0x00, 0x0B]));
+        assert!(!is_decodable_jpeg(&[0xFF, 0xD8, 0xFF, 0xE1, 0x00]));
+        assert!(!is_decodable_jpeg(b"II*\0"));
+    }
+}
@@ -0,0 +1,159 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+
+use crate::infra::storage::{QUARANTINE_SUFFIX, Storage, StorageError, is_content_hash};
+
+#[derive(Debug, Error)]
+pub enum ScrubError {
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScrubReport {
+    pub dry_run: bool,
+    /// 检查过的对象数
+    pub scanned: u64,
+    /// 内容与 hash 不一致的对象，非 dry run 时会被隔离
+    pub corrupt: Vec<String>,
+    /// 数据库中有记录但存储中不存在的对象
+    pub missing: Vec<String>,
+    /// 存储中存在但数据库中没有记录的对象，交给 GC 之外的人工处理
+    pub orphaned: Vec<String>,
+    /// 之前已被隔离、仍未处理的对象
+    pub quarantined: Vec<String>,
+}
+
+/// 流式计算对象的 SHA256，避免把大文件整个读入内存
+async fn hash_object(storage: &dyn Storage, key: &str) -> Result<String, StorageError> {
+    let mut reader = storage.read(key, None).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// 遍历存储，校验每个原图的内容 hash，并与 image / rendition 记录对账。
+///
+/// 损坏的对象会被隔离为 `{key}.quarantined`，对应的 rendition 记录被删除以便重新生成；
+/// 原图损坏后需要重新上传同一文件来修复。
+pub async fn scrub_storage(
+    db: &PgPool,
+    storage: &dyn Storage,
+    dry_run: bool,
+) -> Result<ScrubReport, ScrubError> {
+    let images: HashSet<String> = sqlx::query_scalar!(r#"SELECT "hash" FROM "image""#)
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .collect();
+    let renditions: HashSet<String> =
+        sqlx::query_scalar!(r#"SELECT "storage_key" FROM "rendition""#)
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .collect();
+
+    // 分块上传暂存的对象和等待 GC 删除的缩略图不参与校验
+    let chunks: HashSet<String> =
+        sqlx::query_scalar!(r#"SELECT "storage_key" FROM "upload_chunk""#)
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .collect();
+    let stale: HashSet<String> =
+        sqlx::query_scalar!(r#"SELECT "storage_key" FROM "stale_rendition""#)
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .collect();
+
+    let mut objects = storage.list().await?;
+    objects.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let mut report = ScrubReport {
+        dry_run,
+        scanned: 0,
+        corrupt: vec![],
+        missing: vec![],
+        orphaned: vec![],
+        quarantined: vec![],
+    };
+    let mut present = HashSet::new();
+
+    for object in objects {
+        if object.key.ends_with(QUARANTINE_SUFFIX) {
+            report.quarantined.push(object.key);
+            continue;
+        }
+        if chunks.contains(&object.key) || stale.contains(&object.key) {
+            continue;
+        }
+        report.scanned += 1;
+        present.insert(object.key.clone());
+
+        if !is_content_hash(&object.key) {
+            if !renditions.contains(&object.key) {
+                report.orphaned.push(object.key);
+            }
+            continue;
+        }
+        if !images.contains(&object.key) {
+            report.orphaned.push(object.key.clone());
+        }
+
+        let hash = match hash_object(storage, &object.key).await {
+            Ok(hash) => hash,
+            // 遍历期间被 GC 删除
+            Err(StorageError::NotFound(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if hash == object.key {
+            continue;
+        }
+
+        tracing::warn!(object = object.key, actual = hash, "Corrupted object");
+        if !dry_run {
+            storage.quarantine(&object.key).await?;
+            present.remove(&object.key);
+        }
+        report.corrupt.push(object.key);
+    }
+
+    let mut missing: Vec<String> = images
+        .into_iter()
+        .chain(renditions)
+        .filter(|key| !present.contains(key))
+        .collect();
+    missing.sort();
+
+    if !dry_run {
+        // 缩略图可以从原图重新生成，删除记录后下次访问时会自动重建
+        let keys: Vec<String> = missing
+            .iter()
+            .filter(|key| !is_content_hash(key))
+            .cloned()
+            .collect();
+        sqlx::query!(
+            r#"DELETE FROM "rendition" WHERE "storage_key" = ANY($1)"#,
+            &keys
+        )
+        .execute(db)
+        .await?;
+    }
+    report.missing = missing;
+
+    Ok(report)
+}
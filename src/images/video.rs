@@ -0,0 +1,435 @@
+use std::io::{Read, Seek};
+
+use time::{
+    Duration, OffsetDateTime, PrimitiveDateTime, UtcOffset, macros::datetime,
+    macros::format_description,
+};
+
+use crate::capture_time::parse_offset;
+
+use crate::images::bmff::{boxes, read_top_level, read_u16, read_u32};
+
+/// `moov` box 的大小上限，只包含索引，长视频也只有几 MB
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+
+/// QuickTime 的时间从 1904 年开始计算
+const QUICKTIME_EPOCH: OffsetDateTime = datetime!(1904-01-01 0:00 UTC);
+
+const KEY_CONTENT_IDENTIFIER: &[u8] = b"com.apple.quicktime.content.identifier";
+const KEY_CREATION_DATE: &[u8] = b"com.apple.quicktime.creationdate";
+const KEY_LOCATION: &[u8] = b"com.apple.quicktime.location.ISO6709";
+
+/// 从 MP4 / MOV 容器的 `moov` box 中读取的元数据
+#[derive(Debug, Default)]
+pub struct VideoInfo {
+    /// 显示尺寸，已按旋转矩阵交换宽高
+    pub width: u32,
+    pub height: u32,
+    pub duration_ms: i64,
+    /// 拍摄时的本地时间
+    pub created_at: Option<PrimitiveDateTime>,
+    /// 本地时间附带的 UTC 偏移
+    pub created_offset: Option<UtcOffset>,
+    /// `mvhd` 中的 UTC 创建时间，没有本地时间时使用
+    pub created_at_utc: Option<OffsetDateTime>,
+    pub coordinates: Option<(f64, f64)>,
+    /// Live Photo 的图片和视频共享的标识
+    pub content_identifier: Option<String>,
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn find<'a>(data: &'a [u8], box_type: &[u8]) -> Option<&'a [u8]> {
+    boxes(data)
+        .find(|(t, _)| *t == box_type)
+        .map(|(_, content)| content)
+}
+
+/// `mvhd`：创建时间和时长
+fn parse_mvhd(mvhd: &[u8]) -> Option<(u64, i64)> {
+    let (created, timescale, duration) = match mvhd.first()? {
+        1 => (read_u64(mvhd, 4)?, read_u32(mvhd, 20)?, read_u64(mvhd, 24)?),
+        _ => (
+            read_u32(mvhd, 4)? as u64,
+            read_u32(mvhd, 12)?,
+            read_u32(mvhd, 16)? as u64,
+        ),
+    };
+    if timescale == 0 {
+        return None;
+    }
+    Some((
+        created,
+        (duration as u128 * 1000 / timescale as u128) as i64,
+    ))
+}
+
+/// `tkhd`：显示尺寸（16.16 定点数），旋转 90° 或 270° 时交换宽高
+fn parse_tkhd(tkhd: &[u8]) -> Option<(u32, u32)> {
+    let matrix = match tkhd.first()? {
+        1 => 52,
+        _ => 40,
+    };
+    let a = read_u32(tkhd, matrix)?;
+    let d = read_u32(tkhd, matrix + 16)?;
+    let width = read_u32(tkhd, matrix + 36)? >> 16;
+    let height = read_u32(tkhd, matrix + 40)? >> 16;
+    if a == 0 && d == 0 {
+        Some((height, width))
+    } else {
+        Some((width, height))
+    }
+}
+
+/// 第一条视频轨道（`hdlr` 类型为 `vide`）的尺寸
+fn video_track_dimensions(moov: &[u8]) -> Option<(u32, u32)> {
+    boxes(moov)
+        .filter(|(t, _)| *t == b"trak")
+        .find(|(_, trak)| {
+            find(trak, b"mdia")
+                .and_then(|mdia| find(mdia, b"hdlr"))
+                .and_then(|hdlr| hdlr.get(8..12))
+                == Some(b"vide")
+        })
+        .and_then(|(_, trak)| parse_tkhd(find(trak, b"tkhd")?))
+        .filter(|(width, height)| *width > 0 && *height > 0)
+}
+
+/// QuickTime 的 `meta` box 不是 full box，MP4 的是，通过第一个子 box 是否为 `hdlr` 区分
+fn meta_children(meta: &[u8]) -> &[u8] {
+    match meta.get(4..8) {
+        Some(b"hdlr") => meta,
+        _ => meta.get(4..).unwrap_or_default(),
+    }
+}
+
+/// 读取 `meta` 中 `keys` + `ilst` 形式的字符串元数据
+fn metadata_items(meta: &[u8]) -> Vec<(&[u8], String)> {
+    let meta = meta_children(meta);
+    let Some(keys) = find(meta, b"keys") else {
+        return Vec::new();
+    };
+    // keys: version/flags、数量，然后是 size + namespace + 名称
+    let keys: Vec<&[u8]> = boxes(keys.get(8..).unwrap_or_default())
+        .map(|(_, name)| name)
+        .collect();
+    let Some(ilst) = find(meta, b"ilst") else {
+        return Vec::new();
+    };
+
+    boxes(ilst)
+        .filter_map(|(index, item)| {
+            // ilst 子 box 的类型是从 1 开始的 key 序号
+            let index = u32::from_be_bytes(index.try_into().ok()?) as usize;
+            let key = *keys.get(index.checked_sub(1)?)?;
+            // data: 类型、locale，然后是值，类型 1 为 UTF-8
+            let data = find(item, b"data")?;
+            if read_u32(data, 0)? != 1 {
+                return None;
+            }
+            Some((key, String::from_utf8_lossy(data.get(8..)?).into_owned()))
+        })
+        .collect()
+}
+
+/// `udta` 中的 `©xyz`，Android 等设备录制的 MP4 在这里记录位置
+fn udta_location(moov: &[u8]) -> Option<String> {
+    let xyz = find(find(moov, b"udta")?, b"\xa9xyz")?;
+    let len = read_u16(xyz, 0)? as usize;
+    Some(String::from_utf8_lossy(xyz.get(4..4 + len)?).into_owned())
+}
+
+/// 解析 ISO 6709 格式的位置，例如 `+31.2304+121.4737+010.000/`
+fn parse_iso6709(value: &str) -> Option<(f64, f64)> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (i, c) in value.char_indices().skip(1) {
+        if matches!(c, '+' | '-' | '/') {
+            parts.push(&value[start..i]);
+            start = i;
+        }
+    }
+    let latitude: f64 = parts.first()?.parse().ok()?;
+    let longitude: f64 = parts.get(1)?.parse().ok()?;
+    ((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude))
+        .then_some((latitude, longitude))
+}
+
+/// 解析 `2024-05-01T10:20:30+0800` 形式的时间，返回本地时间和偏移
+fn parse_creation_date(value: &str) -> Option<(PrimitiveDateTime, Option<UtcOffset>)> {
+    let local = PrimitiveDateTime::parse(
+        value.get(..19)?,
+        format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]"),
+    )
+    .ok()?;
+    Some((local, value.get(19..).and_then(parse_offset)))
+}
+
+/// 读取视频的尺寸、时长、拍摄时间、位置和 Live Photo 标识，只读取 `moov` box
+pub fn probe<R: Read + Seek>(reader: &mut R) -> Option<VideoInfo> {
+    let moov = read_top_level(reader, b"moov", MAX_MOOV_SIZE)?;
+    let (created, duration_ms) = parse_mvhd(find(&moov, b"mvhd")?)?;
+    let (width, height) = video_track_dimensions(&moov)?;
+
+    let mut info = VideoInfo {
+        width,
+        height,
+        duration_ms,
+        ..Default::default()
+    };
+
+    let items = find(&moov, b"meta").map(metadata_items).unwrap_or_default();
+    for (key, value) in items {
+        match key {
+            KEY_CONTENT_IDENTIFIER => info.content_identifier = Some(value),
+            KEY_CREATION_DATE => {
+                if let Some((local, offset)) = parse_creation_date(&value) {
+                    info.created_at = Some(local);
+                    info.created_offset = offset;
+                }
+            }
+            KEY_LOCATION => info.coordinates = parse_iso6709(&value),
+            _ => {}
+        }
+    }
+
+    if info.coordinates.is_none() {
+        info.coordinates = udta_location(&moov).and_then(|v| parse_iso6709(&v));
+    }
+    // 0 表示未设置
+    if created > 0 {
+        info.created_at_utc = Some(QUICKTIME_EPOCH + Duration::seconds(created as i64));
+    }
+
+    Some(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use time::macros::{datetime, offset};
+
+    fn make_box(box_type: &[u8], content: &[u8]) -> Vec<u8> {
+        let mut data = (8 + content.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn container(box_type: &[u8], children: &[Vec<u8>]) -> Vec<u8> {
+        make_box(box_type, &children.concat())
+    }
+
+    fn mvhd_v0(created: u32, timescale: u32, duration: u32) -> Vec<u8> {
+        let mut content = vec![0, 0, 0, 0];
+        content.extend_from_slice(&created.to_be_bytes());
+        content.extend_from_slice(&created.to_be_bytes());
+        content.extend_from_slice(&timescale.to_be_bytes());
+        content.extend_from_slice(&duration.to_be_bytes());
+        content.resize(100, 0);
+        make_box(b"mvhd", &content)
+    }
+
+    fn mvhd_v1(created: u64, timescale: u32, duration: u64) -> Vec<u8> {
+        let mut content = vec![1, 0, 0, 0];
+        content.extend_from_slice(&created.to_be_bytes());
+        content.extend_from_slice(&created.to_be_bytes());
+        content.extend_from_slice(&timescale.to_be_bytes());
+        content.extend_from_slice(&duration.to_be_bytes());
+        content.resize(112, 0);
+        make_box(b"mvhd", &content)
+    }
+
+    const IDENTITY: [i32; 9] = [0x10000, 0, 0, 0, 0x10000, 0, 0, 0, 0x40000000];
+    const ROTATE_90: [i32; 9] = [0, 0x10000, 0, -0x10000, 0, 0, 0, 0, 0x40000000];
+
+    fn tkhd(version: u8, matrix: [i32; 9], width: u32, height: u32) -> Vec<u8> {
+        let mut content = vec![version, 0, 0, 7];
+        // 时间、track id 和时长，之后是保留字段、layer、alternate group 和 volume
+        content.resize(if version == 1 { 52 } else { 40 }, 0);
+        for value in matrix {
+            content.extend_from_slice(&value.to_be_bytes());
+        }
+        content.extend_from_slice(&(width << 16).to_be_bytes());
+        content.extend_from_slice(&(height << 16).to_be_bytes());
+        make_box(b"tkhd", &content)
+    }
+
+    fn trak(handler: &[u8; 4], tkhd: Vec<u8>) -> Vec<u8> {
+        let mut hdlr = vec![0; 8];
+        hdlr.extend_from_slice(handler);
+        hdlr.extend_from_slice(&[0; 13]);
+        container(
+            b"trak",
+            &[tkhd, container(b"mdia", &[make_box(b"hdlr", &hdlr)])],
+        )
+    }
+
+    /// QuickTime 风格的 `meta`：`hdlr` + `keys` + `ilst`
+    fn quicktime_meta(items: &[(&[u8], &str)]) -> Vec<u8> {
+        let mut keys = vec![0, 0, 0, 0];
+        keys.extend_from_slice(&(items.len() as u32).to_be_bytes());
+        let mut ilst = Vec::new();
+        for (i, (key, value)) in items.iter().enumerate() {
+            keys.extend(make_box(b"mdta", key));
+            let mut data = 1u32.to_be_bytes().to_vec();
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(value.as_bytes());
+            ilst.extend(container(
+                &(i as u32 + 1).to_be_bytes(),
+                &[make_box(b"data", &data)],
+            ));
+        }
+        let mut hdlr = vec![0; 8];
+        hdlr.extend_from_slice(b"mdta");
+        hdlr.extend_from_slice(&[0; 13]);
+        container(
+            b"meta",
+            &[
+                make_box(b"hdlr", &hdlr),
+                make_box(b"keys", &keys),
+                make_box(b"ilst", &ilst),
+            ],
+        )
+    }
+
+    fn file(moov: Vec<u8>) -> Vec<u8> {
+        let mut data = make_box(b"ftyp", b"qt  \0\0\0\0qt  ");
+        data.extend(make_box(b"wide", b""));
+        data.extend(make_box(b"mdat", &[0; 256]));
+        data.extend(moov);
+        data
+    }
+
+    fn probe_bytes(data: Vec<u8>) -> Option<VideoInfo> {
+        probe(&mut Cursor::new(data))
+    }
+
+    fn quicktime_seconds(at: OffsetDateTime) -> u32 {
+        (at - QUICKTIME_EPOCH).whole_seconds() as u32
+    }
+
+    #[test]
+    fn live_photo_video() {
+        let moov = container(
+            b"moov",
+            &[
+                mvhd_v0(
+                    quicktime_seconds(datetime!(2024-05-01 2:20:31 UTC)),
+                    600,
+                    1800,
+                ),
+                trak(b"soun", tkhd(0, IDENTITY, 0, 0)),
+                trak(b"vide", tkhd(0, ROTATE_90, 1920, 1080)),
+                quicktime_meta(&[
+                    (
+                        KEY_CONTENT_IDENTIFIER,
+                        "5D1E8D4A-1E7B-4F0A-9D38-3F0C6B1E2A77",
+                    ),
+                    (KEY_CREATION_DATE, "2024-05-01T10:20:30+0800"),
+                    (KEY_LOCATION, "+31.2304+121.4737+010.000/"),
+                    (b"com.apple.quicktime.make", "Apple"),
+                ]),
+            ],
+        );
+        let info = probe_bytes(file(moov)).unwrap();
+        assert_eq!((info.width, info.height), (1080, 1920));
+        assert_eq!(info.duration_ms, 3000);
+        assert_eq!(info.created_at, Some(datetime!(2024-05-01 10:20:30)));
+        assert_eq!(info.created_offset, Some(offset!(+8)));
+        assert_eq!(info.created_at_utc, Some(datetime!(2024-05-01 2:20:31 UTC)));
+        assert_eq!(info.coordinates, Some((31.2304, 121.4737)));
+        assert_eq!(
+            info.content_identifier.as_deref(),
+            Some("5D1E8D4A-1E7B-4F0A-9D38-3F0C6B1E2A77")
+        );
+    }
+
+    #[test]
+    fn mp4_with_udta_location() {
+        let mut xyz = 18u16.to_be_bytes().to_vec();
+        xyz.extend_from_slice(&[0x15, 0xC7]);
+        xyz.extend_from_slice(b"-33.8688+151.2093/");
+        let moov = container(
+            b"moov",
+            &[
+                mvhd_v1(0, 90000, 900_000),
+                trak(b"vide", tkhd(1, IDENTITY, 3840, 2160)),
+                container(b"udta", &[make_box(b"\xa9xyz", &xyz)]),
+                // MP4 的 meta 是 full box
+                make_box(b"meta", &[0, 0, 0, 0]),
+            ],
+        );
+        let info = probe_bytes(file(moov)).unwrap();
+        assert_eq!((info.width, info.height), (3840, 2160));
+        assert_eq!(info.duration_ms, 10_000);
+        assert_eq!(info.coordinates, Some((-33.8688, 151.2093)));
+        assert_eq!(info.created_at, None);
+        assert_eq!(info.created_at_utc, None);
+        assert_eq!(info.content_identifier, None);
+    }
+
+    #[test]
+    fn invalid_metadata() {
+        let moov = container(
+            b"moov",
+            &[
+                mvhd_v0(0, 1000, 500),
+                trak(b"vide", tkhd(0, IDENTITY, 640, 480)),
+                quicktime_meta(&[
+                    (KEY_CREATION_DATE, "2024-05-01"),
+                    (KEY_LOCATION, "+91.0000+000.0000/"),
+                ]),
+            ],
+        );
+        let info = probe_bytes(file(moov)).unwrap();
+        assert_eq!(info.duration_ms, 500);
+        assert_eq!(info.created_at, None);
+        assert_eq!(info.coordinates, None);
+    }
+
+    #[test]
+    fn not_a_video() {
+        // 只有音轨
+        let audio_only = container(
+            b"moov",
+            &[
+                mvhd_v0(0, 1000, 500),
+                trak(b"soun", tkhd(0, IDENTITY, 0, 0)),
+            ],
+        );
+        assert!(probe_bytes(file(audio_only)).is_none());
+
+        let zero_timescale = container(
+            b"moov",
+            &[
+                mvhd_v0(0, 0, 500),
+                trak(b"vide", tkhd(0, IDENTITY, 640, 480)),
+            ],
+        );
+        assert!(probe_bytes(file(zero_timescale)).is_none());
+
+        assert!(probe_bytes(make_box(b"ftyp", b"isom\0\0\0\0")).is_none());
+    }
+
+    #[test]
+    fn iso6709() {
+        assert_eq!(
+            parse_iso6709("+31.2304+121.4737/"),
+            Some((31.2304, 121.4737))
+        );
+        assert_eq!(
+            parse_iso6709("-33.8688+151.2093+025.500/"),
+            Some((-33.8688, 151.2093))
+        );
+        assert_eq!(parse_iso6709("+00.0000-180.0000/"), Some((0.0, -180.0)));
+        assert_eq!(parse_iso6709("+31.2304/"), None);
+        assert_eq!(parse_iso6709("+31.2304+181.0000/"), None);
+        assert_eq!(parse_iso6709(""), None);
+    }
+}
@@ -0,0 +1,328 @@
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use std::time::{Duration, SystemTime};
+use time::OffsetDateTime;
+use tokio_util::io::ReaderStream;
+
+use super::storage::{Storage, StorageError};
+
+/// 对象按内容寻址，内容永不改变；需要鉴权，因此只允许私有缓存
+const CACHE_CONTROL_IMMUTABLE: &str = "private, max-age=31536000, immutable";
+/// 同一 URL 对应的对象可能变化时，每次都用 ETag 重新验证
+const CACHE_CONTROL_REVALIDATE: &str = "private, no-cache";
+
+pub struct StoredObject<'a> {
+    /// 存储 key，同时作为强 ETag
+    pub key: &'a str,
+    pub content_type: &'a str,
+    pub last_modified: OffsetDateTime,
+    /// 请求的 URL 是否总是对应这个对象，例如缩略图 URL 中带有当前的旋转角度
+    pub immutable: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum RangeRequest {
+    /// 闭区间 [start, end]
+    Satisfiable(u64, u64),
+    Unsatisfiable,
+}
+
+/// 只支持单个区间，多区间或格式错误时按规范忽略 Range 头
+fn parse_range(value: &str, len: u64) -> Option<RangeRequest> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+        // 后缀区间：最后 N 个字节
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(RangeRequest::Unsatisfiable);
+        }
+        (len.saturating_sub(suffix), len - 1)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = if end.is_empty() {
+            len.saturating_sub(1)
+        } else {
+            let end: u64 = end.parse().ok()?;
+            if end < start {
+                return None;
+            }
+            end.min(len.saturating_sub(1))
+        };
+        if start >= len {
+            return Some(RangeRequest::Unsatisfiable);
+        }
+        (start, end)
+    };
+
+    Some(RangeRequest::Satisfiable(range.0, range.1))
+}
+
+/// If-None-Match 使用弱比较
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|t| t.trim())
+        .any(|t| t == "*" || t.strip_prefix("W/").unwrap_or(t) == etag)
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: SystemTime) -> bool {
+    // 同时存在时 If-None-Match 优先
+    if let Some(if_none_match) = header_str(headers, header::IF_NONE_MATCH) {
+        return etag_matches(if_none_match, etag);
+    }
+    header_str(headers, header::IF_MODIFIED_SINCE)
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+        .is_some_and(|since| last_modified <= since)
+}
+
+/// If-Range 不匹配时忽略 Range，返回完整内容
+fn is_range_allowed(headers: &HeaderMap, etag: &str, last_modified: SystemTime) -> bool {
+    match header_str(headers, header::IF_RANGE) {
+        None => true,
+        Some(v) if v.starts_with('"') => v == etag,
+        Some(v) => httpdate::parse_http_date(v).is_ok_and(|date| date == last_modified),
+    }
+}
+
+fn set_header(headers: &mut HeaderMap, name: header::HeaderName, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+fn internal_error() -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error".to_string(),
+    )
+}
+
+fn read_error(key: &str) -> impl FnOnce(StorageError) -> (StatusCode, String) + '_ {
+    move |e| {
+        tracing::error!(error = ?e, object = key, "Failed to read object");
+        internal_error()
+    }
+}
+
+/// 从存储流式返回对象，支持 ETag / Last-Modified 条件请求和单区间 Range 请求
+pub async fn serve_object(
+    storage: &dyn Storage,
+    headers: &HeaderMap,
+    object: StoredObject<'_>,
+) -> Result<Response, (StatusCode, String)> {
+    let etag = format!("\"{}\"", object.key);
+    // HTTP 日期只精确到秒
+    let last_modified =
+        SystemTime::UNIX_EPOCH + Duration::from_secs(object.last_modified.unix_timestamp() as u64);
+
+    let mut response_headers = HeaderMap::new();
+    set_header(&mut response_headers, header::ETAG, &etag);
+    set_header(
+        &mut response_headers,
+        header::LAST_MODIFIED,
+        &httpdate::fmt_http_date(last_modified),
+    );
+    set_header(
+        &mut response_headers,
+        header::CACHE_CONTROL,
+        if object.immutable {
+            CACHE_CONTROL_IMMUTABLE
+        } else {
+            CACHE_CONTROL_REVALIDATE
+        },
+    );
+    set_header(&mut response_headers, header::ACCEPT_RANGES, "bytes");
+
+    if is_not_modified(headers, &etag, last_modified) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+
+    let len = storage.size(object.key).await.map_err(|e| {
+        tracing::error!(error = ?e, object = object.key, "Failed to stat object");
+        internal_error()
+    })?;
+
+    let range = header_str(headers, header::RANGE)
+        .filter(|_| is_range_allowed(headers, &etag, last_modified))
+        .and_then(|v| parse_range(v, len));
+
+    set_header(
+        &mut response_headers,
+        header::CONTENT_TYPE,
+        object.content_type,
+    );
+
+    match range {
+        Some(RangeRequest::Unsatisfiable) => {
+            set_header(
+                &mut response_headers,
+                header::CONTENT_RANGE,
+                &format!("bytes */{}", len),
+            );
+            Ok((StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response())
+        }
+        Some(RangeRequest::Satisfiable(start, end)) => {
+            let reader = storage
+                .read(object.key, Some((start, end)))
+                .await
+                .map_err(read_error(object.key))?;
+            let part_len = end - start + 1;
+            set_header(
+                &mut response_headers,
+                header::CONTENT_RANGE,
+                &format!("bytes {}-{}/{}", start, end, len),
+            );
+            set_header(
+                &mut response_headers,
+                header::CONTENT_LENGTH,
+                &part_len.to_string(),
+            );
+            let body = Body::from_stream(ReaderStream::new(reader));
+            Ok((StatusCode::PARTIAL_CONTENT, response_headers, body).into_response())
+        }
+        None => {
+            set_header(
+                &mut response_headers,
+                header::CONTENT_LENGTH,
+                &len.to_string(),
+            );
+            let reader = storage
+                .read(object.key, None)
+                .await
+                .map_err(read_error(object.key))?;
+            let body = Body::from_stream(ReaderStream::new(reader));
+            Ok((StatusCode::OK, response_headers, body).into_response())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETAG: &str = "\"abc\"";
+
+    fn last_modified() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    }
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(name.clone(), HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn ranges() {
+        use RangeRequest::{Satisfiable, Unsatisfiable};
+        let cases = [
+            ("bytes=0-499", 1000, Some(Satisfiable(0, 499))),
+            ("bytes=500-999", 1000, Some(Satisfiable(500, 999))),
+            (" bytes= 10 - 20 ", 1000, Some(Satisfiable(10, 20))),
+            // 结束位置超出长度时截断
+            ("bytes=900-2000", 1000, Some(Satisfiable(900, 999))),
+            // 开放区间
+            ("bytes=500-", 1000, Some(Satisfiable(500, 999))),
+            ("bytes=0-", 1, Some(Satisfiable(0, 0))),
+            // 后缀区间
+            ("bytes=-500", 1000, Some(Satisfiable(500, 999))),
+            ("bytes=-2000", 1000, Some(Satisfiable(0, 999))),
+            ("bytes=-0", 1000, Some(Unsatisfiable)),
+            ("bytes=-1", 0, Some(Unsatisfiable)),
+            // 起始位置超出长度
+            ("bytes=1000-", 1000, Some(Unsatisfiable)),
+            ("bytes=1000-1001", 1000, Some(Unsatisfiable)),
+            ("bytes=0-0", 0, Some(Unsatisfiable)),
+            // 多区间和格式错误时忽略
+            ("bytes=0-1,5-6", 1000, None),
+            ("bytes=5-1", 1000, None),
+            ("bytes=a-b", 1000, None),
+            ("bytes=-", 1000, None),
+            ("bytes=5", 1000, None),
+            ("items=0-1", 1000, None),
+        ];
+        for (value, len, expected) in cases {
+            assert_eq!(parse_range(value, len), expected, "{} / {}", value, len);
+        }
+    }
+
+    #[test]
+    fn not_modified() {
+        let date = httpdate::fmt_http_date(last_modified());
+        let earlier = httpdate::fmt_http_date(last_modified() - Duration::from_secs(1));
+        let later = httpdate::fmt_http_date(last_modified() + Duration::from_secs(1));
+        let cases = [
+            (vec![], false),
+            (vec![(header::IF_NONE_MATCH, ETAG)], true),
+            // If-None-Match 使用弱比较
+            (vec![(header::IF_NONE_MATCH, "W/\"abc\"")], true),
+            (vec![(header::IF_NONE_MATCH, "\"xyz\", W/\"abc\"")], true),
+            (vec![(header::IF_NONE_MATCH, "*")], true),
+            (vec![(header::IF_NONE_MATCH, "\"xyz\"")], false),
+            (vec![(header::IF_MODIFIED_SINCE, date.as_str())], true),
+            (vec![(header::IF_MODIFIED_SINCE, later.as_str())], true),
+            (vec![(header::IF_MODIFIED_SINCE, earlier.as_str())], false),
+            (vec![(header::IF_MODIFIED_SINCE, "yesterday")], false),
+            // 同时存在时忽略 If-Modified-Since
+            (
+                vec![
+                    (header::IF_NONE_MATCH, "\"xyz\""),
+                    (header::IF_MODIFIED_SINCE, later.as_str()),
+                ],
+                false,
+            ),
+        ];
+        for (pairs, expected) in cases {
+            assert_eq!(
+                is_not_modified(&headers(&pairs), ETAG, last_modified()),
+                expected,
+                "{:?}",
+                pairs
+            );
+        }
+    }
+
+    #[test]
+    fn range_allowed() {
+        let date = httpdate::fmt_http_date(last_modified());
+        let later = httpdate::fmt_http_date(last_modified() + Duration::from_secs(1));
+        let cases = [
+            (None, true),
+            (Some(ETAG), true),
+            (Some("\"xyz\""), false),
+            // If-Range 使用强比较
+            (Some("W/\"abc\""), false),
+            // 日期必须完全一致
+            (Some(date.as_str()), true),
+            (Some(later.as_str()), false),
+            (Some("not a date"), false),
+        ];
+        for (if_range, expected) in cases {
+            let headers = match if_range {
+                Some(v) => headers(&[(header::IF_RANGE, v)]),
+                None => HeaderMap::new(),
+            };
+            assert_eq!(
+                is_range_allowed(&headers, ETAG, last_modified()),
+                expected,
+                "{:?}",
+                if_range
+            );
+        }
+    }
+}
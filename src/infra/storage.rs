@@ -1,63 +1,240 @@
+pub mod local;
+pub mod memory;
+pub mod s3;
+
+use async_trait::async_trait;
 use bytes::Bytes;
-use std::{fs, io::Write, path::PathBuf};
+use sha2::{Digest, Sha256};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use thiserror::Error;
+use tokio::io::AsyncRead;
+
+use crate::config::StorageConfig;
+pub use local::LocalStorage;
+pub use memory::MemoryStorage;
+pub use s3::S3Storage;
 
 #[derive(Debug, Error)]
 pub enum StorageError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("Object not found: {0}")]
+    NotFound(String),
+    #[error("Object content does not match its hash: {0}")]
+    Corrupted(String),
     #[error("Storage generic error: {0}")]
     Other(String),
 }
+
+/// 被隔离的对象使用的 key 后缀
+pub const QUARANTINE_SUFFIX: &str = ".quarantined";
+
 #[derive(Debug, Clone)]
-pub struct LocalStorage {
-    base_path: PathBuf,
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: u64,
 }
 
-impl LocalStorage {
-    pub fn new(base_path: PathBuf) -> Self {
-        if !fs::exists(&base_path).expect("failed to check storage root existence") {
-            fs::create_dir_all(&base_path).expect("failed to create storage root");
-        }
-        Self { base_path }
+/// 流式读取对象内容
+pub type ObjectReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// 对象存储后端，key 为内容的 SHA256 或由其派生
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn save(&self, key: &str, data: Bytes) -> Result<(), StorageError>;
+
+    /// 从本地文件保存对象，默认实现会把文件整个读入内存，后端应尽量以流的方式实现
+    async fn save_file(&self, key: &str, path: &Path) -> Result<(), StorageError> {
+        let data = tokio::fs::read(path).await?;
+        self.save(key, Bytes::from(data)).await
     }
 
-    /// 策略：使用前 4 个字符做两级目录散列
-    /// Key: "e3b0c44298fc..." -> Path: "base/e3/b0/e3b0c44298fc..."
-    fn get_full_path(&self, key: &str) -> PathBuf {
-        if key.len() < 4 {
-            return self.base_path.join(key);
+    async fn get(&self, key: &str) -> Result<Bytes, StorageError>;
+
+    /// 读取对象并校验内容的 SHA256 是否与 key 一致，只适用于以内容哈希为 key 的对象
+    async fn get_verified(&self, key: &str) -> Result<Bytes, StorageError> {
+        let data = self.get(key).await?;
+        if !is_content_hash(key) || format!("{:x}", Sha256::digest(&data)) != key {
+            return Err(StorageError::Corrupted(key.to_string()));
         }
-        let p1 = &key[0..2];
-        let p2 = &key[2..4];
-        self.base_path.join(p1).join(p2).join(key)
+        Ok(data)
     }
 
-    pub fn save(&self, key: &str, data: Bytes) -> Result<(), StorageError> {
-        let path = self.get_full_path(key);
+    /// 读取对象的全部或闭区间 `[start, end]` 内的字节
+    async fn read(
+        &self,
+        key: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<ObjectReader, StorageError>;
 
-        // 确保父目录存在
-        if let Some(parent) = path.parent() {
-            if !parent.exists() {
-                fs::create_dir_all(parent)?;
-            }
-        }
+    /// 对象大小，对象不存在时返回 `StorageError::NotFound`
+    async fn size(&self, key: &str) -> Result<u64, StorageError>;
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError>;
+
+    /// 删除对象，对象不存在时视为成功
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    /// 列出存储中的全部对象
+    async fn list(&self) -> Result<Vec<ObjectInfo>, StorageError>;
+
+    /// 将损坏的对象移动到 `{key}.quarantined`，保留以便人工检查，原 key 随之失效
+    async fn quarantine(&self, key: &str) -> Result<(), StorageError> {
+        let data = self.get(key).await?;
+        self.save(&format!("{}{}", key, QUARANTINE_SUFFIX), data)
+            .await?;
+        self.delete(key).await
+    }
+}
+
+pub type SharedStorage = Arc<dyn Storage>;
+
+/// key 是否为 SHA256 HEX，派生对象（如缩略图）的 key 不是
+pub fn is_content_hash(key: &str) -> bool {
+    key.len() == 64 && key.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// 策略：使用前 4 个字符做两级目录散列
+/// Key: "e3b0c44298fc..." -> Path: "e3/b0/e3b0c44298fc..."
+pub fn object_path(key: &str) -> String {
+    if key.len() < 4 || !key.is_char_boundary(4) {
+        return key.to_string();
+    }
+    format!("{}/{}/{}", &key[0..2], &key[2..4], key)
+}
 
-        // 写入文件
-        let mut file = fs::File::create(&path)?;
-        file.write_all(&data)?;
+pub fn create_storage(config: &StorageConfig) -> SharedStorage {
+    match config {
+        StorageConfig::Local { dir } => Arc::new(LocalStorage::new(PathBuf::from(dir))),
+        StorageConfig::S3(s3_config) => Arc::new(S3Storage::new(s3_config.clone())),
+        StorageConfig::Memory => Arc::new(MemoryStorage::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    const KEY: &str = "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a";
+
+    async fn read_all(storage: &dyn Storage, key: &str, range: Option<(u64, u64)>) -> Vec<u8> {
+        let mut reader = storage.read(key, range).await.unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await.unwrap();
+        data
+    }
 
-        Ok(())
+    fn sorted_keys(objects: Vec<ObjectInfo>) -> Vec<(String, u64)> {
+        let mut keys: Vec<_> = objects.into_iter().map(|o| (o.key, o.size)).collect();
+        keys.sort();
+        keys
     }
 
-    pub fn get(&self, key: &str) -> Result<Bytes, StorageError> {
-        let path = self.get_full_path(key);
-        let data = fs::read(path)?;
-        Ok(Bytes::from(data))
+    /// 所有后端都应满足的行为
+    async fn contract(storage: &dyn Storage) {
+        let data = Bytes::from_static(b"0123456789");
+        let derived = format!("{}_256.jpeg", KEY);
+
+        assert!(!storage.exists(KEY).await.unwrap());
+        assert!(matches!(
+            storage.get(KEY).await,
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(matches!(
+            storage.size(KEY).await,
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(sorted_keys(storage.list().await.unwrap()).is_empty());
+
+        storage.save(KEY, data.clone()).await.unwrap();
+        storage
+            .save(&derived, Bytes::from_static(b"thumb"))
+            .await
+            .unwrap();
+        assert!(storage.exists(KEY).await.unwrap());
+        assert_eq!(storage.get(KEY).await.unwrap(), data);
+        assert_eq!(storage.size(KEY).await.unwrap(), 10);
+
+        // 覆盖写入
+        storage
+            .save(&derived, Bytes::from_static(b"thumbnail"))
+            .await
+            .unwrap();
+        assert_eq!(storage.get(&derived).await.unwrap(), "thumbnail");
+
+        assert_eq!(read_all(storage, KEY, None).await, b"0123456789");
+        assert_eq!(read_all(storage, KEY, Some((2, 4))).await, b"234");
+        assert_eq!(read_all(storage, KEY, Some((9, 9))).await, b"9");
+        assert_eq!(read_all(storage, KEY, Some((0, 9))).await, b"0123456789");
+
+        assert_eq!(
+            sorted_keys(storage.list().await.unwrap()),
+            [(KEY.to_string(), 10), (derived.clone(), 9)]
+        );
+
+        let quarantined = format!("{}{}", KEY, QUARANTINE_SUFFIX);
+        storage.quarantine(KEY).await.unwrap();
+        assert!(!storage.exists(KEY).await.unwrap());
+        assert_eq!(storage.get(&quarantined).await.unwrap(), data);
+        assert_eq!(
+            sorted_keys(storage.list().await.unwrap()),
+            [(quarantined.clone(), 10), (derived.clone(), 9)]
+        );
+
+        storage.delete(&derived).await.unwrap();
+        storage.delete(&quarantined).await.unwrap();
+        // 删除不存在的对象视为成功
+        storage.delete(KEY).await.unwrap();
+        assert!(sorted_keys(storage.list().await.unwrap()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn memory_storage() {
+        contract(&MemoryStorage::new()).await;
+    }
+
+    #[tokio::test]
+    async fn local_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        contract(&LocalStorage::new(dir.path().to_path_buf())).await;
+
+        // 从本地文件保存
+        let storage = LocalStorage::new(dir.path().to_path_buf());
+        let source = dir.path().join("source");
+        std::fs::write(&source, b"abc").unwrap();
+        storage.save_file(KEY, &source).await.unwrap();
+        assert_eq!(storage.get(KEY).await.unwrap(), "abc");
+    }
+
+    #[tokio::test]
+    async fn get_verified() {
+        let storage = MemoryStorage::new();
+        let key = format!("{:x}", Sha256::digest(b"abc"));
+        storage
+            .save(&key, Bytes::from_static(b"abc"))
+            .await
+            .unwrap();
+        assert_eq!(storage.get_verified(&key).await.unwrap(), "abc");
+
+        storage
+            .save(&key, Bytes::from_static(b"abd"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            storage.get_verified(&key).await,
+            Err(StorageError::Corrupted(_))
+        ));
     }
 
-    pub fn exists(&self, key: &str) -> Result<bool, StorageError> {
-        let path = self.get_full_path(key);
-        Ok(path.exists())
+    #[test]
+    fn object_paths() {
+        assert_eq!(object_path("e3b0c442"), "e3/b0/e3b0c442");
+        assert_eq!(object_path("abc"), "abc");
+        assert!(is_content_hash(KEY));
+        assert!(!is_content_hash(&format!("{}_256.jpeg", KEY)));
     }
 }
@@ -0,0 +1,194 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::{
+    fs,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use uuid::Uuid;
+
+use super::{ObjectInfo, ObjectReader, QUARANTINE_SUFFIX, Storage, StorageError, object_path};
+
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    base_path: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(base_path: PathBuf) -> Self {
+        if !fs::exists(&base_path).expect("failed to check storage root existence") {
+            fs::create_dir_all(&base_path).expect("failed to create storage root");
+        }
+        Self { base_path }
+    }
+
+    /// Key: "e3b0c44298fc..." -> Path: "base/e3/b0/e3b0c44298fc..."
+    fn get_full_path(&self, key: &str) -> PathBuf {
+        self.base_path.join(object_path(key))
+    }
+
+    /// 由 `write` 写入同目录下的临时文件并 fsync，再原子地重命名到目标路径
+    async fn write_atomic<F, Fut>(&self, key: &str, write: F) -> Result<(), StorageError>
+    where
+        F: FnOnce(PathBuf) -> Fut,
+        Fut: Future<Output = std::io::Result<()>>,
+    {
+        let path = self.get_full_path(key);
+        let parent = path
+            .parent()
+            .ok_or_else(|| StorageError::Other(format!("Invalid key: {}", key)))?;
+
+        // 确保父目录存在
+        tokio::fs::create_dir_all(parent).await?;
+
+        let tmp_path = parent.join(format!(".{}.{}.tmp", key, Uuid::now_v7()));
+        let result = async {
+            write(tmp_path.clone()).await?;
+            tokio::fs::rename(&tmp_path, &path).await
+        }
+        .await;
+
+        if let Err(e) = result {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return Err(e.into());
+        }
+
+        // 持久化目录项，保证重命名在断电后依然有效
+        #[cfg(unix)]
+        tokio::fs::File::open(parent).await?.sync_all().await?;
+
+        Ok(())
+    }
+}
+
+/// 递归遍历存储目录，跳过以 `.` 开头的临时文件
+fn walk(dir: &Path, objects: &mut Vec<ObjectInfo>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk(&entry.path(), objects)?;
+        } else if file_type.is_file() {
+            let key = entry.file_name().to_string_lossy().to_string();
+            if key.starts_with('.') {
+                continue;
+            }
+            objects.push(ObjectInfo {
+                key,
+                size: entry.metadata()?.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn not_found(key: &str, e: std::io::Error) -> StorageError {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        StorageError::NotFound(key.to_string())
+    } else {
+        e.into()
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    /// 先写入同目录下的临时文件并 fsync，再原子地重命名到目标路径，
+    /// 崩溃或并发写入同一个 key 时不会留下被截断的文件
+    async fn save(&self, key: &str, data: Bytes) -> Result<(), StorageError> {
+        self.write_atomic(key, |tmp_path| async move {
+            let mut file = tokio::fs::File::create(&tmp_path).await?;
+            file.write_all(&data).await?;
+            file.sync_all().await
+        })
+        .await
+    }
+
+    async fn save_file(&self, key: &str, path: &Path) -> Result<(), StorageError> {
+        self.write_atomic(key, |tmp_path| async move {
+            // 不使用 fs::copy，避免继承上传临时文件的 0600 权限
+            let mut source = tokio::fs::File::open(path).await?;
+            let mut file = tokio::fs::File::create(&tmp_path).await?;
+            tokio::io::copy(&mut source, &mut file).await?;
+            file.sync_all().await
+        })
+        .await
+    }
+
+    async fn get(&self, key: &str) -> Result<Bytes, StorageError> {
+        let path = self.get_full_path(key);
+        let data = tokio::fs::read(path).await.map_err(|e| not_found(key, e))?;
+        Ok(Bytes::from(data))
+    }
+
+    async fn read(
+        &self,
+        key: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<ObjectReader, StorageError> {
+        let path = self.get_full_path(key);
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| not_found(key, e))?;
+        match range {
+            Some((start, end)) => {
+                file.seek(SeekFrom::Start(start)).await?;
+                Ok(Box::new(file.take(end - start + 1)))
+            }
+            None => Ok(Box::new(file)),
+        }
+    }
+
+    async fn size(&self, key: &str) -> Result<u64, StorageError> {
+        let path = self.get_full_path(key);
+        let metadata = tokio::fs::metadata(path)
+            .await
+            .map_err(|e| not_found(key, e))?;
+        Ok(metadata.len())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        let path = self.get_full_path(key);
+        Ok(tokio::fs::try_exists(path).await?)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let path = self.get_full_path(key);
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+
+        // 清理空的散列目录，目录非空时 remove_dir 会失败，忽略即可
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if d == self.base_path || tokio::fs::remove_dir(d).await.is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<ObjectInfo>, StorageError> {
+        let base_path = self.base_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut objects = Vec::new();
+            walk(&base_path, &mut objects)?;
+            Ok(objects)
+        })
+        .await
+        .map_err(|e| StorageError::Other(e.to_string()))?
+    }
+
+    async fn quarantine(&self, key: &str) -> Result<(), StorageError> {
+        let path = self.get_full_path(key);
+        let quarantined = self.get_full_path(&format!("{}{}", key, QUARANTINE_SUFFIX));
+        tokio::fs::rename(&path, &quarantined)
+            .await
+            .map_err(|e| not_found(key, e))?;
+        Ok(())
+    }
+}
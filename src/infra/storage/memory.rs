@@ -0,0 +1,94 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    sync::{Arc, RwLock},
+};
+
+use super::{ObjectInfo, ObjectReader, Storage, StorageError};
+
+/// 进程内存储，重启后数据丢失，用于开发和测试
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    objects: Arc<RwLock<HashMap<String, Bytes>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lookup(&self, key: &str) -> Result<Bytes, StorageError> {
+        self.objects
+            .read()
+            .map_err(|_| StorageError::Other("memory storage lock poisoned".to_string()))?
+            .get(key)
+            .cloned()
+            .ok_or_else(|| StorageError::NotFound(key.to_string()))
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn save(&self, key: &str, data: Bytes) -> Result<(), StorageError> {
+        self.objects
+            .write()
+            .map_err(|_| StorageError::Other("memory storage lock poisoned".to_string()))?
+            .insert(key.to_string(), data);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Bytes, StorageError> {
+        self.lookup(key)
+    }
+
+    async fn read(
+        &self,
+        key: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<ObjectReader, StorageError> {
+        let data = self.lookup(key)?;
+        let data = match range {
+            Some((start, end)) => {
+                let end = (end as usize + 1).min(data.len());
+                data.slice((start as usize).min(end)..end)
+            }
+            None => data,
+        };
+        Ok(Box::new(Cursor::new(data)))
+    }
+
+    async fn size(&self, key: &str) -> Result<u64, StorageError> {
+        Ok(self.lookup(key)?.len() as u64)
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        match self.lookup(key) {
+            Ok(_) => Ok(true),
+            Err(StorageError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.objects
+            .write()
+            .map_err(|_| StorageError::Other("memory storage lock poisoned".to_string()))?
+            .remove(key);
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<ObjectInfo>, StorageError> {
+        Ok(self
+            .objects
+            .read()
+            .map_err(|_| StorageError::Other("memory storage lock poisoned".to_string()))?
+            .iter()
+            .map(|(key, data)| ObjectInfo {
+                key: key.clone(),
+                size: data.len() as u64,
+            })
+            .collect())
+    }
+}
@@ -1,9 +1,17 @@
 pub mod ai;
+pub mod albums;
 pub mod auth;
+pub mod capture_time;
 pub mod config;
 pub mod exif;
 pub mod images;
 pub mod infra;
 pub mod photos;
+pub mod renditions;
+pub mod search;
+pub mod smart_albums;
 pub mod tags;
+pub mod timeline;
+pub mod trash;
+pub mod uploads;
 pub mod users;
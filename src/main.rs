@@ -1,7 +1,7 @@
 use moments_aura::{
-    ai, auth, config,
-    infra::{self, storage::LocalStorage},
-    photos, tags, users,
+    ai, albums, auth, config, images,
+    infra::{self, storage::SharedStorage},
+    photos, smart_albums, tags, timeline, trash, uploads, users,
 };
 use reverse_geocoder::ReverseGeocoder;
 use std::{path::Path, sync::Arc};
@@ -14,19 +14,20 @@ use axum::{
     routing,
 };
 use sqlx::PgPool;
-use std::{fs, path::PathBuf};
 
 #[derive(Clone)]
 pub struct AppState {
-    storage: LocalStorage,
+    storage: SharedStorage,
     db: PgPool,
     jwt_service: auth::JwtService,
     geocoder: Arc<ReverseGeocoder>,
     ai_service: Option<Arc<ai::AiService>>,
+    rendition_config: Arc<config::RenditionConfig>,
+    upload_config: Arc<config::UploadConfig>,
 }
 
-impl FromRef<AppState> for LocalStorage {
-    fn from_ref(state: &AppState) -> LocalStorage {
+impl FromRef<AppState> for SharedStorage {
+    fn from_ref(state: &AppState) -> SharedStorage {
         state.storage.clone()
     }
 }
@@ -55,6 +56,18 @@ impl FromRef<AppState> for Arc<ai::AiService> {
     }
 }
 
+impl FromRef<AppState> for Arc<config::RenditionConfig> {
+    fn from_ref(state: &AppState) -> Arc<config::RenditionConfig> {
+        state.rendition_config.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<config::UploadConfig> {
+    fn from_ref(state: &AppState) -> Arc<config::UploadConfig> {
+        state.upload_config.clone()
+    }
+}
+
 async fn server_info_handler(State(state): State<AppState>) -> axum::Json<serde_json::Value> {
     let mut features = vec![];
     if state.ai_service.is_some() {
@@ -68,12 +81,34 @@ async fn server_info_handler(State(state): State<AppState>) -> axum::Json<serde_
 fn create_router(app_state: AppState) -> Router {
     let mut router = Router::new()
         .route("/server-info", routing::get(server_info_handler))
-        .route("/photos/upload", routing::post(photos::upload_handler))
+        .route(
+            "/photos/upload",
+            routing::post(photos::upload_handler)
+                .layer(DefaultBodyLimit::max(photos::MAX_UPLOAD_BODY_SIZE)),
+        )
         .route("/photos/list", routing::get(photos::list_handler))
+        .route("/photos/timeline", routing::get(timeline::buckets_handler))
+        .route("/photos/search", routing::get(photos::search_handler))
+        .route(
+            "/photos/{photo_id}",
+            routing::get(photos::get_photo_handler).patch(photos::update_photo_handler),
+        )
         .route("/tags/list", routing::get(tags::list_tags_handler))
         .route(
             "/photos/{photo_id}/content",
             routing::get(photos::get_content_handler),
+        )
+        .route(
+            "/photos/{photo_id}/rotation",
+            routing::put(photos::set_rotation_handler),
+        )
+        .route(
+            "/photos/{photo_id}/live-video",
+            routing::get(photos::get_live_video_handler),
+        )
+        .route(
+            "/photos/{photo_id}/thumbnail",
+            routing::get(photos::get_thumbnail_handler),
         );
 
     if app_state.ai_service.is_some() {
@@ -88,6 +123,26 @@ fn create_router(app_state: AppState) -> Router {
             "/photos/delete-batch",
             routing::post(photos::delete_batch_handler),
         )
+        .route(
+            "/photos/trash/list",
+            routing::get(photos::list_trash_handler),
+        )
+        .route(
+            "/photos/restore-batch",
+            routing::post(photos::restore_batch_handler),
+        )
+        .route(
+            "/photos/trash/empty",
+            routing::post(photos::empty_trash_handler),
+        )
+        .route(
+            "/photos/favorite-batch",
+            routing::post(photos::favorite_batch_handler),
+        )
+        .route(
+            "/photos/unfavorite-batch",
+            routing::post(photos::unfavorite_batch_handler),
+        )
         .route(
             "/tags/add-batch",
             routing::post(photos::add_tags_batch_handler),
@@ -96,8 +151,69 @@ fn create_router(app_state: AppState) -> Router {
             "/tags/delete-batch",
             routing::post(photos::delete_tags_batch_handler),
         )
+        .route(
+            "/albums",
+            routing::get(albums::list_albums_handler).post(albums::create_album_handler),
+        )
+        .route(
+            "/albums/{album_id}",
+            routing::get(albums::get_album_handler)
+                .patch(albums::update_album_handler)
+                .delete(albums::delete_album_handler),
+        )
+        .route(
+            "/albums/{album_id}/photos",
+            routing::get(photos::list_album_photos_handler),
+        )
+        .route(
+            "/albums/{album_id}/add-batch",
+            routing::post(albums::add_photos_batch_handler),
+        )
+        .route(
+            "/albums/{album_id}/remove-batch",
+            routing::post(albums::remove_photos_batch_handler),
+        )
+        .route(
+            "/albums/{album_id}/order",
+            routing::put(albums::reorder_photos_handler),
+        )
+        .route(
+            "/albums/{album_id}/cover",
+            routing::put(albums::set_cover_handler),
+        )
+        .route(
+            "/smart-albums",
+            routing::get(smart_albums::list_smart_albums_handler)
+                .post(smart_albums::create_smart_album_handler),
+        )
+        .route(
+            "/smart-albums/{smart_album_id}",
+            routing::get(smart_albums::get_smart_album_handler)
+                .patch(smart_albums::update_smart_album_handler)
+                .delete(smart_albums::delete_smart_album_handler),
+        )
+        .route(
+            "/smart-albums/{smart_album_id}/photos",
+            routing::get(photos::list_smart_album_photos_handler),
+        )
+        .route("/uploads", routing::post(uploads::create_upload_handler))
+        .route(
+            "/uploads/{upload_id}",
+            routing::get(uploads::get_upload_handler).delete(uploads::delete_upload_handler),
+        )
+        .route(
+            "/uploads/{upload_id}/chunks/{index}",
+            routing::put(uploads::put_chunk_handler),
+        )
+        .route(
+            "/uploads/{upload_id}/complete",
+            routing::post(uploads::complete_upload_handler),
+        )
         .route("/auth/register", routing::post(auth::register_handler))
         .route("/auth/login", routing::post(auth::login_handler))
+        .route("/auth/refresh", routing::post(auth::refresh_handler))
+        .route("/auth/logout", routing::post(auth::logout_handler))
+        .route("/auth/logout-all", routing::post(auth::logout_all_handler))
         .route("/users/me", routing::get(users::get_own_profile_handler))
         .route_layer(DefaultBodyLimit::max(100 * 1024 * 1024)) // 100MB
         .with_state(app_state)
@@ -119,25 +235,65 @@ async fn main() {
     // app
     let config_path = Path::new("config.toml");
     let app_config = config::AppConfig::new(config_path);
-    let listener = tokio::net::TcpListener::bind(&app_config.address)
-        .await
-        .expect(&format!("Failed to bind address: {}", app_config.address));
 
-    let storage_dir = PathBuf::from(&app_config.storage_dir);
-    fs::create_dir_all(&storage_dir).expect(&format!(
-        "Failed to create store directory: {}",
-        storage_dir.display()
-    ));
-    let storage = LocalStorage::new(storage_dir);
+    let storage = infra::storage::create_storage(&app_config.storage);
 
     let db = infra::db::create_pool(&app_config.database_url)
         .await
-        .expect(&format!(
-            "Failed to create database pool: {}",
-            app_config.database_url
-        ));
+        .unwrap_or_else(|_| {
+            panic!(
+                "Failed to create database pool: {}",
+                app_config.database_url
+            )
+        });
+
+    // 子命令：moments-aura gc [--dry-run] | scrub [--dry-run]
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(command) = args.first() {
+        match command.as_str() {
+            "gc" => {
+                let dry_run = args.iter().any(|a| a == "--dry-run");
+                let report = images::gc::collect_garbage(
+                    &db,
+                    storage.as_ref(),
+                    app_config.gc.grace_period_minutes,
+                    dry_run,
+                )
+                .await
+                .expect("Failed to collect orphaned images");
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).expect("Failed to serialize report")
+                );
+            }
+            "scrub" => {
+                let dry_run = args.iter().any(|a| a == "--dry-run");
+                let report = images::scrub::scrub_storage(&db, storage.as_ref(), dry_run)
+                    .await
+                    .expect("Failed to scrub storage");
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).expect("Failed to serialize report")
+                );
+            }
+            _ => {
+                eprintln!("Unknown command: {}", command);
+                eprintln!("Usage: moments-aura [gc [--dry-run] | scrub [--dry-run]]");
+                std::process::exit(2);
+            }
+        }
+        db.close().await;
+        return;
+    }
+
+    let listener = tokio::net::TcpListener::bind(&app_config.address)
+        .await
+        .unwrap_or_else(|_| panic!("Failed to bind address: {}", app_config.address));
 
     let jwt_service = auth::JwtService::new(app_config.jwt_secret.as_bytes());
+    auth::password::init_dummy_hash()
+        .await
+        .expect("Failed to initialize dummy password hash");
 
     let ai_service = if app_config.ai.enable {
         Some(Arc::new(ai::AiService::new(app_config.ai)))
@@ -145,12 +301,19 @@ async fn main() {
         None
     };
 
+    trash::spawn_purge_task(db.clone(), app_config.trash);
+    images::gc::spawn_gc_task(db.clone(), storage.clone(), app_config.gc);
+    images::backfill::spawn_backfill_task(db.clone(), storage.clone());
+    uploads::spawn_expire_task(db.clone(), storage.clone());
+
     let router = create_router(AppState {
         storage,
         db: db.clone(),
         jwt_service,
         geocoder: Arc::new(ReverseGeocoder::new()),
         ai_service,
+        rendition_config: Arc::new(app_config.renditions),
+        upload_config: Arc::new(app_config.uploads),
     });
 
     tracing::info!("Running server on {}", &app_config.address);
@@ -1,7 +1,7 @@
 use axum::{
     Json,
     extract::{Multipart, Path, Query, State},
-    http::{StatusCode, header},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
 use reverse_geocoder::ReverseGeocoder;
@@ -10,30 +10,91 @@ use serde_json::json;
 use sqlx::PgPool;
 use std::sync::Arc;
 use uuid::Uuid;
+use validator::Validate;
 
-use crate::{ai, auth::AuthUser, exif::parse_exif, images, infra::storage::LocalStorage};
+use crate::{
+    ai,
+    albums::{self, PositionCursor},
+    auth::AuthUser,
+    capture_time,
+    config::RenditionConfig,
+    exif::{PhotoMetadata, parse_exif},
+    images::{self, format::MediaFormat},
+    infra::{
+        content::{StoredObject, serve_object},
+        storage::{SharedStorage, Storage, StorageError},
+    },
+    renditions::{self, Rotation},
+    search, smart_albums,
+    tags::parse_tag_list,
+    timeline::{Cursor, invalid_cursor},
+};
 
 const MAX_UPLOAD_FILES: usize = 16;
+/// 时间线每页的默认数量和上限
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 500;
+const CAPTURED_AT_FORMAT: &[time::format_description::BorrowedFormatItem<'static>] = time::macros::format_description!(
+    "[year]-[month]-[day]T[hour]:[minute]:[second][offset_hour sign:mandatory]:[offset_minute]"
+);
+pub const MAX_UPLOAD_FILE_SIZE: u64 = 100 * 1024 * 1024; // 100MB
+/// 上传的文件会流式写入临时文件，请求体可以远大于其他接口的限制
+pub const MAX_UPLOAD_BODY_SIZE: usize = 1024 * 1024 * 1024; // 1GB
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UploadStatus {
+    Created,
+    /// 用户已有内容相同的照片，不会重复创建
+    Duplicate,
+    /// 内容相同的照片在回收站中，不会自动恢复，需通过回收站接口恢复
+    InTrash,
+    /// Live Photo 的图片或视频，与已有的另一半合并为同一张照片
+    Linked,
+    RejectedFormat,
+    TooLarge,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadResult {
+    file_name: Option<String>,
+    status: UploadStatus,
+    photo_id: Option<String>,
+    image_hash: Option<String>,
+    reason: Option<String>,
+}
+
+impl UploadResult {
+    fn rejected(file_name: Option<String>, status: UploadStatus, reason: String) -> Self {
+        Self {
+            file_name,
+            status,
+            photo_id: None,
+            image_hash: None,
+            reason: Some(reason),
+        }
+    }
+}
+
 pub async fn upload_handler(
-    State(storage): State<LocalStorage>,
+    State(storage): State<SharedStorage>,
     State(db): State<PgPool>,
     State(geocoder): State<Arc<ReverseGeocoder>>,
-    AuthUser { user_id }: AuthUser,
+    AuthUser { user_id, .. }: AuthUser,
     mut multipart: Multipart,
 ) -> Result<Response, (StatusCode, String)> {
-    let mut uploaded_count = 0;
+    let mut results = vec![];
     while let Some(field) = multipart.next_field().await.map_err(|_| {
         (
             StatusCode::BAD_REQUEST,
             "Failed to parse multipart".to_string(),
         )
     })? {
-        match (field.name(), field.content_type()) {
-            (Some("file"), Some("image/jpeg" | "image/png" | "image/gif" | "image/webp")) => (),
-            _ => continue,
-        };
+        if field.name() != Some("file") {
+            continue;
+        }
 
-        if uploaded_count >= MAX_UPLOAD_FILES {
+        if results.len() >= MAX_UPLOAD_FILES {
             return Err((
                 StatusCode::BAD_REQUEST,
                 format!(
@@ -43,58 +104,348 @@ pub async fn upload_handler(
             ));
         }
 
-        let bytes = field.bytes().await.map_err(|e| {
-            tracing::error!(error = ?e, "Failed to read bytes from field");
-            (StatusCode::BAD_REQUEST, "Internal server error".to_string())
-        })?;
+        // 格式在暂存后按文件内容识别，不信任客户端提供的 content type
+        let file_name = field.file_name().map(str::to_string);
 
-        let info = images::save_image(bytes, &storage, &db).await?;
+        let file = match images::StagedFile::from_stream(field, MAX_UPLOAD_FILE_SIZE).await {
+            Ok(file) => file,
+            Err(e @ images::StageError::TooLarge(_)) => {
+                results.push(UploadResult::rejected(
+                    file_name,
+                    UploadStatus::TooLarge,
+                    e.to_string(),
+                ));
+                continue;
+            }
+            Err(e) => {
+                tracing::error!(error = ?e, "Failed to stage uploaded file");
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal server error".to_string(),
+                ));
+            }
+        };
 
-        let photo_id = uuid::Uuid::now_v7();
+        results
+            .push(create_photo(&file, file_name, user_id, storage.as_ref(), &db, &geocoder).await?);
+    }
 
-        let uploaded_at = time::OffsetDateTime::now_utc();
-        let mut captured_at = None;
-        let mut latitude = None;
-        let mut longitude = None;
-        let mut location = None;
+    let uploaded_count = results
+        .iter()
+        .filter(|r| matches!(r.status, UploadStatus::Created | UploadStatus::Linked))
+        .count();
+    Ok(Json(json!({
+        "uploaded_count": uploaded_count,
+        "results": results,
+    }))
+    .into_response())
+}
 
-        if let Some(exif) = &info.exif {
-            let parsed_exif = parse_exif(exif);
-            captured_at = parsed_exif.date_time;
-            if let Some(coord) = parsed_exif.coordinates {
-                latitude = Some(coord.0);
-                longitude = Some(coord.1);
-                location = Some(format!("{}", geocoder.search(coord).record));
-            }
-        }
+struct DuplicatePhoto {
+    id: Uuid,
+    in_trash: bool,
+}
 
-        sqlx::query!(
-            "INSERT INTO photo (id, user_id, image_hash, uploaded_at, captured_at, latitude, longitude, location) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
-            photo_id,
-            user_id,
-            info.hash,
-            uploaded_at,
-            captured_at,
-            latitude,
-            longitude,
-            location
+/// 查找用户已有的、内容相同的照片，优先返回不在回收站中的
+async fn find_duplicate_photo(
+    conn: &mut sqlx::PgConnection,
+    user_id: Uuid,
+    image_hash: &str,
+) -> Result<Option<DuplicatePhoto>, sqlx::Error> {
+    sqlx::query_as!(
+        DuplicatePhoto,
+        r#"
+        SELECT "id", "deleted_at" IS NOT NULL AS "in_trash!"
+        FROM "photo"
+        WHERE "user_id" = $1 AND ("image_hash" = $2 OR "live_video_hash" = $2)
+        ORDER BY "deleted_at" NULLS FIRST, "uploaded_at"
+        LIMIT 1
+        "#,
+        user_id,
+        image_hash
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// 写入照片的拍摄参数，已存在时覆盖
+async fn save_photo_metadata(
+    conn: &mut sqlx::PgConnection,
+    photo_id: Uuid,
+    metadata: &PhotoMetadata,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO "photo_metadata" (
+            "photo_id", "camera_make", "camera_model", "lens_make", "lens_model",
+            "focal_length", "focal_length_35mm", "aperture", "exposure_time",
+            "iso", "orientation", "flash", "offset_time", "gps_timestamp"
         )
-        .execute(&db)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        ON CONFLICT ("photo_id") DO UPDATE SET
+            "camera_make" = EXCLUDED."camera_make",
+            "camera_model" = EXCLUDED."camera_model",
+            "lens_make" = EXCLUDED."lens_make",
+            "lens_model" = EXCLUDED."lens_model",
+            "focal_length" = EXCLUDED."focal_length",
+            "focal_length_35mm" = EXCLUDED."focal_length_35mm",
+            "aperture" = EXCLUDED."aperture",
+            "exposure_time" = EXCLUDED."exposure_time",
+            "iso" = EXCLUDED."iso",
+            "orientation" = EXCLUDED."orientation",
+            "flash" = EXCLUDED."flash",
+            "offset_time" = EXCLUDED."offset_time",
+            "gps_timestamp" = EXCLUDED."gps_timestamp"
+        "#,
+        photo_id,
+        metadata.camera_make,
+        metadata.camera_model,
+        metadata.lens_make,
+        metadata.lens_model,
+        metadata.focal_length,
+        metadata.focal_length_35mm,
+        metadata.aperture,
+        metadata.exposure_time,
+        metadata.iso,
+        metadata.orientation,
+        metadata.flash,
+        metadata.offset_time,
+        metadata.gps_timestamp
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// 为暂存的文件创建照片，用户已有相同内容的照片时返回 `Duplicate` 或 `InTrash`
+pub async fn create_photo(
+    file: &images::StagedFile,
+    file_name: Option<String>,
+    user_id: Uuid,
+    storage: &dyn Storage,
+    db: &PgPool,
+    geocoder: &ReverseGeocoder,
+) -> Result<UploadResult, (StatusCode, String)> {
+    let db_error = |e: sqlx::Error| {
+        tracing::error!(error = ?e, "Failed to insert image");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_string(),
+        )
+    };
+    let duplicate = |photo: DuplicatePhoto, file_name: Option<String>| {
+        let (status, reason) = if photo.in_trash {
+            (UploadStatus::InTrash, "Photo already exists in trash")
+        } else {
+            (UploadStatus::Duplicate, "Photo already exists")
+        };
+        UploadResult {
+            file_name,
+            status,
+            photo_id: Some(photo.id.to_string()),
+            image_hash: Some(file.hash.clone()),
+            reason: Some(reason.to_string()),
+        }
+    };
+
+    // 重复的照片不需要再写入存储
+    let mut conn = db.acquire().await.map_err(db_error)?;
+    if let Some(photo) = find_duplicate_photo(&mut conn, user_id, &file.hash)
         .await
-        .map_err(|e| {
-            tracing::error!(error = ?e, "Failed to insert image");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Internal server error".to_string(),
+        .map_err(db_error)?
+    {
+        return Ok(duplicate(photo, file_name));
+    }
+    drop(conn);
+
+    let info = match images::save_image(file, storage, db).await {
+        Ok(info) => info,
+        Err((StatusCode::BAD_REQUEST, reason)) => {
+            return Ok(UploadResult::rejected(
+                file_name,
+                UploadStatus::RejectedFormat,
+                reason,
+            ));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let photo_id = uuid::Uuid::now_v7();
+
+    let uploaded_at = time::OffsetDateTime::now_utc();
+    let mut captured_at = None;
+    let mut latitude = None;
+    let mut longitude = None;
+    let mut location = None;
+    let mut altitude = None;
+    let mut metadata = None;
+    let mut coordinates = None;
+
+    if let Some(exif) = &info.exif {
+        let parsed_exif = parse_exif(exif);
+        coordinates = parsed_exif.coordinates;
+        altitude = parsed_exif.altitude;
+        captured_at = parsed_exif.date_time.map(|local| {
+            capture_time::from_local(
+                local,
+                parsed_exif.offset,
+                parsed_exif.metadata.gps_timestamp,
+                coordinates,
             )
-        })?;
+        });
+        metadata = Some(parsed_exif.metadata);
+    }
+    if let Some(video) = &info.video {
+        coordinates = video.coordinates;
+        captured_at = match (video.created_at, video.created_at_utc) {
+            (Some(local), _) => Some(capture_time::from_local(
+                local,
+                video.created_offset,
+                None,
+                coordinates,
+            )),
+            (None, Some(at)) => Some(capture_time::from_utc(at, coordinates)),
+            (None, None) => None,
+        };
+    }
+    if let Some(coord) = coordinates {
+        latitude = Some(coord.0);
+        longitude = Some(coord.1);
+        location = Some(format!("{}", geocoder.search(coord).record));
+    }
 
-        uploaded_count += 1;
+    // 加锁后再次检查，避免同一用户并发上传同一文件时创建两条记录
+    let mut tx = db.begin().await.map_err(db_error)?;
+    images::lock_image_hash(&mut tx, &info.hash)
+        .await
+        .map_err(db_error)?;
+    if let Some(photo) = find_duplicate_photo(&mut tx, user_id, &info.hash)
+        .await
+        .map_err(db_error)?
+    {
+        tx.commit().await.map_err(db_error)?;
+        return Ok(duplicate(photo, file_name));
     }
-    Ok(Json(json!({
-        "uploaded_count": uploaded_count,
-    }))
-    .into_response())
+
+    // Live Photo 的图片和视频分别上传，先到的创建照片，后到的合并进去。
+    // 对配对标识加锁，避免两者同时上传时各自创建一张照片
+    if let Some(content_identifier) = &info.content_identifier {
+        images::lock_image_hash(&mut tx, content_identifier)
+            .await
+            .map_err(db_error)?;
+        let linked = if info.video.is_some() {
+            sqlx::query_scalar!(
+                r#"
+                UPDATE "photo" SET "live_video_hash" = $3
+                WHERE "id" = (
+                    SELECT "photo"."id" FROM "photo"
+                    JOIN "image" ON "photo"."image_hash" = "image"."hash"
+                    WHERE "photo"."user_id" = $1 AND "photo"."content_identifier" = $2
+                    AND "photo"."live_video_hash" IS NULL AND "image"."duration_ms" IS NULL
+                    AND "photo"."deleted_at" IS NULL
+                    ORDER BY "photo"."uploaded_at"
+                    LIMIT 1
+                )
+                RETURNING "id"
+                "#,
+                user_id,
+                content_identifier,
+                info.hash
+            )
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?
+        } else {
+            // 视频先上传时照片以视频为主体，图片到达后换成图片，视频移到 live_video_hash
+            sqlx::query_scalar!(
+                r#"
+                UPDATE "photo" SET
+                    "image_hash" = $3,
+                    "live_video_hash" = "photo"."image_hash",
+                    "captured_at" = COALESCE($4, "photo"."captured_at"),
+                    "captured_at_offset" = CASE WHEN $4::TIMESTAMPTZ IS NULL
+                        THEN "photo"."captured_at_offset" ELSE $9 END,
+                    "captured_at_offset_source" = CASE WHEN $4::TIMESTAMPTZ IS NULL
+                        THEN "photo"."captured_at_offset_source" ELSE $10 END,
+                    "latitude" = COALESCE($5, "photo"."latitude"),
+                    "longitude" = COALESCE($6, "photo"."longitude"),
+                    "location" = COALESCE($7, "photo"."location"),
+                    "altitude" = COALESCE($8, "photo"."altitude")
+                WHERE "id" = (
+                    SELECT "photo"."id" FROM "photo"
+                    JOIN "image" ON "photo"."image_hash" = "image"."hash"
+                    WHERE "photo"."user_id" = $1 AND "photo"."content_identifier" = $2
+                    AND "photo"."live_video_hash" IS NULL AND "image"."duration_ms" IS NOT NULL
+                    AND "photo"."deleted_at" IS NULL
+                    ORDER BY "photo"."uploaded_at"
+                    LIMIT 1
+                )
+                RETURNING "id"
+                "#,
+                user_id,
+                content_identifier,
+                info.hash,
+                captured_at.map(|c| c.at),
+                latitude,
+                longitude,
+                location,
+                altitude,
+                captured_at.and_then(|c| c.offset_seconds()),
+                captured_at.and_then(|c| c.offset_source())
+            )
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?
+        };
+        if let Some(photo_id) = linked {
+            if let Some(metadata) = &metadata {
+                save_photo_metadata(&mut tx, photo_id, metadata)
+                    .await
+                    .map_err(db_error)?;
+            }
+            tx.commit().await.map_err(db_error)?;
+            return Ok(UploadResult {
+                file_name,
+                status: UploadStatus::Linked,
+                photo_id: Some(photo_id.to_string()),
+                image_hash: Some(info.hash),
+                reason: None,
+            });
+        }
+    }
+
+    sqlx::query!(
+        "INSERT INTO photo (id, user_id, image_hash, uploaded_at, captured_at, captured_at_offset, captured_at_offset_source, latitude, longitude, altitude, location, content_identifier) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+        photo_id,
+        user_id,
+        info.hash,
+        uploaded_at,
+        captured_at.map(|c| c.at),
+        captured_at.and_then(|c| c.offset_seconds()),
+        captured_at.and_then(|c| c.offset_source()),
+        latitude,
+        longitude,
+        altitude,
+        location,
+        info.content_identifier
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    if let Some(metadata) = &metadata {
+        save_photo_metadata(&mut tx, photo_id, metadata)
+            .await
+            .map_err(db_error)?;
+    }
+    tx.commit().await.map_err(db_error)?;
+
+    Ok(UploadResult {
+        file_name,
+        status: UploadStatus::Created,
+        photo_id: Some(photo_id.to_string()),
+        image_hash: Some(info.hash),
+        reason: None,
+    })
 }
 
 #[derive(Debug, Serialize)]
@@ -103,104 +454,621 @@ struct Photo {
     image_hash: String,
     width: i32,
     height: i32,
+    /// 仅视频有值
+    duration_ms: Option<i64>,
+    /// 是否为带有配对视频的 Live Photo
+    live_photo: bool,
+    /// 用户设置的顺时针旋转角度，`width` / `height` 已按旋转交换
+    rotation: i16,
+    is_favorite: bool,
     uploaded_at: i64,
+    /// 拍摄时刻的 Unix 时间戳
+    captured_at: Option<i64>,
+    /// 拍摄地相对 UTC 的偏移，单位秒，加到 `captured_at` 上得到当地时间；为空时时区未知
+    captured_at_offset: Option<i32>,
     tags: Vec<String>,
 }
 
 #[derive(Deserialize)]
 pub struct ListParams {
+    /// 搜索查询，语法见 `search` 模块
+    q: Option<String>,
+    /// 任一标签，兼容旧版参数，与 `q` 同时使用时取交集
     tags: Option<String>,
     untagged: Option<bool>,
+    /// `true` 只列出收藏的照片，`false` 只列出未收藏的
+    favorite: Option<bool>,
+    /// 上一页返回的 `next_cursor`
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+impl ListParams {
+    fn filter(&self) -> Result<Option<search::Expr>, search::ParseError> {
+        search::build_filter(
+            self.q.as_deref(),
+            parse_tag_list(self.tags.clone()),
+            self.untagged.unwrap_or(false),
+            self.favorite,
+        )
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
 }
 
 #[derive(Debug, Serialize)]
 pub struct ListImagesResponse {
     photos: Vec<Photo>,
+    /// 没有更多照片时为空
+    next_cursor: Option<String>,
+}
+
+/// 查询 `PhotoRow` 的列，需要 JOIN `image`
+const PHOTO_ROW_COLUMNS: &str = r#"
+        SELECT
+            "photo"."id",
+            "photo"."image_hash",
+            "photo"."uploaded_at",
+            "photo"."timeline_at",
+            "photo"."captured_at",
+            "photo"."captured_at_offset",
+            CASE WHEN "photo"."rotation" IN (90, 270) THEN "image"."height" ELSE "image"."width" END as "width",
+            CASE WHEN "photo"."rotation" IN (90, 270) THEN "image"."width" ELSE "image"."height" END as "height",
+            "photo"."rotation",
+            "photo"."is_favorite",
+            "image"."duration_ms",
+            "photo"."live_video_hash" IS NOT NULL as "live_photo",
+            ARRAY(
+                SELECT "tag"."name" FROM "photo_tag"
+                JOIN "tag" ON "photo_tag"."tag_id" = "tag"."id"
+                WHERE "photo_tag"."photo_id" = "photo"."id"
+                ORDER BY "tag"."name"
+            ) as "tags""#;
+/// 未删除的照片，以用户 id 的绑定参数结尾
+const PHOTO_ROW_FROM: &str = r#"
+        FROM "photo"
+        JOIN "image" ON "photo"."image_hash" = "image"."hash"
+        WHERE "photo"."deleted_at" IS NULL AND "photo"."user_id" = "#;
+
+/// 列表查询的一行，筛选条件是动态生成的，无法使用 `query!`
+#[derive(sqlx::FromRow)]
+struct PhotoRow {
+    id: Uuid,
+    image_hash: String,
+    uploaded_at: time::OffsetDateTime,
+    timeline_at: time::OffsetDateTime,
+    captured_at: Option<time::OffsetDateTime>,
+    captured_at_offset: Option<i32>,
+    width: i32,
+    height: i32,
+    rotation: i16,
+    is_favorite: bool,
+    duration_ms: Option<i64>,
+    live_photo: bool,
+    tags: Vec<String>,
+}
+
+impl From<PhotoRow> for Photo {
+    fn from(v: PhotoRow) -> Self {
+        Photo {
+            id: v.id.to_string(),
+            image_hash: v.image_hash,
+            width: v.width,
+            height: v.height,
+            duration_ms: v.duration_ms,
+            live_photo: v.live_photo,
+            rotation: v.rotation,
+            is_favorite: v.is_favorite,
+            uploaded_at: v.uploaded_at.unix_timestamp(),
+            captured_at: v.captured_at.map(|t| t.unix_timestamp()),
+            captured_at_offset: v.captured_at_offset,
+            tags: v.tags,
+        }
+    }
 }
 
 pub async fn list_handler(
     State(db): State<PgPool>,
-    AuthUser { user_id }: AuthUser,
+    AuthUser { user_id, .. }: AuthUser,
+    Query(params): Query<ListParams>,
+) -> Result<Response, (StatusCode, String)> {
+    list_photos(&db, user_id, &params, None).await
+}
+
+/// 列出智能相册中的照片：保存的筛选条件与请求中的筛选参数取交集，按时间线排列
+pub async fn list_smart_album_photos_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(smart_album_id): Path<Uuid>,
     Query(params): Query<ListParams>,
 ) -> Result<Response, (StatusCode, String)> {
-    let tags_filter: Option<Vec<String>> = params
-        .tags
-        .map(|s| {
-            s.split(',')
-                .map(|t| t.trim().to_string())
-                .filter(|t| !t.is_empty())
-                .collect()
+    let smart_filter = smart_albums::get_filter(&db, user_id, smart_album_id).await?;
+    list_photos(&db, user_id, &params, smart_filter.to_expr()).await
+}
+
+/// 按时间线分页列出照片，`base` 为额外的筛选条件
+async fn list_photos(
+    db: &PgPool,
+    user_id: Uuid,
+    params: &ListParams,
+    base: Option<search::Expr>,
+) -> Result<Response, (StatusCode, String)> {
+    let filter = match params.filter() {
+        Ok(Some(filter)) => Some(match base {
+            Some(base) => search::Expr::And(vec![base, filter]),
+            None => filter,
+        }),
+        Ok(None) => base,
+        Err(e) => return Ok(e.into_response()),
+    };
+    let limit = params.limit();
+    let cursor = params
+        .cursor
+        .as_deref()
+        .map(|c| Cursor::decode(c).ok_or_else(invalid_cursor))
+        .transpose()?;
+
+    let mut qb = sqlx::QueryBuilder::new(PHOTO_ROW_COLUMNS);
+    qb.push(PHOTO_ROW_FROM).push_bind(user_id);
+    if let Some(filter) = &filter {
+        qb.push(" AND ");
+        filter.push_sql(&mut qb);
+    }
+    if let Some(cursor) = cursor {
+        qb.push(r#" AND ("photo"."timeline_at", "photo"."id") < ("#)
+            .push_bind(cursor.at)
+            .push(", ")
+            .push_bind(cursor.id)
+            .push(")");
+    }
+    // 多取一张用于判断是否还有下一页
+    qb.push(r#" ORDER BY "photo"."timeline_at" DESC, "photo"."id" DESC LIMIT "#)
+        .push_bind(limit + 1);
+
+    let mut rows: Vec<PhotoRow> = qb.build_query_as().fetch_all(db).await.map_err(|e| {
+        tracing::error!(error = ?e, "Failed to fetch images");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_string(),
+        )
+    })?;
+
+    let next_cursor = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().map(|v| {
+            Cursor {
+                at: v.timeline_at,
+                id: v.id,
+            }
+            .encode()
         })
-        .filter(|v: &Vec<String>| !v.is_empty());
+    } else {
+        None
+    };
+
+    let photos = rows.into_iter().map(Photo::from).collect();
+
+    Ok(Json(ListImagesResponse {
+        photos,
+        next_cursor,
+    })
+    .into_response())
+}
+
+#[derive(sqlx::FromRow)]
+struct AlbumPhotoRow {
+    #[sqlx(flatten)]
+    photo: PhotoRow,
+    position: i64,
+}
+
+/// 按相册中的顺序列出照片，筛选参数与 `/photos/list` 相同
+pub async fn list_album_photos_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(album_id): Path<Uuid>,
+    Query(params): Query<ListParams>,
+) -> Result<Response, (StatusCode, String)> {
+    albums::check_album(&db, user_id, album_id).await?;
+    let filter = match params.filter() {
+        Ok(filter) => filter,
+        Err(e) => return Ok(e.into_response()),
+    };
+    let limit = params.limit();
+    let cursor = params
+        .cursor
+        .as_deref()
+        .map(|c| PositionCursor::decode(c).ok_or_else(invalid_cursor))
+        .transpose()?;
+
+    let mut qb = sqlx::QueryBuilder::new(PHOTO_ROW_COLUMNS);
+    qb.push(
+        r#",
+            "album_photo"."position"
+        FROM "photo"
+        JOIN "image" ON "photo"."image_hash" = "image"."hash"
+        JOIN "album_photo" ON "album_photo"."photo_id" = "photo"."id"
+        WHERE "album_photo"."album_id" = "#,
+    )
+    .push_bind(album_id)
+    .push(r#" AND "photo"."deleted_at" IS NULL AND "photo"."user_id" = "#)
+    .push_bind(user_id);
+    if let Some(filter) = &filter {
+        qb.push(" AND ");
+        filter.push_sql(&mut qb);
+    }
+    if let Some(cursor) = cursor {
+        qb.push(r#" AND ("album_photo"."position", "photo"."id") > ("#)
+            .push_bind(cursor.position)
+            .push(", ")
+            .push_bind(cursor.id)
+            .push(")");
+    }
+    qb.push(r#" ORDER BY "album_photo"."position", "photo"."id" LIMIT "#)
+        .push_bind(limit + 1);
+
+    let mut rows: Vec<AlbumPhotoRow> = qb.build_query_as().fetch_all(&db).await.map_err(|e| {
+        tracing::error!(error = ?e, "Failed to fetch album photos");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_string(),
+        )
+    })?;
+
+    let next_cursor = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().map(|v| {
+            PositionCursor {
+                position: v.position,
+                id: v.photo.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+
+    Ok(Json(ListImagesResponse {
+        photos: rows.into_iter().map(|v| Photo::from(v.photo)).collect(),
+        next_cursor,
+    })
+    .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    /// 搜索的文本，匹配标题、标签、地点和描述
+    text: String,
+    /// 额外的筛选条件，语法见 `search` 模块
+    q: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    photos: Vec<Photo>,
+    /// 没有更多结果时为空
+    next_offset: Option<i64>,
+}
+
+/// 全文搜索，每个词按前缀匹配，结果按相关度排序（标题和标签优先于地点，地点优先于描述）。
+/// 相关度没有稳定的游标，因此按 `offset` 分页
+pub async fn search_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Query(params): Query<SearchParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let query = search::prefix_tsquery(&params.text).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            "Search text must contain letters or digits".to_string(),
+        )
+    })?;
+    let filter = match search::build_filter(params.q.as_deref(), None, false, None) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(e.into_response()),
+    };
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let mut qb = sqlx::QueryBuilder::new(PHOTO_ROW_COLUMNS);
+    qb.push(PHOTO_ROW_FROM).push_bind(user_id);
+    qb.push(r#" AND "photo"."search_vector" @@ to_tsquery('simple', "#)
+        .push_bind(&query)
+        .push(")");
+    if let Some(filter) = &filter {
+        qb.push(" AND ");
+        filter.push_sql(&mut qb);
+    }
+    qb.push(r#" ORDER BY ts_rank("photo"."search_vector", to_tsquery('simple', "#)
+        .push_bind(&query)
+        .push(r#")) DESC, "photo"."timeline_at" DESC, "photo"."id" DESC LIMIT "#)
+        .push_bind(limit + 1)
+        .push(" OFFSET ")
+        .push_bind(offset);
 
-    let untagged_filter = params.untagged.unwrap_or(false);
+    let mut rows: Vec<PhotoRow> = qb.build_query_as().fetch_all(&db).await.map_err(|e| {
+        tracing::error!(error = ?e, "Failed to search photos");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_string(),
+        )
+    })?;
+
+    let next_offset = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        Some(offset + limit)
+    } else {
+        None
+    };
 
-    let photos: Vec<Photo> = sqlx::query!(
+    Ok(Json(SearchResponse {
+        photos: rows.into_iter().map(Photo::from).collect(),
+        next_offset,
+    })
+    .into_response())
+}
+
+#[derive(Debug, Serialize)]
+struct PhotoDetail {
+    #[serde(flatten)]
+    photo: Photo,
+    format: String,
+    size: i64,
+    /// 拍摄地的当地时间，例如 `2024-05-01T10:20:30+08:00`；时区未知时按 UTC 显示
+    captured_at_local: Option<String>,
+    /// `exif`、`gps_time` 或 `longitude`，后两者是推算的时区
+    captured_at_offset_source: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    /// 海拔，单位 m，海平面以下为负
+    altitude: Option<f64>,
+    location: Option<String>,
+    caption: Option<String>,
+    description: Option<String>,
+    /// 没有 EXIF 的照片（例如截图、视频）为空
+    metadata: Option<PhotoMetadata>,
+}
+
+pub async fn get_photo_handler(
+    State(db): State<PgPool>,
+    Path(photo_id): Path<Uuid>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Response, (StatusCode, String)> {
+    let v = sqlx::query!(
         r#"
         SELECT
             "photo"."id",
             "photo"."image_hash",
             "photo"."uploaded_at",
-            "image"."width",
-            "image"."height",
-            COALESCE(ARRAY_AGG("tag"."name") FILTER (WHERE "tag"."name" IS NOT NULL), '{}') as "tags!"
+            "photo"."captured_at",
+            "photo"."captured_at_offset",
+            "photo"."captured_at_offset_source",
+            "photo"."latitude",
+            "photo"."longitude",
+            "photo"."altitude",
+            "photo"."location",
+            "photo"."caption",
+            "photo"."description",
+            "photo"."live_video_hash" IS NOT NULL as "live_photo!",
+            CASE WHEN "photo"."rotation" IN (90, 270) THEN "image"."height" ELSE "image"."width" END as "width!",
+            CASE WHEN "photo"."rotation" IN (90, 270) THEN "image"."width" ELSE "image"."height" END as "height!",
+            "photo"."rotation",
+            "photo"."is_favorite",
+            "image"."duration_ms",
+            "image"."extension",
+            "image"."size",
+            ARRAY(
+                SELECT "tag"."name" FROM "photo_tag"
+                JOIN "tag" ON "photo_tag"."tag_id" = "tag"."id"
+                WHERE "photo_tag"."photo_id" = "photo"."id"
+                ORDER BY "tag"."name"
+            ) as "tags!",
+            "photo_metadata"."photo_id" IS NOT NULL as "has_metadata!",
+            "photo_metadata"."camera_make" as "camera_make?",
+            "photo_metadata"."camera_model" as "camera_model?",
+            "photo_metadata"."lens_make" as "lens_make?",
+            "photo_metadata"."lens_model" as "lens_model?",
+            "photo_metadata"."focal_length" as "focal_length?",
+            "photo_metadata"."focal_length_35mm" as "focal_length_35mm?",
+            "photo_metadata"."aperture" as "aperture?",
+            "photo_metadata"."exposure_time" as "exposure_time?",
+            "photo_metadata"."iso" as "iso?",
+            "photo_metadata"."orientation" as "orientation?",
+            "photo_metadata"."flash" as "flash?",
+            "photo_metadata"."offset_time" as "offset_time?",
+            "photo_metadata"."gps_timestamp" as "gps_timestamp?"
         FROM "photo"
         JOIN "image" ON "photo"."image_hash" = "image"."hash"
-        LEFT JOIN "photo_tag" ON "photo"."id" = "photo_tag"."photo_id"
-        LEFT JOIN "tag" ON "photo_tag"."tag_id" = "tag"."id"
+        LEFT JOIN "photo_metadata" ON "photo"."id" = "photo_metadata"."photo_id"
+        WHERE "photo"."id" = $1 AND "photo"."user_id" = $2 AND "photo"."deleted_at" IS NULL
+        "#,
+        photo_id,
+        user_id
+    )
+    .fetch_optional(&db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = ?e, "Failed to fetch photo");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_string(),
+        )
+    })?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Photo not found".to_string()))?;
+
+    let metadata = v.has_metadata.then_some(PhotoMetadata {
+        camera_make: v.camera_make,
+        camera_model: v.camera_model,
+        lens_make: v.lens_make,
+        lens_model: v.lens_model,
+        focal_length: v.focal_length,
+        focal_length_35mm: v.focal_length_35mm,
+        aperture: v.aperture,
+        exposure_time: v.exposure_time,
+        iso: v.iso,
+        orientation: v.orientation,
+        flash: v.flash,
+        offset_time: v.offset_time,
+        gps_timestamp: v.gps_timestamp,
+    });
+
+    Ok(Json(PhotoDetail {
+        photo: Photo {
+            id: v.id.to_string(),
+            image_hash: v.image_hash,
+            width: v.width,
+            height: v.height,
+            duration_ms: v.duration_ms,
+            live_photo: v.live_photo,
+            rotation: v.rotation,
+            is_favorite: v.is_favorite,
+            uploaded_at: v.uploaded_at.unix_timestamp(),
+            captured_at: v.captured_at.map(|t| t.unix_timestamp()),
+            captured_at_offset: v.captured_at_offset,
+            tags: v.tags,
+        },
+        format: v.extension,
+        size: v.size,
+        captured_at_local: v.captured_at.and_then(|t| {
+            let offset = time::UtcOffset::from_whole_seconds(v.captured_at_offset.unwrap_or(0))
+                .unwrap_or(time::UtcOffset::UTC);
+            t.to_offset(offset).format(CAPTURED_AT_FORMAT).ok()
+        }),
+        captured_at_offset_source: v.captured_at_offset_source,
+        latitude: v.latitude,
+        longitude: v.longitude,
+        altitude: v.altitude,
+        location: v.location,
+        caption: v.caption,
+        description: v.description,
+        metadata,
+    })
+    .into_response())
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdatePhotoPayload {
+    #[validate(length(max = 200, message = "Caption must be at most 200 characters"))]
+    caption: Option<String>,
+    #[validate(length(max = 5000, message = "Description must be at most 5000 characters"))]
+    description: Option<String>,
+}
+
+/// 修改照片的标题和描述：省略的字段保持不变，空字符串表示清除
+pub async fn update_photo_handler(
+    State(db): State<PgPool>,
+    Path(photo_id): Path<Uuid>,
+    AuthUser { user_id, .. }: AuthUser,
+    Json(payload): Json<UpdatePhotoPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    if let Err(e) = payload.validate() {
+        let body = Json(json!({
+            "details": e
+        }));
+        return Ok((StatusCode::BAD_REQUEST, body).into_response());
+    }
 
-        WHERE "photo"."user_id" = $1
-        AND ($2::text[] IS NULL OR EXISTS (
-            SELECT 1 FROM "photo_tag" "pt" 
-            JOIN "tag" "t" ON "pt"."tag_id" = "t"."id"
-            WHERE "pt"."photo_id" = "photo"."id" AND "t"."name" = ANY($2::text[])
-        ))
-        AND ($3::boolean IS NOT TRUE OR NOT EXISTS (
-            SELECT 1 FROM "photo_tag" "pt" WHERE "pt"."photo_id" = "photo"."id"
-        ))
-        GROUP BY "photo"."id", "image"."width", "image"."height"
-        ORDER BY "photo"."uploaded_at" DESC
+    let v = sqlx::query!(
+        r#"
+        UPDATE "photo" SET
+            "caption" = CASE WHEN $3::TEXT IS NULL THEN "caption" ELSE NULLIF(btrim($3), '') END,
+            "description" = CASE WHEN $4::TEXT IS NULL THEN "description" ELSE NULLIF(btrim($4), '') END
+        WHERE "id" = $1 AND "user_id" = $2 AND "deleted_at" IS NULL
+        RETURNING "caption", "description"
         "#,
+        photo_id,
         user_id,
-        tags_filter as Option<Vec<String>>,
-        untagged_filter
+        payload.caption,
+        payload.description
     )
-    .fetch_all(&db)
+    .fetch_optional(&db)
     .await
     .map_err(|e| {
-        tracing::error!(error = ?e, "Failed to fetch images");
+        tracing::error!(error = ?e, "Failed to update photo");
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             "Internal server error".to_string(),
         )
     })?
-    .iter()
-    .map(|v| Photo {
-        id: v.id.to_string(),
-        image_hash: v.image_hash.clone(),
-        width: v.width,
-        height: v.height,
-        uploaded_at: v.uploaded_at.unix_timestamp(),
-        tags: v.tags.clone(),
-    })
-    .collect();
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Photo not found".to_string()))?;
 
-    Ok(Json(ListImagesResponse { photos }).into_response())
+    Ok(Json(json!({
+        "photo_id": photo_id.to_string(),
+        "caption": v.caption,
+        "description": v.description,
+    }))
+    .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SetRotationPayload {
+    rotation: i32,
+}
+
+/// 设置照片的旋转角度（顺时针 0 / 90 / 180 / 270），用于纠正 EXIF 方向错误的照片。
+/// 缩略图按角度分别缓存，客户端应在缩略图 URL 中带上角度以避开浏览器缓存
+pub async fn set_rotation_handler(
+    State(db): State<PgPool>,
+    Path(photo_id): Path<Uuid>,
+    AuthUser { user_id, .. }: AuthUser,
+    Json(payload): Json<SetRotationPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    let rotation = Rotation::from_degrees(payload.rotation).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            "Rotation must be 0, 90, 180 or 270".to_string(),
+        )
+    })?;
+    let result = sqlx::query!(
+        r#"UPDATE "photo" SET "rotation" = $3 WHERE "id" = $1 AND "user_id" = $2 AND "deleted_at" IS NULL"#,
+        photo_id,
+        user_id,
+        rotation.degrees()
+    )
+    .execute(&db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = ?e, "Failed to update photo rotation");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_string(),
+        )
+    })?;
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Photo not found".to_string()));
+    }
+    Ok(Json(json!({
+        "photo_id": photo_id.to_string(),
+        "rotation": rotation.degrees(),
+    }))
+    .into_response())
 }
 
 pub async fn get_content_handler(
-    State(storage): State<LocalStorage>,
+    State(storage): State<SharedStorage>,
     State(db): State<PgPool>,
+    State(rendition_config): State<Arc<RenditionConfig>>,
     Path(photo_id): Path<Uuid>,
-    AuthUser { user_id }: AuthUser,
+    AuthUser { user_id, .. }: AuthUser,
+    headers: HeaderMap,
 ) -> Result<Response, (StatusCode, String)> {
     let photo = sqlx::query!(
         r#"SELECT
             "image"."hash",
-            "image"."extension"
+            "image"."extension",
+            "image"."created_at"
         FROM "photo"
         JOIN "image" ON "photo"."image_hash" = "image"."hash"
-        WHERE "photo"."id" = $1 AND "photo"."user_id" = $2"#,
+        WHERE "photo"."id" = $1 AND "photo"."user_id" = $2 AND "photo"."deleted_at" IS NULL"#,
         photo_id,
         user_id
     )
@@ -215,30 +1083,196 @@ pub async fn get_content_handler(
     })?
     .ok_or_else(|| (StatusCode::NOT_FOUND, "Image not found".to_string()))?;
 
-    let bytes = storage.get(&photo.hash).map_err(|e| {
-        tracing::error!(error = ?e, "Failed to get image content");
+    let format = MediaFormat::from_extension(&photo.extension);
+
+    // 浏览器不一定支持 HEIC 等格式，客户端没有在 Accept 中声明时返回最大尺寸的缩略图。
+    // 用户设置的旋转不会应用到原图，由客户端按照照片的 `rotation` 显示。
+    // 无法生成缩略图时（未启用解码器）仍然返回原图。视频总是返回原文件，播放器通过 Range 请求分段读取
+    if let Some(format) = format
+        && !format.is_video()
+        && !format.is_web_safe()
+        && !accepts(&headers, format.content_type())
+        && let Some(size) = rendition_config.sizes.iter().max().copied()
+    {
+        match renditions::get_or_create_rendition(
+            &db,
+            storage.as_ref(),
+            &rendition_config,
+            &photo.hash,
+            size,
+            Rotation::None,
+        )
+        .await
+        {
+            Ok(rendition) => {
+                let mut response = serve_object(
+                    storage.as_ref(),
+                    &headers,
+                    StoredObject {
+                        key: &rendition.storage_key,
+                        content_type: rendition.format.content_type(),
+                        last_modified: rendition.created_at,
+                        immutable: true,
+                    },
+                )
+                .await?;
+                response
+                    .headers_mut()
+                    .insert(header::VARY, HeaderValue::from_static("accept"));
+                return Ok(response);
+            }
+            Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, _)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut response = serve_object(
+        storage.as_ref(),
+        &headers,
+        StoredObject {
+            key: &photo.hash,
+            content_type: format.map_or("application/octet-stream", |f| f.content_type()),
+            last_modified: photo.created_at,
+            immutable: true,
+        },
+    )
+    .await?;
+    if format.is_some_and(|f| !f.is_web_safe() && !f.is_video()) {
+        response
+            .headers_mut()
+            .insert(header::VARY, HeaderValue::from_static("accept"));
+    }
+    Ok(response)
+}
+
+/// Live Photo 配对的视频，支持 Range 请求
+pub async fn get_live_video_handler(
+    State(storage): State<SharedStorage>,
+    State(db): State<PgPool>,
+    Path(photo_id): Path<Uuid>,
+    AuthUser { user_id, .. }: AuthUser,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let video = sqlx::query!(
+        r#"SELECT
+            "image"."hash",
+            "image"."extension",
+            "image"."created_at"
+        FROM "photo"
+        JOIN "image" ON "photo"."live_video_hash" = "image"."hash"
+        WHERE "photo"."id" = $1 AND "photo"."user_id" = $2 AND "photo"."deleted_at" IS NULL"#,
+        photo_id,
+        user_id
+    )
+    .fetch_optional(&db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = ?e, "Failed to fetch live video");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_string(),
+        )
+    })?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Live video not found".to_string()))?;
+
+    serve_object(
+        storage.as_ref(),
+        &headers,
+        StoredObject {
+            key: &video.hash,
+            content_type: MediaFormat::from_extension(&video.extension)
+                .map_or("application/octet-stream", |f| f.content_type()),
+            last_modified: video.created_at,
+            immutable: true,
+        },
+    )
+    .await
+}
+
+/// `Accept` 头中是否显式列出了某个 content type，通配符不算
+fn accepts(headers: &HeaderMap, content_type: &str) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            v.split(',')
+                .any(|t| t.split(';').next().unwrap_or_default().trim() == content_type)
+        })
+}
+
+#[derive(Deserialize)]
+pub struct ThumbnailParams {
+    size: Option<u32>,
+    /// 客户端所知的旋转角度，例如 `?r=90`，与照片当前的旋转一致时响应可以永久缓存
+    r: Option<i32>,
+}
+
+pub async fn get_thumbnail_handler(
+    State(storage): State<SharedStorage>,
+    State(db): State<PgPool>,
+    State(rendition_config): State<Arc<RenditionConfig>>,
+    Path(photo_id): Path<Uuid>,
+    Query(params): Query<ThumbnailParams>,
+    AuthUser { user_id, .. }: AuthUser,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    // 回收站中的照片也允许获取缩略图，以便回收站页面展示
+    let photo = sqlx::query!(
+        r#"SELECT "photo"."image_hash", "photo"."rotation", "image"."extension"
+        FROM "photo"
+        JOIN "image" ON "photo"."image_hash" = "image"."hash"
+        WHERE "photo"."id" = $1 AND "photo"."user_id" = $2"#,
+        photo_id,
+        user_id
+    )
+    .fetch_optional(&db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = ?e, "Failed to fetch image");
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            "Interval server error".to_string(),
+            "Internal server error".to_string(),
+        )
+    })?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Image not found".to_string()))?;
+
+    // 没有视频解码器，无法截取视频帧
+    if MediaFormat::from_extension(&photo.extension).is_some_and(|f| f.is_video()) {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Preview is not available for this format".to_string(),
+        ));
+    }
+
+    let size = renditions::pick_size(&rendition_config, params.size).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            "Thumbnails are not configured".to_string(),
         )
     })?;
 
-    let content_type = match photo.extension.as_str() {
-        "jpeg" => "image/jpeg",
-        "png" => "image/png",
-        "gif" => "image/gif",
-        "webp" => "image/webp",
-        _ => "application/octet-stream",
-    };
+    let rendition = renditions::get_or_create_rendition(
+        &db,
+        storage.as_ref(),
+        &rendition_config,
+        &photo.image_hash,
+        size,
+        Rotation::from_degrees(photo.rotation as i32).unwrap_or_default(),
+    )
+    .await?;
 
-    Ok((
-        [
-            (header::CONTENT_TYPE, content_type),
-            (header::CONTENT_LENGTH, bytes.len().to_string().as_ref()),
-        ],
-        bytes,
+    // 旋转后 URL 不变，只有 URL 中带有当前旋转角度时才能永久缓存
+    serve_object(
+        storage.as_ref(),
+        &headers,
+        StoredObject {
+            key: &rendition.storage_key,
+            content_type: rendition.format.content_type(),
+            last_modified: rendition.created_at,
+            immutable: params.r == Some(photo.rotation as i32),
+        },
     )
-        .into_response())
+    .await
 }
 
 #[derive(Deserialize)]
@@ -248,19 +1282,20 @@ pub struct DeleteBatchPayload {
 
 pub async fn delete_batch_handler(
     State(db): State<PgPool>,
-    AuthUser { user_id }: AuthUser,
+    AuthUser { user_id, .. }: AuthUser,
     Json(payload): Json<DeleteBatchPayload>,
 ) -> Result<Response, (StatusCode, String)> {
     let mut image_ids_uuid = Vec::new();
     for image_id in &payload.image_ids {
-        let uuid = uuid::Uuid::parse_str(&image_id).map_err(|_| {
+        let uuid = uuid::Uuid::parse_str(image_id).map_err(|_| {
             tracing::error!("Invalid image id");
             (StatusCode::BAD_REQUEST, "Invalid image id".to_string())
         })?;
         image_ids_uuid.push(uuid);
     }
+    // 移入回收站，由后台任务在保留期后永久删除
     sqlx::query!(
-        "DELETE FROM photo WHERE id = ANY($1) AND user_id = $2",
+        "UPDATE photo SET deleted_at = NOW() WHERE id = ANY($1) AND user_id = $2 AND deleted_at IS NULL",
         &image_ids_uuid,
         user_id
     )
@@ -279,6 +1314,145 @@ pub async fn delete_batch_handler(
     .into_response())
 }
 
+#[derive(Debug, Serialize)]
+struct TrashedPhoto {
+    #[serde(flatten)]
+    photo: Photo,
+    deleted_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListTrashResponse {
+    photos: Vec<TrashedPhoto>,
+}
+
+pub async fn list_trash_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Response, (StatusCode, String)> {
+    let photos: Vec<TrashedPhoto> = sqlx::query!(
+        r#"
+        SELECT
+            "photo"."id",
+            "photo"."image_hash",
+            "photo"."uploaded_at",
+            "photo"."deleted_at" as "deleted_at!",
+            "photo"."captured_at",
+            "photo"."captured_at_offset",
+            CASE WHEN "photo"."rotation" IN (90, 270) THEN "image"."height" ELSE "image"."width" END as "width!",
+            CASE WHEN "photo"."rotation" IN (90, 270) THEN "image"."width" ELSE "image"."height" END as "height!",
+            "photo"."rotation",
+            "photo"."is_favorite",
+            "image"."duration_ms",
+            "photo"."live_video_hash" IS NOT NULL as "live_photo!",
+            COALESCE(ARRAY_AGG("tag"."name") FILTER (WHERE "tag"."name" IS NOT NULL), '{}') as "tags!"
+        FROM "photo"
+        JOIN "image" ON "photo"."image_hash" = "image"."hash"
+        LEFT JOIN "photo_tag" ON "photo"."id" = "photo_tag"."photo_id"
+        LEFT JOIN "tag" ON "photo_tag"."tag_id" = "tag"."id"
+        WHERE "photo"."user_id" = $1 AND "photo"."deleted_at" IS NOT NULL
+        GROUP BY "photo"."id", "image"."width", "image"."height", "image"."duration_ms"
+        ORDER BY "photo"."deleted_at" DESC
+        "#,
+        user_id
+    )
+    .fetch_all(&db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = ?e, "Failed to fetch trashed photos");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_string(),
+        )
+    })?
+    .iter()
+    .map(|v| TrashedPhoto {
+        photo: Photo {
+            id: v.id.to_string(),
+            image_hash: v.image_hash.clone(),
+            width: v.width,
+            height: v.height,
+            duration_ms: v.duration_ms,
+            live_photo: v.live_photo,
+            rotation: v.rotation,
+            is_favorite: v.is_favorite,
+            uploaded_at: v.uploaded_at.unix_timestamp(),
+            captured_at: v.captured_at.map(|t| t.unix_timestamp()),
+            captured_at_offset: v.captured_at_offset,
+            tags: v.tags.clone(),
+        },
+        deleted_at: v.deleted_at.unix_timestamp(),
+    })
+    .collect();
+
+    Ok(Json(ListTrashResponse { photos }).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct RestoreBatchPayload {
+    photo_ids: Vec<String>,
+}
+
+pub async fn restore_batch_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Json(payload): Json<RestoreBatchPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    let mut photo_uuids = Vec::new();
+    for photo_id in &payload.photo_ids {
+        let uuid = Uuid::parse_str(photo_id)
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid photo id".to_string()))?;
+        photo_uuids.push(uuid);
+    }
+
+    let restored: Vec<String> = sqlx::query!(
+        "UPDATE photo SET deleted_at = NULL WHERE id = ANY($1) AND user_id = $2 AND deleted_at IS NOT NULL RETURNING id",
+        &photo_uuids,
+        user_id
+    )
+    .fetch_all(&db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = ?e, "Failed to restore photos");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_string(),
+        )
+    })?
+    .iter()
+    .map(|v| v.id.to_string())
+    .collect();
+
+    Ok(Json(json!({
+        "restored_photo_ids": restored,
+    }))
+    .into_response())
+}
+
+pub async fn empty_trash_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Response, (StatusCode, String)> {
+    let result = sqlx::query!(
+        "DELETE FROM photo WHERE user_id = $1 AND deleted_at IS NOT NULL",
+        user_id
+    )
+    .execute(&db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = ?e, "Failed to empty trash");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_string(),
+        )
+    })?;
+
+    Ok(Json(json!({
+        "deleted_count": result.rows_affected(),
+    }))
+    .into_response())
+}
+
 #[derive(Deserialize)]
 pub struct TagBatchPayload {
     tag_names: Vec<String>,
@@ -287,7 +1461,7 @@ pub struct TagBatchPayload {
 
 pub async fn add_tags_batch_handler(
     State(db): State<PgPool>,
-    AuthUser { user_id }: AuthUser,
+    AuthUser { user_id, .. }: AuthUser,
     Json(payload): Json<TagBatchPayload>,
 ) -> Result<Response, (StatusCode, String)> {
     let mut photo_uuids = Vec::new();
@@ -366,9 +1540,67 @@ pub async fn add_tags_batch_handler(
     .into_response())
 }
 
+#[derive(Deserialize)]
+pub struct FavoriteBatchPayload {
+    photo_ids: Vec<String>,
+}
+
+pub async fn favorite_batch_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Json(payload): Json<FavoriteBatchPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    set_favorite(&db, user_id, &payload.photo_ids, true).await
+}
+
+pub async fn unfavorite_batch_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Json(payload): Json<FavoriteBatchPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    set_favorite(&db, user_id, &payload.photo_ids, false).await
+}
+
+async fn set_favorite(
+    db: &PgPool,
+    user_id: Uuid,
+    photo_ids: &[String],
+    is_favorite: bool,
+) -> Result<Response, (StatusCode, String)> {
+    let photo_uuids: Vec<Uuid> = photo_ids
+        .iter()
+        .filter_map(|id| Uuid::parse_str(id).ok())
+        .collect();
+
+    if photo_uuids.is_empty() {
+        return Ok(Json(json!({"success": true})).into_response());
+    }
+
+    sqlx::query!(
+        r#"UPDATE "photo" SET "is_favorite" = $3 WHERE "id" = ANY($1) AND "user_id" = $2 AND "deleted_at" IS NULL"#,
+        &photo_uuids,
+        user_id,
+        is_favorite
+    )
+    .execute(db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = ?e, "Failed to update favorites");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Database error".to_string(),
+        )
+    })?;
+
+    Ok(Json(json!({
+        "success": true
+    }))
+    .into_response())
+}
+
 pub async fn delete_tags_batch_handler(
     State(db): State<PgPool>,
-    AuthUser { user_id }: AuthUser,
+    AuthUser { user_id, .. }: AuthUser,
     Json(payload): Json<TagBatchPayload>,
 ) -> Result<Response, (StatusCode, String)> {
     let mut photo_uuids = Vec::new();
@@ -419,20 +1651,22 @@ pub struct RecommendTagsResponse {
 }
 
 pub async fn recommend_tags_handler(
-    State(storage): State<LocalStorage>,
+    State(storage): State<SharedStorage>,
     State(db): State<PgPool>,
     State(ai_service): State<Arc<ai::AiService>>,
+    State(rendition_config): State<Arc<RenditionConfig>>,
     Path(photo_id): Path<Uuid>,
-    AuthUser { user_id }: AuthUser,
+    AuthUser { user_id, .. }: AuthUser,
 ) -> Result<Response, (StatusCode, String)> {
     // 1. Fetch image info to check ownership and get hash
     let photo = sqlx::query!(
         r#"SELECT
             "image"."hash",
-            "image"."extension"
+            "image"."extension",
+            "photo"."rotation"
         FROM "photo"
         JOIN "image" ON "photo"."image_hash" = "image"."hash"
-        WHERE "photo"."id" = $1 AND "photo"."user_id" = $2"#,
+        WHERE "photo"."id" = $1 AND "photo"."user_id" = $2 AND "photo"."deleted_at" IS NULL"#,
         photo_id,
         user_id
     )
@@ -447,26 +1681,48 @@ pub async fn recommend_tags_handler(
     })?
     .ok_or_else(|| (StatusCode::NOT_FOUND, "Image not found".to_string()))?;
 
-    // 2. Fetch image content
-    let bytes = storage.get(&photo.hash).map_err(|e| {
+    // 2. Determine MIME type
+    let format = MediaFormat::from_extension(&photo.extension)
+        .filter(|f| !f.is_video())
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                "Unsupported image format for AI analysis".to_string(),
+            )
+        })?;
+
+    // 3. Fetch image content, HEIC 等格式和旋转过的照片改用缩略图
+    let content_error = |e: StorageError| {
         tracing::error!(error = ?e, "Failed to get image content for AI analysis");
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             "Internal server error".to_string(),
         )
-    })?;
-
-    // 3. Determine MIME type
-    let mime_type = match photo.extension.as_str() {
-        "jpeg" | "jpg" => "image/jpeg",
-        "png" => "image/png",
-        "gif" => "image/gif",
-        "webp" => "image/webp",
+    };
+    let rotation = Rotation::from_degrees(photo.rotation as i32).unwrap_or_default();
+    let (bytes, mime_type) = match rendition_config.sizes.iter().max().copied() {
+        Some(size) if !format.is_web_safe() || rotation != Rotation::None => {
+            let rendition = renditions::get_or_create_rendition(
+                &db,
+                storage.as_ref(),
+                &rendition_config,
+                &photo.hash,
+                size,
+                rotation,
+            )
+            .await?;
+            let bytes = storage
+                .get(&rendition.storage_key)
+                .await
+                .map_err(content_error)?;
+            (bytes, rendition.format.content_type())
+        }
         _ => {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                "Unsupported image format for AI analysis".to_string(),
-            ));
+            let bytes = storage
+                .get_verified(&photo.hash)
+                .await
+                .map_err(content_error)?;
+            (bytes, format.content_type())
         }
     };
 
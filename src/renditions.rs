@@ -0,0 +1,342 @@
+use axum::http::StatusCode;
+use bytes::Bytes;
+use image::{
+    DynamicImage, ImageEncoder,
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    metadata::Orientation,
+};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+
+use crate::{
+    config::{RenditionConfig, RenditionFormat},
+    exif::{get_image_exif, orientation},
+    images::{format::MediaFormat, heif, raw},
+    infra::storage::Storage,
+};
+
+#[derive(Debug)]
+enum RenderError {
+    /// 没有可用的解码器，例如未启用 `heif` feature 时的 HEIC，或者没有嵌入预览图的 RAW
+    Unsupported(String),
+    Image(image::ImageError),
+}
+
+impl From<image::ImageError> for RenderError {
+    fn from(e: image::ImageError) -> Self {
+        RenderError::Image(e)
+    }
+}
+
+/// 按文件内容识别格式并解码原图，转换为显示方向
+fn decode(original: &[u8]) -> Result<DynamicImage, RenderError> {
+    let format = MediaFormat::detect(&mut std::io::Cursor::new(original));
+    let mut image = match format {
+        Some(format) if format.is_heif() => {
+            // libheif 已经应用了 irot / imir
+            return heif::decode(original).map_err(RenderError::Unsupported);
+        }
+        Some(format) if format.is_raw() => {
+            raw::decode_preview(original).map_err(RenderError::Unsupported)?
+        }
+        Some(format) => match format.image_format() {
+            Some(image_format) => image::load_from_memory_with_format(original, image_format)?,
+            None => return Err(RenderError::Unsupported(format!("{:?}", format))),
+        },
+        None => image::load_from_memory(original)?,
+    };
+
+    // RAW 的嵌入预览图没有旋转，方向记录在 RAW 本身的 EXIF 中
+    if let Some(exif) = get_image_exif(std::io::Cursor::new(original))
+        && let Some(orientation) = Orientation::from_exif(orientation(&exif))
+    {
+        image.apply_orientation(orientation);
+    }
+    Ok(image)
+}
+
+/// 用户设置的额外旋转，顺时针，在 EXIF Orientation 之后应用
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: i32) -> Option<Self> {
+        match degrees {
+            0 => Some(Self::None),
+            90 => Some(Self::Rotate90),
+            180 => Some(Self::Rotate180),
+            270 => Some(Self::Rotate270),
+            _ => None,
+        }
+    }
+
+    pub fn degrees(&self) -> i16 {
+        match self {
+            Self::None => 0,
+            Self::Rotate90 => 90,
+            Self::Rotate180 => 180,
+            Self::Rotate270 => 270,
+        }
+    }
+
+    fn apply(&self, image: DynamicImage) -> DynamicImage {
+        match self {
+            Self::None => image,
+            Self::Rotate90 => image.rotate90(),
+            Self::Rotate180 => image.rotate180(),
+            Self::Rotate270 => image.rotate270(),
+        }
+    }
+}
+
+impl RenditionFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RenditionFormat::Jpeg => "jpeg",
+            RenditionFormat::Webp => "webp",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            RenditionFormat::Jpeg => "image/jpeg",
+            RenditionFormat::Webp => "image/webp",
+        }
+    }
+}
+
+pub struct Rendition {
+    pub storage_key: String,
+    pub format: RenditionFormat,
+    pub created_at: OffsetDateTime,
+}
+
+/// 选择不小于请求尺寸的最小配置尺寸，请求超过所有配置时使用最大的尺寸
+pub fn pick_size(config: &RenditionConfig, requested: Option<u32>) -> Option<u32> {
+    let mut sizes = config.sizes.clone();
+    sizes.sort_unstable();
+    match requested {
+        None => sizes.first().copied(),
+        Some(requested) => sizes
+            .iter()
+            .copied()
+            .find(|s| *s >= requested)
+            .or(sizes.last().copied()),
+    }
+}
+
+/// 缩略图的生成方式改变时递增，使新旧文件的 key（同时也是 ETag）不同。
+/// 版本 2 开始应用 EXIF Orientation
+const RENDITION_VERSION: u32 = 2;
+
+/// 派生的存储 key，与原图位于同一个散列目录下
+/// Key: "e3b0c442..._256_v2.jpeg"，旋转过的为 "e3b0c442..._256_r90_v2.jpeg"
+pub fn rendition_key(
+    image_hash: &str,
+    size: u32,
+    format: RenditionFormat,
+    rotation: Rotation,
+) -> String {
+    let rotation = match rotation {
+        Rotation::None => String::new(),
+        _ => format!("_r{}", rotation.degrees()),
+    };
+    format!(
+        "{}_{}{}_v{}.{}",
+        image_hash,
+        size,
+        rotation,
+        RENDITION_VERSION,
+        format.extension()
+    )
+}
+
+/// 将原图缩放到长边不超过 `size`，不会放大
+fn render(
+    original: &[u8],
+    size: u32,
+    format: RenditionFormat,
+    quality: u8,
+    rotation: Rotation,
+) -> Result<(Vec<u8>, u32, u32), RenderError> {
+    let image = rotation.apply(decode(original)?);
+    let image = if image.width().max(image.height()) > size {
+        image.thumbnail(size, size)
+    } else {
+        image
+    };
+
+    let mut buf = Vec::new();
+    match format {
+        RenditionFormat::Jpeg => {
+            let image = DynamicImage::ImageRgb8(image.to_rgb8());
+            JpegEncoder::new_with_quality(&mut buf, quality).write_image(
+                image.as_bytes(),
+                image.width(),
+                image.height(),
+                image.color().into(),
+            )?;
+        }
+        RenditionFormat::Webp => {
+            // 只有无损编码，`quality` 不起作用
+            let image = DynamicImage::ImageRgba8(image.to_rgba8());
+            WebPEncoder::new_lossless(&mut buf).write_image(
+                image.as_bytes(),
+                image.width(),
+                image.height(),
+                image.color().into(),
+            )?;
+        }
+    }
+
+    Ok((buf, image.width(), image.height()))
+}
+
+fn internal_error() -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error".to_string(),
+    )
+}
+
+/// 获取缓存的缩略图，不存在时从原图生成
+pub async fn get_or_create_rendition(
+    db: &PgPool,
+    storage: &dyn Storage,
+    config: &RenditionConfig,
+    image_hash: &str,
+    size: u32,
+    rotation: Rotation,
+) -> Result<Rendition, (StatusCode, String)> {
+    let format = config.format;
+
+    let existing = sqlx::query!(
+        r#"SELECT "storage_key", "created_at" FROM "rendition" WHERE "image_hash" = $1 AND "size" = $2 AND "format" = $3 AND "rotation" = $4"#,
+        image_hash,
+        size as i32,
+        format.extension(),
+        rotation.degrees()
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = ?e, "Failed to fetch rendition");
+        internal_error()
+    })?;
+
+    if let Some(existing) = existing {
+        return Ok(Rendition {
+            storage_key: existing.storage_key,
+            format,
+            created_at: existing.created_at,
+        });
+    }
+
+    let original = storage.get_verified(image_hash).await.map_err(|e| {
+        tracing::error!(error = ?e, object = image_hash, "Failed to get image content");
+        internal_error()
+    })?;
+
+    let quality = config.quality;
+    let (data, width, height) =
+        tokio::task::spawn_blocking(move || render(&original, size, format, quality, rotation))
+            .await
+            .map_err(|e| {
+                tracing::error!(error = ?e, "Rendition task failed");
+                internal_error()
+            })?
+            .map_err(|e| match e {
+                RenderError::Unsupported(reason) => {
+                    tracing::info!(object = image_hash, reason, "Cannot render image");
+                    (
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                        "Preview is not available for this format".to_string(),
+                    )
+                }
+                RenderError::Image(e) => {
+                    tracing::error!(error = ?e, object = image_hash, "Failed to render image");
+                    internal_error()
+                }
+            })?;
+
+    let storage_key = rendition_key(image_hash, size, format, rotation);
+    let byte_size = data.len() as i64;
+    storage
+        .save(&storage_key, Bytes::from(data))
+        .await
+        .map_err(|e| {
+            tracing::error!(error = ?e, object = storage_key, "Failed to save rendition");
+            internal_error()
+        })?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO "rendition" ("image_hash", "size", "format", "rotation", "storage_key", "width", "height", "byte_size")
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT DO NOTHING
+        "#,
+        image_hash,
+        size as i32,
+        format.extension(),
+        rotation.degrees(),
+        storage_key,
+        width as i32,
+        height as i32,
+        byte_size
+    )
+    .execute(db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = ?e, "Failed to insert rendition");
+        internal_error()
+    })?;
+
+    Ok(Rendition {
+        storage_key,
+        format,
+        created_at: OffsetDateTime::now_utc(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        let hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(
+            rendition_key(hash, 256, RenditionFormat::Jpeg, Rotation::None),
+            format!("{}_256_v2.jpeg", hash)
+        );
+        assert_eq!(
+            rendition_key(hash, 1024, RenditionFormat::Webp, Rotation::Rotate270),
+            format!("{}_1024_r270_v2.webp", hash)
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        let config = RenditionConfig {
+            sizes: vec![2048, 256, 1024],
+            ..Default::default()
+        };
+        assert_eq!(pick_size(&config, None), Some(256));
+        assert_eq!(pick_size(&config, Some(100)), Some(256));
+        assert_eq!(pick_size(&config, Some(256)), Some(256));
+        assert_eq!(pick_size(&config, Some(257)), Some(1024));
+        assert_eq!(pick_size(&config, Some(4096)), Some(2048));
+
+        let empty = RenditionConfig {
+            sizes: vec![],
+            ..Default::default()
+        };
+        assert_eq!(pick_size(&empty, Some(256)), None);
+    }
+}
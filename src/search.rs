@@ -0,0 +1,930 @@
+//! 照片搜索的查询语言，例如：
+//!
+//! ```text
+//! (cat OR dog) -screenshot date:2024-05..2024-08 location:kyoto width:>=3000 camera:"iPhone 15"
+//! ```
+//!
+//! - 空格分隔的条件之间为 AND，也可以显式写 `AND`、`OR`、`NOT`（大写），`-` 前缀等同于 `NOT`，括号用于分组
+//! - 不带字段的词和 `tag:` 匹配标签，含空格的值用双引号括起来
+//! - `date:2024`、`date:2024-05`、`date:2024-05-01` 按拍摄地的当地日期匹配，`a..b` 为范围（包含两端），
+//!   `before:` / `after:` 表示在该日期之前 / 之后（不含该日期）
+//! - `location:` 和 `camera:` 为不区分大小写的包含匹配
+//! - `text:` 全文搜索标题、标签、地点和描述，每个词按前缀匹配
+//! - `width:` / `height:` 支持 `>`、`>=`、`<`、`<=`、`=` 和 `a..b`，按显示方向计算
+//! - `orientation:portrait|landscape|square`、`format:jpeg|heic|...`、`is:photo|video|live|untagged|favorite`
+//!
+//! 解析结果通过 `QueryBuilder` 生成 SQL，所有值都作为参数绑定
+
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{Postgres, QueryBuilder};
+use time::{Date, Month, PrimitiveDateTime, Time};
+
+use crate::images::format::MediaFormat;
+
+/// 查询的长度上限（字符数）
+const MAX_QUERY_LENGTH: usize = 1000;
+/// 括号和 `NOT` 的嵌套层数上限，避免递归过深
+const MAX_DEPTH: usize = 32;
+
+/// 显示方向的宽高，需要查询中 JOIN 了 `image`
+const DISPLAY_WIDTH: &str =
+    r#"(CASE WHEN "photo"."rotation" IN (90, 270) THEN "image"."height" ELSE "image"."width" END)"#;
+const DISPLAY_HEIGHT: &str =
+    r#"(CASE WHEN "photo"."rotation" IN (90, 270) THEN "image"."width" ELSE "image"."height" END)"#;
+/// 拍摄地的当地时间，时区未知的按 UTC
+const LOCAL_CAPTURED_AT: &str = r#"(("photo"."captured_at" AT TIME ZONE 'UTC') + make_interval(secs => COALESCE("photo"."captured_at_offset", 0)))"#;
+
+/// 解析错误，`start` 和 `end` 是出错部分在查询中的字符位置（左闭右开），用于在输入框中高亮
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParseError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>, start: usize, end: usize) -> Self {
+        Self {
+            message: message.into(),
+            start,
+            end,
+        }
+    }
+}
+
+impl IntoResponse for ParseError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_REQUEST, Json(json!({ "details": self }))).into_response()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq(i32),
+    Lt(i32),
+    Le(i32),
+    Gt(i32),
+    Ge(i32),
+    Between(i32, i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    Width,
+    Height,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+    Square,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Photo,
+    Video,
+    Live,
+    Untagged,
+    Favorite,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Tag(String),
+    Is(Kind),
+    /// 当地时间的范围，左闭右开
+    Captured {
+        start: Option<PrimitiveDateTime>,
+        end: Option<PrimitiveDateTime>,
+    },
+    Location(String),
+    Size(Dimension, Comparison),
+    Orientation(Orientation),
+    Camera(String),
+    Format(MediaFormat),
+    /// `to_tsquery` 的查询，由 `prefix_tsquery` 生成
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Word {
+        field: Option<String>,
+        value: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+}
+
+fn tokenize(chars: &[char]) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let single = |kind| Token {
+            kind,
+            start: i,
+            end: i + 1,
+        };
+        match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => tokens.push(single(TokenKind::LParen)),
+            ')' => tokens.push(single(TokenKind::RParen)),
+            // 只有紧跟着条件的 `-` 才表示 NOT
+            '-' if chars
+                .get(i + 1)
+                .is_some_and(|next| !next.is_whitespace() && *next != ')') =>
+            {
+                tokens.push(single(TokenKind::Not))
+            }
+            _ => {
+                let token = read_word(chars, i)?;
+                i = token.end;
+                tokens.push(token);
+                continue;
+            }
+        }
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+/// 读取一个词：`value`、`field:value`，值可以用双引号括起来
+fn read_word(chars: &[char], start: usize) -> Result<Token, ParseError> {
+    let mut i = start;
+    let mut field = None;
+    let mut value = String::new();
+    let mut quoted = false;
+    while let Some(&c) = chars.get(i) {
+        match c {
+            '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|c| *c == '"')
+                    .map(|p| i + 1 + p)
+                    .ok_or_else(|| ParseError::new("Unterminated quote", i, chars.len()))?;
+                value.extend(&chars[i + 1..end]);
+                quoted = true;
+                i = end + 1;
+            }
+            c if c.is_whitespace() || c == '(' || c == ')' => break,
+            ':' if field.is_none() && !quoted => {
+                field = Some(std::mem::take(&mut value));
+                i += 1;
+            }
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    let kind = match (&field, value.as_str(), quoted) {
+        (None, "AND", false) => TokenKind::And,
+        (None, "OR", false) => TokenKind::Or,
+        (None, "NOT", false) => TokenKind::Not,
+        _ => TokenKind::Word { field, value },
+    };
+    Ok(Token {
+        kind,
+        start,
+        end: i,
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// 运算符后面必须跟着一个条件
+    fn expect_operand(&self, operator: &Token, name: &str) -> Result<(), ParseError> {
+        match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Word { .. } | TokenKind::LParen | TokenKind::Not) => Ok(()),
+            _ => Err(ParseError::new(
+                format!("Expected a condition after {}", name),
+                operator.start,
+                operator.end,
+            )),
+        }
+    }
+
+    fn parse_or(&mut self, depth: usize) -> Result<Expr, ParseError> {
+        let mut items = vec![self.parse_and(depth)?];
+        while let Some(TokenKind::Or) = self.peek().map(|t| &t.kind) {
+            let operator = self.next().expect("peeked token");
+            self.expect_operand(&operator, "OR")?;
+            items.push(self.parse_and(depth)?);
+        }
+        Ok(if items.len() == 1 {
+            items.pop().expect("one item")
+        } else {
+            Expr::Or(items)
+        })
+    }
+
+    fn parse_and(&mut self, depth: usize) -> Result<Expr, ParseError> {
+        let mut items = vec![self.parse_unary(depth)?];
+        loop {
+            match self.peek().map(|t| &t.kind) {
+                None | Some(TokenKind::RParen | TokenKind::Or) => break,
+                Some(TokenKind::And) => {
+                    let operator = self.next().expect("peeked token");
+                    self.expect_operand(&operator, "AND")?;
+                }
+                _ => {}
+            }
+            items.push(self.parse_unary(depth)?);
+        }
+        Ok(if items.len() == 1 {
+            items.pop().expect("one item")
+        } else {
+            Expr::And(items)
+        })
+    }
+
+    fn parse_unary(&mut self, depth: usize) -> Result<Expr, ParseError> {
+        if let Some(TokenKind::Not) = self.peek().map(|t| &t.kind) {
+            let operator = self.next().expect("peeked token");
+            if depth >= MAX_DEPTH {
+                return Err(ParseError::new(
+                    "Query is nested too deeply",
+                    operator.start,
+                    operator.end,
+                ));
+            }
+            self.expect_operand(&operator, "NOT")?;
+            return Ok(Expr::Not(Box::new(self.parse_unary(depth + 1)?)));
+        }
+        self.parse_primary(depth)
+    }
+
+    fn parse_primary(&mut self, depth: usize) -> Result<Expr, ParseError> {
+        let Some(token) = self.next() else {
+            return Err(ParseError::new("Expected a condition", self.len, self.len));
+        };
+        match token.kind {
+            TokenKind::LParen => {
+                if depth >= MAX_DEPTH {
+                    return Err(ParseError::new(
+                        "Query is nested too deeply",
+                        token.start,
+                        token.end,
+                    ));
+                }
+                if let Some(TokenKind::RParen) = self.peek().map(|t| &t.kind) {
+                    let close = self.next().expect("peeked token");
+                    return Err(ParseError::new("Empty parentheses", token.start, close.end));
+                }
+                let expr = self.parse_or(depth + 1)?;
+                match self.next() {
+                    Some(Token {
+                        kind: TokenKind::RParen,
+                        ..
+                    }) => Ok(expr),
+                    _ => Err(ParseError::new(
+                        "Missing closing parenthesis",
+                        token.start,
+                        token.end,
+                    )),
+                }
+            }
+            TokenKind::RParen => Err(ParseError::new(
+                "Unexpected closing parenthesis",
+                token.start,
+                token.end,
+            )),
+            TokenKind::And | TokenKind::Or => Err(ParseError::new(
+                "Expected a condition before the operator",
+                token.start,
+                token.end,
+            )),
+            TokenKind::Not => unreachable!("handled by parse_unary"),
+            TokenKind::Word { field, value } => {
+                parse_term(field.as_deref(), &value, token.start, token.end).map(Expr::Term)
+            }
+        }
+    }
+}
+
+/// 解析查询，空查询返回 `None`
+pub fn parse(query: &str) -> Result<Option<Expr>, ParseError> {
+    let chars: Vec<char> = query.chars().collect();
+    if chars.len() > MAX_QUERY_LENGTH {
+        return Err(ParseError::new(
+            format!("Query is longer than {} characters", MAX_QUERY_LENGTH),
+            MAX_QUERY_LENGTH,
+            chars.len(),
+        ));
+    }
+    let tokens = tokenize(&chars)?;
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        len: chars.len(),
+    };
+    let expr = parser.parse_or(0)?;
+    // parse_or 只会停在多余的右括号上
+    if let Some(token) = parser.peek() {
+        return Err(ParseError::new(
+            "Unexpected closing parenthesis",
+            token.start,
+            token.end,
+        ));
+    }
+    Ok(Some(expr))
+}
+
+/// 列表接口的筛选条件：`q` 查询，兼容旧版的 `tags`（任一标签）和 `untagged`，以及是否收藏，全部取交集
+pub fn build_filter(
+    query: Option<&str>,
+    tags: Option<Vec<String>>,
+    untagged: bool,
+    favorite: Option<bool>,
+) -> Result<Option<Expr>, ParseError> {
+    let mut items: Vec<Expr> = query
+        .map(parse)
+        .transpose()?
+        .flatten()
+        .into_iter()
+        .collect();
+    if let Some(tags) = tags {
+        items.push(Expr::Or(
+            tags.into_iter().map(|t| Expr::Term(Term::Tag(t))).collect(),
+        ));
+    }
+    if untagged {
+        items.push(Expr::Term(Term::Is(Kind::Untagged)));
+    }
+    match favorite {
+        Some(true) => items.push(Expr::Term(Term::Is(Kind::Favorite))),
+        Some(false) => items.push(Expr::Not(Box::new(Expr::Term(Term::Is(Kind::Favorite))))),
+        None => {}
+    }
+    Ok(match items.len() {
+        0 => None,
+        1 => items.pop(),
+        _ => Some(Expr::And(items)),
+    })
+}
+
+fn parse_term(
+    field: Option<&str>,
+    value: &str,
+    start: usize,
+    end: usize,
+) -> Result<Term, ParseError> {
+    let invalid = |message: String| ParseError::new(message, start, end);
+    if value.is_empty() {
+        return Err(invalid("Empty value".to_string()));
+    }
+    let Some(field) = field else {
+        return Ok(Term::Tag(value.to_string()));
+    };
+
+    let lowercase = value.to_lowercase();
+    match field.to_lowercase().as_str() {
+        "tag" => Ok(Term::Tag(value.to_string())),
+        "is" => match lowercase.as_str() {
+            "photo" => Ok(Term::Is(Kind::Photo)),
+            "video" => Ok(Term::Is(Kind::Video)),
+            "live" => Ok(Term::Is(Kind::Live)),
+            "untagged" => Ok(Term::Is(Kind::Untagged)),
+            "favorite" => Ok(Term::Is(Kind::Favorite)),
+            _ => Err(invalid(format!(
+                "Unknown value `{}`, expected photo, video, live, untagged or favorite",
+                value
+            ))),
+        },
+        "date" => {
+            let (start, end) = match value.split_once("..") {
+                Some((from, to)) => {
+                    if from.is_empty() && to.is_empty() {
+                        return Err(invalid("Empty date range".to_string()));
+                    }
+                    let period = |s: &str| parse_period(s).ok_or_else(|| invalid(invalid_date(s)));
+                    let start = match from {
+                        "" => None,
+                        from => Some(period(from)?.0),
+                    };
+                    let end = match to {
+                        "" => None,
+                        to => Some(period(to)?.1),
+                    };
+                    (start, end)
+                }
+                None => {
+                    let (s, e) = parse_period(value).ok_or_else(|| invalid(invalid_date(value)))?;
+                    (Some(s), Some(e))
+                }
+            };
+            if let (Some(s), Some(e)) = (start, end)
+                && s >= e
+            {
+                return Err(invalid("Date range ends before it starts".to_string()));
+            }
+            Ok(Term::Captured { start, end })
+        }
+        "before" => {
+            let (s, _) = parse_period(value).ok_or_else(|| invalid(invalid_date(value)))?;
+            Ok(Term::Captured {
+                start: None,
+                end: Some(s),
+            })
+        }
+        "after" => {
+            let (_, e) = parse_period(value).ok_or_else(|| invalid(invalid_date(value)))?;
+            Ok(Term::Captured {
+                start: Some(e),
+                end: None,
+            })
+        }
+        "location" | "loc" => Ok(Term::Location(value.to_string())),
+        "camera" => Ok(Term::Camera(value.to_string())),
+        "text" => prefix_tsquery(value)
+            .map(Term::Text)
+            .ok_or_else(|| invalid(format!("No searchable words in `{}`", value))),
+        "width" | "height" => {
+            let dimension = if field.eq_ignore_ascii_case("width") {
+                Dimension::Width
+            } else {
+                Dimension::Height
+            };
+            let comparison = parse_comparison(value).ok_or_else(|| {
+                invalid(format!(
+                    "Invalid size `{}`, expected a number such as 1920, >=1000 or 800..1200",
+                    value
+                ))
+            })?;
+            Ok(Term::Size(dimension, comparison))
+        }
+        "orientation" => match lowercase.as_str() {
+            "portrait" => Ok(Term::Orientation(Orientation::Portrait)),
+            "landscape" => Ok(Term::Orientation(Orientation::Landscape)),
+            "square" => Ok(Term::Orientation(Orientation::Square)),
+            _ => Err(invalid(format!(
+                "Unknown orientation `{}`, expected portrait, landscape or square",
+                value
+            ))),
+        },
+        "format" => MediaFormat::from_extension(&lowercase)
+            .map(Term::Format)
+            .ok_or_else(|| invalid(format!("Unknown format `{}`", value))),
+        _ => {
+            // 只高亮字段名
+            let field_end = start + field.chars().count();
+            Err(ParseError::new(
+                format!("Unknown field `{}`", field),
+                start,
+                field_end,
+            ))
+        }
+    }
+}
+
+fn invalid_date(value: &str) -> String {
+    format!(
+        "Invalid date `{}`, expected YYYY, YYYY-MM or YYYY-MM-DD",
+        value
+    )
+}
+
+/// `YYYY`、`YYYY-MM` 或 `YYYY-MM-DD` 表示的时间段，返回开始和结束（不含）
+pub fn parse_period(value: &str) -> Option<(PrimitiveDateTime, PrimitiveDateTime)> {
+    let parts: Vec<&str> = value.split('-').collect();
+    let number = |s: &str, max_len: usize| {
+        (!s.is_empty() && s.len() <= max_len && s.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| s.parse::<i32>().ok())
+            .flatten()
+    };
+    let year = parts
+        .first()
+        .and_then(|s| number(s, 4))
+        .filter(|_| parts[0].len() == 4)?;
+    let month = |s: &str| Month::try_from(u8::try_from(number(s, 2)?).ok()?).ok();
+    let month_start = |year: i32, month: Month| Date::from_calendar_date(year, month, 1).ok();
+    let next_month = |year: i32, month: Month| match month {
+        Month::December => month_start(year + 1, Month::January),
+        _ => month_start(year, month.next()),
+    };
+
+    let (start, end) = match parts.as_slice() {
+        [_] => (
+            month_start(year, Month::January)?,
+            month_start(year + 1, Month::January)?,
+        ),
+        [_, m] => {
+            let m = month(m)?;
+            (month_start(year, m)?, next_month(year, m)?)
+        }
+        [_, m, d] => {
+            let day = u8::try_from(number(d, 2)?).ok()?;
+            let date = Date::from_calendar_date(year, month(m)?, day).ok()?;
+            (date, date.next_day()?)
+        }
+        _ => return None,
+    };
+    Some((
+        start.with_time(Time::MIDNIGHT),
+        end.with_time(Time::MIDNIGHT),
+    ))
+}
+
+fn parse_comparison(value: &str) -> Option<Comparison> {
+    let number = |s: &str| s.parse::<u32>().ok().and_then(|v| i32::try_from(v).ok());
+    if let Some((from, to)) = value.split_once("..") {
+        let (from, to) = (number(from)?, number(to)?);
+        return (from <= to).then_some(Comparison::Between(from, to));
+    }
+    let comparison = if let Some(v) = value.strip_prefix(">=") {
+        Comparison::Ge(number(v)?)
+    } else if let Some(v) = value.strip_prefix("<=") {
+        Comparison::Le(number(v)?)
+    } else if let Some(v) = value.strip_prefix('>') {
+        Comparison::Gt(number(v)?)
+    } else if let Some(v) = value.strip_prefix('<') {
+        Comparison::Lt(number(v)?)
+    } else if let Some(v) = value.strip_prefix('=') {
+        Comparison::Eq(number(v)?)
+    } else {
+        Comparison::Eq(number(value)?)
+    };
+    Some(comparison)
+}
+
+/// 将用户输入的文本转换为 `to_tsquery` 的查询：只保留字母和数字，每个词按前缀匹配，词之间为 AND。
+/// 例如 `kyoto temple` 转换为 `kyoto:* & temple:*`，没有可搜索的词时返回 `None`
+pub fn prefix_tsquery(text: &str) -> Option<String> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| format!("{}:*", w.to_lowercase()))
+        .collect();
+    (!words.is_empty()).then(|| words.join(" & "))
+}
+
+/// 转义 LIKE 的通配符，生成包含匹配的模式
+fn contains_pattern(value: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in value.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+impl Expr {
+    /// 生成 WHERE 条件，需要查询中有 `photo` 和 `image` 两张表
+    pub fn push_sql(&self, qb: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Expr::And(items) | Expr::Or(items) => {
+                let separator = if matches!(self, Expr::And(_)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                qb.push("(");
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        qb.push(separator);
+                    }
+                    item.push_sql(qb);
+                }
+                qb.push(")");
+            }
+            Expr::Not(expr) => {
+                qb.push("(NOT ");
+                expr.push_sql(qb);
+                qb.push(")");
+            }
+            // 字段为 NULL 时条件为 FALSE，这样 NOT 才能匹配到没有该字段的照片
+            Expr::Term(term) => {
+                qb.push("COALESCE(");
+                term.push_sql(qb);
+                qb.push(", FALSE)");
+            }
+        }
+    }
+}
+
+impl Term {
+    fn push_sql(&self, qb: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Term::Tag(name) => {
+                qb.push(
+                    r#"EXISTS (SELECT 1 FROM "photo_tag" "pt" JOIN "tag" "t" ON "pt"."tag_id" = "t"."id" WHERE "pt"."photo_id" = "photo"."id" AND "t"."name" = "#,
+                );
+                qb.push_bind(name.clone());
+                qb.push(")");
+            }
+            Term::Is(Kind::Photo) => {
+                qb.push(r#""image"."duration_ms" IS NULL"#);
+            }
+            Term::Is(Kind::Video) => {
+                qb.push(r#""image"."duration_ms" IS NOT NULL"#);
+            }
+            Term::Is(Kind::Live) => {
+                qb.push(r#""photo"."live_video_hash" IS NOT NULL"#);
+            }
+            Term::Is(Kind::Untagged) => {
+                qb.push(
+                    r#"NOT EXISTS (SELECT 1 FROM "photo_tag" "pt" WHERE "pt"."photo_id" = "photo"."id")"#,
+                );
+            }
+            Term::Is(Kind::Favorite) => {
+                qb.push(r#""photo"."is_favorite""#);
+            }
+            Term::Captured { start, end } => {
+                qb.push("(TRUE");
+                if let Some(start) = start {
+                    qb.push(" AND ")
+                        .push(LOCAL_CAPTURED_AT)
+                        .push(" >= ")
+                        .push_bind(*start);
+                }
+                if let Some(end) = end {
+                    qb.push(" AND ")
+                        .push(LOCAL_CAPTURED_AT)
+                        .push(" < ")
+                        .push_bind(*end);
+                }
+                // 没有拍摄时间的照片不属于任何日期
+                qb.push(r#" AND "photo"."captured_at" IS NOT NULL)"#);
+            }
+            Term::Location(text) => {
+                qb.push(r#""photo"."location" ILIKE "#)
+                    .push_bind(contains_pattern(text));
+            }
+            Term::Camera(text) => {
+                qb.push(
+                    r#"EXISTS (SELECT 1 FROM "photo_metadata" "m" WHERE "m"."photo_id" = "photo"."id" AND concat_ws(' ', "m"."camera_make", "m"."camera_model") ILIKE "#,
+                );
+                qb.push_bind(contains_pattern(text));
+                qb.push(")");
+            }
+            Term::Size(dimension, comparison) => {
+                let column = match dimension {
+                    Dimension::Width => DISPLAY_WIDTH,
+                    Dimension::Height => DISPLAY_HEIGHT,
+                };
+                qb.push(column);
+                match *comparison {
+                    Comparison::Eq(v) => qb.push(" = ").push_bind(v),
+                    Comparison::Lt(v) => qb.push(" < ").push_bind(v),
+                    Comparison::Le(v) => qb.push(" <= ").push_bind(v),
+                    Comparison::Gt(v) => qb.push(" > ").push_bind(v),
+                    Comparison::Ge(v) => qb.push(" >= ").push_bind(v),
+                    Comparison::Between(from, to) => qb
+                        .push(" BETWEEN ")
+                        .push_bind(from)
+                        .push(" AND ")
+                        .push_bind(to),
+                };
+            }
+            Term::Orientation(orientation) => {
+                let operator = match orientation {
+                    Orientation::Portrait => " < ",
+                    Orientation::Landscape => " > ",
+                    Orientation::Square => " = ",
+                };
+                qb.push(DISPLAY_WIDTH).push(operator).push(DISPLAY_HEIGHT);
+            }
+            Term::Format(format) => {
+                qb.push(r#""image"."extension" = "#)
+                    .push_bind(format.extension());
+            }
+            Term::Text(query) => {
+                qb.push(r#""photo"."search_vector" @@ to_tsquery('simple', "#)
+                    .push_bind(query.clone())
+                    .push(")");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn tag(name: &str) -> Expr {
+        Expr::Term(Term::Tag(name.to_string()))
+    }
+
+    fn error(query: &str) -> (String, usize, usize) {
+        let e = parse(query).unwrap_err();
+        (e.message, e.start, e.end)
+    }
+
+    #[test]
+    fn empty_query() {
+        assert_eq!(parse("").unwrap(), None);
+        assert_eq!(parse("   ").unwrap(), None);
+    }
+
+    #[test]
+    fn implicit_and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("cat dog OR bird").unwrap().unwrap(),
+            Expr::Or(vec![Expr::And(vec![tag("cat"), tag("dog")]), tag("bird")])
+        );
+        assert_eq!(
+            parse("cat AND (dog OR bird)").unwrap().unwrap(),
+            Expr::And(vec![tag("cat"), Expr::Or(vec![tag("dog"), tag("bird")])])
+        );
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(
+            parse("-cat NOT \"red car\"").unwrap().unwrap(),
+            Expr::And(vec![
+                Expr::Not(Box::new(tag("cat"))),
+                Expr::Not(Box::new(tag("red car"))),
+            ])
+        );
+        // 值中间的 `-` 不是 NOT
+        assert_eq!(parse("well-known").unwrap().unwrap(), tag("well-known"));
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(
+            parse("camera:\"iPhone 15\"").unwrap().unwrap(),
+            Expr::Term(Term::Camera("iPhone 15".to_string()))
+        );
+        assert_eq!(
+            parse("width:>=3000").unwrap().unwrap(),
+            Expr::Term(Term::Size(Dimension::Width, Comparison::Ge(3000)))
+        );
+        assert_eq!(
+            parse("height:600..800").unwrap().unwrap(),
+            Expr::Term(Term::Size(Dimension::Height, Comparison::Between(600, 800)))
+        );
+        assert_eq!(
+            parse("format:JPG").unwrap().unwrap(),
+            Expr::Term(Term::Format(MediaFormat::Jpeg))
+        );
+        assert_eq!(
+            parse("orientation:portrait").unwrap().unwrap(),
+            Expr::Term(Term::Orientation(Orientation::Portrait))
+        );
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(
+            parse("date:2024-02").unwrap().unwrap(),
+            Expr::Term(Term::Captured {
+                start: Some(datetime!(2024-02-01 0:00)),
+                end: Some(datetime!(2024-03-01 0:00)),
+            })
+        );
+        assert_eq!(
+            parse("date:2023-12-31..2024").unwrap().unwrap(),
+            Expr::Term(Term::Captured {
+                start: Some(datetime!(2023-12-31 0:00)),
+                end: Some(datetime!(2025-01-01 0:00)),
+            })
+        );
+        assert_eq!(
+            parse("after:2024-12").unwrap().unwrap(),
+            Expr::Term(Term::Captured {
+                start: Some(datetime!(2025-01-01 0:00)),
+                end: None,
+            })
+        );
+        assert_eq!(
+            parse("date:..2024-05-01").unwrap().unwrap(),
+            Expr::Term(Term::Captured {
+                start: None,
+                end: Some(datetime!(2024-05-02 0:00)),
+            })
+        );
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(
+            error("cat color:red"),
+            ("Unknown field `color`".to_string(), 4, 9)
+        );
+        assert_eq!(error("cat \"dog"), ("Unterminated quote".to_string(), 4, 8));
+        assert_eq!(
+            error("(cat dog"),
+            ("Missing closing parenthesis".to_string(), 0, 1)
+        );
+        assert_eq!(
+            error("cat)"),
+            ("Unexpected closing parenthesis".to_string(), 3, 4)
+        );
+        assert_eq!(
+            error("cat OR"),
+            ("Expected a condition after OR".to_string(), 4, 6)
+        );
+        assert_eq!(error("()"), ("Empty parentheses".to_string(), 0, 2));
+        assert_eq!(
+            error("date:2024-13").0,
+            "Invalid date `2024-13`, expected YYYY, YYYY-MM or YYYY-MM-DD"
+        );
+        assert_eq!(error("date:2024-02-30").1, 0);
+        assert_eq!(
+            error("date:2025..2024").0,
+            "Date range ends before it starts"
+        );
+        assert_eq!(
+            error("width:big").0.split(',').next(),
+            Some("Invalid size `big`")
+        );
+        assert_eq!(error("tag:").0, "Empty value");
+        // 位置按字符计算
+        assert_eq!(error("猫 x:y"), ("Unknown field `x`".to_string(), 2, 3));
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let query = format!("{}cat{}", "(".repeat(40), ")".repeat(40));
+        assert_eq!(error(&query).0, "Query is nested too deeply");
+        assert!(parse(&format!("{}cat", "NOT ".repeat(40))).is_err());
+    }
+
+    #[test]
+    fn sql_binds_values() {
+        let expr = parse("(kitten OR -location:\"50%\") camera:x_y")
+            .unwrap()
+            .unwrap();
+        let mut qb = QueryBuilder::<Postgres>::new("");
+        expr.push_sql(&mut qb);
+        let sql = qb.sql();
+        assert!(!sql.contains("kitten") && !sql.contains("50%"));
+        assert_eq!(sql.matches('$').count(), 3);
+        assert_eq!(contains_pattern("50%"), "%50\\%%");
+        assert_eq!(contains_pattern("x_y"), "%x\\_y%");
+    }
+
+    #[test]
+    fn text_search() {
+        assert_eq!(
+            prefix_tsquery("Kyoto  temple's"),
+            Some("kyoto:* & temple:* & s:*".to_string())
+        );
+        assert_eq!(
+            prefix_tsquery("东京 tower!"),
+            Some("东京:* & tower:*".to_string())
+        );
+        assert_eq!(prefix_tsquery("&|!:*()'"), None);
+        assert_eq!(
+            parse("text:\"new york\"").unwrap().unwrap(),
+            Expr::Term(Term::Text("new:* & york:*".to_string()))
+        );
+        assert_eq!(error("text:!!").0, "No searchable words in `!!`");
+    }
+}
@@ -0,0 +1,632 @@
+use std::borrow::Cow;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use time::PrimitiveDateTime;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::{
+    auth::AuthUser,
+    search::{self, Expr, Kind, Term},
+};
+
+/// 当前的筛选条件版本，结构不兼容地变化时递增，并在读取时转换旧版本
+const FILTER_VERSION: u32 = 1;
+const MAX_TAGS: usize = 50;
+/// `SmartFilter` 的全部字段，读取保存的定义时丢弃其余的键
+const FIELDS: &[&str] = &[
+    "version",
+    "tags",
+    "captured_from",
+    "captured_to",
+    "location",
+    "favorite",
+];
+
+fn internal_error(e: impl std::fmt::Debug) -> (StatusCode, String) {
+    tracing::error!(error = ?e, "Smart album operation failed");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error".to_string(),
+    )
+}
+
+fn not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Smart album not found".to_string())
+}
+
+/// 智能相册的筛选条件，以 JSON 保存在 `smart_album.filter`，各条件之间取交集。
+/// 接口拒绝未知字段；读取保存的定义时忽略未知字段、缺少的字段取默认值，因此新增条件不影响已保存的定义
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Validate)]
+#[serde(default, deny_unknown_fields)]
+#[validate(schema(function = "validate_filter"))]
+pub struct SmartFilter {
+    version: u32,
+    /// 任一标签
+    #[validate(custom(function = "validate_tags"))]
+    tags: Vec<String>,
+    /// 拍摄地的当地日期范围，格式与搜索的 `date:` 相同（`YYYY`、`YYYY-MM` 或 `YYYY-MM-DD`），包含两端
+    #[validate(custom(function = "validate_period"))]
+    captured_from: Option<String>,
+    #[validate(custom(function = "validate_period"))]
+    captured_to: Option<String>,
+    /// 地点的包含匹配，不区分大小写
+    #[validate(length(
+        min = 1,
+        max = 200,
+        message = "Location must be between 1 and 200 characters"
+    ))]
+    location: Option<String>,
+    /// `true` 只包含收藏的照片，`false` 只包含未收藏的
+    favorite: Option<bool>,
+}
+
+fn validation_error(code: &'static str, message: String) -> ValidationError {
+    ValidationError::new(code).with_message(Cow::Owned(message))
+}
+
+fn validate_tags(tags: &[String]) -> Result<(), ValidationError> {
+    if tags.len() > MAX_TAGS {
+        return Err(validation_error(
+            "tags",
+            format!("At most {} tags are allowed", MAX_TAGS),
+        ));
+    }
+    if tags.iter().any(|t| t.trim().is_empty()) {
+        return Err(validation_error(
+            "tags",
+            "Tags must not be empty".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_period(value: &str) -> Result<(), ValidationError> {
+    search::parse_period(value).map(|_| ()).ok_or_else(|| {
+        validation_error(
+            "date",
+            format!(
+                "Invalid date `{}`, expected YYYY, YYYY-MM or YYYY-MM-DD",
+                value
+            ),
+        )
+    })
+}
+
+fn validate_filter(filter: &SmartFilter) -> Result<(), ValidationError> {
+    if filter.version > FILTER_VERSION {
+        return Err(validation_error(
+            "version",
+            format!("Unsupported filter version {}", filter.version),
+        ));
+    }
+    if filter.tags.is_empty()
+        && filter.captured_from.is_none()
+        && filter.captured_to.is_none()
+        && filter.location.is_none()
+        && filter.favorite.is_none()
+    {
+        return Err(validation_error(
+            "empty",
+            "Filter must have at least one condition".to_string(),
+        ));
+    }
+    if let (Some(start), Some(end)) = filter.period_bounds()
+        && start >= end
+    {
+        return Err(validation_error(
+            "date",
+            "Date range ends before it starts".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl SmartFilter {
+    /// 日期范围的开始和结束（不含），`captured_to` 当天或当月的照片也包含在内
+    fn period_bounds(&self) -> (Option<PrimitiveDateTime>, Option<PrimitiveDateTime>) {
+        (
+            self.captured_from
+                .as_deref()
+                .and_then(search::parse_period)
+                .map(|(start, _)| start),
+            self.captured_to
+                .as_deref()
+                .and_then(search::parse_period)
+                .map(|(_, end)| end),
+        )
+    }
+
+    /// 去除地点两端的空白，在校验之前调用
+    fn trim(&mut self) {
+        self.location = self.location.take().map(|v| v.trim().to_string());
+    }
+
+    /// 转换为搜索条件，与 `/photos/list` 的筛选共用同一套 SQL；没有条件时为空
+    pub fn to_expr(&self) -> Option<Expr> {
+        let mut items = Vec::new();
+        if !self.tags.is_empty() {
+            items.push(Expr::Or(
+                self.tags
+                    .iter()
+                    .map(|t| Expr::Term(Term::Tag(t.trim().to_string())))
+                    .collect(),
+            ));
+        }
+        let (start, end) = self.period_bounds();
+        if start.is_some() || end.is_some() {
+            items.push(Expr::Term(Term::Captured { start, end }));
+        }
+        if let Some(location) = &self.location {
+            items.push(Expr::Term(Term::Location(location.clone())));
+        }
+        match self.favorite {
+            Some(true) => items.push(Expr::Term(Term::Is(Kind::Favorite))),
+            Some(false) => items.push(Expr::Not(Box::new(Expr::Term(Term::Is(Kind::Favorite))))),
+            None => {}
+        }
+        match items.len() {
+            0 => None,
+            1 => items.pop(),
+            _ => Some(Expr::And(items)),
+        }
+    }
+
+    /// 解析保存的定义，旧版本在这里转换为当前结构
+    fn from_stored(value: &str) -> Result<Self, serde_json::Error> {
+        let mut fields: serde_json::Map<String, serde_json::Value> = serde_json::from_str(value)?;
+        fields.retain(|k, _| FIELDS.contains(&k.as_str()));
+        let mut filter: SmartFilter = serde_json::from_value(serde_json::Value::Object(fields))?;
+        filter.version = FILTER_VERSION;
+        Ok(filter)
+    }
+
+    fn to_stored(&self) -> String {
+        let mut filter = self.clone();
+        filter.version = FILTER_VERSION;
+        serde_json::to_string(&filter).unwrap_or_else(|_| "{}".to_string())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SmartAlbum {
+    id: String,
+    name: String,
+    filter: SmartFilter,
+    /// 当前符合条件的照片数，不含回收站中的照片
+    photo_count: i64,
+    /// 符合条件的最新一张照片，没有照片时为空
+    cover_photo_id: Option<String>,
+    created_at: i64,
+    updated_at: i64,
+}
+
+struct SmartAlbumRow {
+    id: Uuid,
+    name: String,
+    filter: String,
+    created_at: time::OffsetDateTime,
+    updated_at: time::OffsetDateTime,
+}
+
+/// 计算每个筛选条件符合的照片数和最新一张照片，结果与 `filters` 一一对应。
+/// 每个条件是一条 `UNION ALL` 分支，整个列表只需一次查询
+async fn evaluate(
+    db: &PgPool,
+    user_id: Uuid,
+    filters: &[SmartFilter],
+) -> Result<Vec<(i64, Option<Uuid>)>, sqlx::Error> {
+    let mut results = vec![(0, None); filters.len()];
+    if filters.is_empty() {
+        return Ok(results);
+    }
+
+    let mut qb = sqlx::QueryBuilder::new("");
+    for (index, filter) in filters.iter().enumerate() {
+        if index > 0 {
+            qb.push(" UNION ALL ");
+        }
+        qb.push("(SELECT ");
+        qb.push_bind(index as i32);
+        qb.push(
+            r#", COUNT(*) OVER (), "photo"."id"
+            FROM "photo"
+            JOIN "image" ON "photo"."image_hash" = "image"."hash"
+            WHERE "photo"."deleted_at" IS NULL AND "photo"."user_id" = "#,
+        );
+        qb.push_bind(user_id);
+        if let Some(expr) = filter.to_expr() {
+            qb.push(" AND ");
+            expr.push_sql(&mut qb);
+        }
+        qb.push(r#" ORDER BY "photo"."timeline_at" DESC, "photo"."id" DESC LIMIT 1)"#);
+    }
+
+    let rows: Vec<(i32, i64, Uuid)> = qb.build_query_as().fetch_all(db).await?;
+    for (index, count, id) in rows {
+        results[index as usize] = (count, Some(id));
+    }
+    Ok(results)
+}
+
+async fn to_smart_albums(
+    db: &PgPool,
+    user_id: Uuid,
+    rows: Vec<SmartAlbumRow>,
+) -> Result<Vec<SmartAlbum>, (StatusCode, String)> {
+    let filters = rows
+        .iter()
+        .map(|row| SmartFilter::from_stored(&row.filter))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(internal_error)?;
+    let results = evaluate(db, user_id, &filters)
+        .await
+        .map_err(internal_error)?;
+    Ok(rows
+        .into_iter()
+        .zip(filters)
+        .zip(results)
+        .map(
+            |((row, filter), (photo_count, cover_photo_id))| SmartAlbum {
+                id: row.id.to_string(),
+                name: row.name,
+                filter,
+                photo_count,
+                cover_photo_id: cover_photo_id.map(|id| id.to_string()),
+                created_at: row.created_at.unix_timestamp(),
+                updated_at: row.updated_at.unix_timestamp(),
+            },
+        )
+        .collect())
+}
+
+async fn to_smart_album(
+    db: &PgPool,
+    user_id: Uuid,
+    row: SmartAlbumRow,
+) -> Result<SmartAlbum, (StatusCode, String)> {
+    let mut smart_albums = to_smart_albums(db, user_id, vec![row]).await?;
+    Ok(smart_albums.remove(0))
+}
+
+async fn fetch_row(
+    db: &PgPool,
+    user_id: Uuid,
+    smart_album_id: Uuid,
+) -> Result<SmartAlbumRow, (StatusCode, String)> {
+    sqlx::query_as!(
+        SmartAlbumRow,
+        r#"
+        SELECT "id", "name", "filter"::TEXT as "filter!", "created_at", "updated_at"
+        FROM "smart_album"
+        WHERE "id" = $1 AND "user_id" = $2
+        "#,
+        smart_album_id,
+        user_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(internal_error)?
+    .ok_or_else(not_found)
+}
+
+/// 读取智能相册的筛选条件，供列出照片时使用
+pub async fn get_filter(
+    db: &PgPool,
+    user_id: Uuid,
+    smart_album_id: Uuid,
+) -> Result<SmartFilter, (StatusCode, String)> {
+    let row = fetch_row(db, user_id, smart_album_id).await?;
+    SmartFilter::from_stored(&row.filter).map_err(internal_error)
+}
+
+#[derive(Serialize)]
+pub struct ListSmartAlbumsResponse {
+    smart_albums: Vec<SmartAlbum>,
+}
+
+pub async fn list_smart_albums_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Response, (StatusCode, String)> {
+    let rows = sqlx::query_as!(
+        SmartAlbumRow,
+        r#"
+        SELECT "id", "name", "filter"::TEXT as "filter!", "created_at", "updated_at"
+        FROM "smart_album"
+        WHERE "user_id" = $1
+        ORDER BY "created_at" DESC, "id" DESC
+        "#,
+        user_id
+    )
+    .fetch_all(&db)
+    .await
+    .map_err(internal_error)?;
+
+    let smart_albums = to_smart_albums(&db, user_id, rows).await?;
+    Ok(Json(ListSmartAlbumsResponse { smart_albums }).into_response())
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CreateSmartAlbumPayload {
+    #[validate(length(
+        min = 1,
+        max = 200,
+        message = "Name must be between 1 and 200 characters"
+    ))]
+    name: String,
+    #[validate(nested)]
+    filter: SmartFilter,
+}
+
+pub async fn create_smart_album_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Json(mut payload): Json<CreateSmartAlbumPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    payload.name = payload.name.trim().to_string();
+    payload.filter.trim();
+    if let Err(e) = payload.validate() {
+        let body = Json(json!({
+            "details": e
+        }));
+        return Ok((StatusCode::BAD_REQUEST, body).into_response());
+    }
+
+    let row = sqlx::query_as!(
+        SmartAlbumRow,
+        r#"
+        INSERT INTO "smart_album" ("id", "user_id", "name", "filter")
+        VALUES ($1, $2, $3, $4::TEXT::JSONB)
+        RETURNING "id", "name", "filter"::TEXT as "filter!", "created_at", "updated_at"
+        "#,
+        Uuid::now_v7(),
+        user_id,
+        payload.name,
+        payload.filter.to_stored()
+    )
+    .fetch_one(&db)
+    .await
+    .map_err(internal_error)?;
+
+    let smart_album = to_smart_album(&db, user_id, row).await?;
+    Ok((StatusCode::CREATED, Json(smart_album)).into_response())
+}
+
+pub async fn get_smart_album_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(smart_album_id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let row = fetch_row(&db, user_id, smart_album_id).await?;
+    let smart_album = to_smart_album(&db, user_id, row).await?;
+    Ok(Json(smart_album).into_response())
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateSmartAlbumPayload {
+    #[validate(length(
+        min = 1,
+        max = 200,
+        message = "Name must be between 1 and 200 characters"
+    ))]
+    name: Option<String>,
+    /// 整体替换筛选条件
+    #[validate(nested)]
+    filter: Option<SmartFilter>,
+}
+
+/// 修改智能相册的名称或筛选条件，省略的字段保持不变
+pub async fn update_smart_album_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(smart_album_id): Path<Uuid>,
+    Json(mut payload): Json<UpdateSmartAlbumPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    payload.name = payload.name.map(|v| v.trim().to_string());
+    if let Some(filter) = &mut payload.filter {
+        filter.trim();
+    }
+    if let Err(e) = payload.validate() {
+        let body = Json(json!({
+            "details": e
+        }));
+        return Ok((StatusCode::BAD_REQUEST, body).into_response());
+    }
+
+    let row = sqlx::query_as!(
+        SmartAlbumRow,
+        r#"
+        UPDATE "smart_album" SET
+            "name" = COALESCE($3, "name"),
+            "filter" = COALESCE($4::TEXT::JSONB, "filter")
+        WHERE "id" = $1 AND "user_id" = $2
+        RETURNING "id", "name", "filter"::TEXT as "filter!", "created_at", "updated_at"
+        "#,
+        smart_album_id,
+        user_id,
+        payload.name,
+        payload.filter.as_ref().map(SmartFilter::to_stored)
+    )
+    .fetch_optional(&db)
+    .await
+    .map_err(internal_error)?
+    .ok_or_else(not_found)?;
+
+    let smart_album = to_smart_album(&db, user_id, row).await?;
+    Ok(Json(smart_album).into_response())
+}
+
+pub async fn delete_smart_album_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(smart_album_id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let result = sqlx::query!(
+        r#"DELETE FROM "smart_album" WHERE "id" = $1 AND "user_id" = $2"#,
+        smart_album_id,
+        user_id
+    )
+    .execute(&db)
+    .await
+    .map_err(internal_error)?;
+    if result.rows_affected() == 0 {
+        return Err(not_found());
+    }
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn filter(value: serde_json::Value) -> SmartFilter {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn tag(name: &str) -> Expr {
+        Expr::Term(Term::Tag(name.to_string()))
+    }
+
+    #[test]
+    fn unknown_fields() {
+        assert!(
+            serde_json::from_value::<SmartFilter>(json!({"tags": ["cat"], "album": 1})).is_err()
+        );
+        assert_eq!(
+            SmartFilter::from_stored(r#"{"tags": ["cat"], "album": 1}"#).unwrap(),
+            filter(json!({"version": FILTER_VERSION, "tags": ["cat"]}))
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let ok = [
+            json!({"tags": ["cat"]}),
+            json!({"favorite": false}),
+            json!({"captured_from": "2024", "captured_to": "2024"}),
+            json!({"captured_from": "2024-03-01", "captured_to": "2024-03"}),
+            json!({"location": "Paris"}),
+        ];
+        for value in ok {
+            assert!(filter(value.clone()).validate().is_ok(), "{value}");
+        }
+
+        let err = [
+            json!({}),
+            json!({"tags": []}),
+            json!({"tags": [" "]}),
+            json!({"tags": vec!["cat"; MAX_TAGS + 1]}),
+            json!({"version": FILTER_VERSION + 1, "favorite": true}),
+            json!({"captured_from": "2024-13"}),
+            json!({"captured_from": "2024-03-02", "captured_to": "2024-03-01"}),
+            json!({"captured_from": "2025", "captured_to": "2024"}),
+            json!({"location": ""}),
+        ];
+        for value in err {
+            assert!(filter(value.clone()).validate().is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn trim() {
+        let mut f = filter(json!({"location": "  Paris "}));
+        f.trim();
+        assert_eq!(f.location.as_deref(), Some("Paris"));
+
+        let mut f = filter(json!({"location": "   "}));
+        f.trim();
+        assert!(f.validate().is_err());
+    }
+
+    #[test]
+    fn to_expr() {
+        assert_eq!(SmartFilter::default().to_expr(), None);
+        assert_eq!(
+            filter(json!({"tags": [" cat ", "dog"]})).to_expr(),
+            Some(Expr::Or(vec![tag("cat"), tag("dog")]))
+        );
+        assert_eq!(
+            filter(json!({"favorite": false})).to_expr(),
+            Some(Expr::Not(Box::new(Expr::Term(Term::Is(Kind::Favorite)))))
+        );
+        assert_eq!(
+            filter(json!({
+                "tags": ["cat"],
+                "captured_from": "2024-03",
+                "captured_to": "2024",
+                "location": "Paris",
+                "favorite": true,
+            }))
+            .to_expr(),
+            Some(Expr::And(vec![
+                Expr::Or(vec![tag("cat")]),
+                Expr::Term(Term::Captured {
+                    start: Some(datetime!(2024-03-01 00:00)),
+                    end: Some(datetime!(2025-01-01 00:00)),
+                }),
+                Expr::Term(Term::Location("Paris".to_string())),
+                Expr::Term(Term::Is(Kind::Favorite)),
+            ]))
+        );
+        assert_eq!(
+            filter(json!({"captured_to": "2024-02"})).to_expr(),
+            Some(Expr::Term(Term::Captured {
+                start: None,
+                end: Some(datetime!(2024-03-01 00:00)),
+            }))
+        );
+    }
+
+    #[test]
+    fn stored() {
+        let f = filter(json!({
+            "tags": ["cat"],
+            "captured_from": "2024",
+            "location": "Paris",
+            "favorite": true,
+        }));
+        let stored = f.to_stored();
+        let value: serde_json::Value = serde_json::from_str(&stored).unwrap();
+        assert_eq!(value["version"], FILTER_VERSION);
+        assert_eq!(
+            SmartFilter::from_stored(&stored).unwrap(),
+            SmartFilter {
+                version: FILTER_VERSION,
+                ..f
+            }
+        );
+
+        // 旧的定义缺少版本和后来新增的字段
+        assert_eq!(
+            SmartFilter::from_stored(r#"{"tags": ["cat"]}"#).unwrap(),
+            filter(json!({"version": FILTER_VERSION, "tags": ["cat"]}))
+        );
+        assert!(SmartFilter::from_stored("[]").is_err());
+    }
+
+    #[test]
+    fn fields() {
+        let value = serde_json::to_value(SmartFilter::default()).unwrap();
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        let mut fields = FIELDS.to_vec();
+        keys.sort();
+        fields.sort();
+        assert_eq!(keys, fields);
+    }
+}
@@ -9,6 +9,17 @@ use sqlx::PgPool;
 
 use crate::auth::AuthUser;
 
+/// 解析逗号分隔的标签列表，忽略空白项，全部为空时返回 `None`
+pub fn parse_tag_list(tags: Option<String>) -> Option<Vec<String>> {
+    tags.map(|s| {
+        s.split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect()
+    })
+    .filter(|v: &Vec<String>| !v.is_empty())
+}
+
 #[derive(Serialize)]
 pub struct TagWithCount {
     name: String,
@@ -22,7 +33,7 @@ pub struct ListTagsResponse {
 
 pub async fn list_tags_handler(
     State(db): State<PgPool>,
-    AuthUser { user_id }: AuthUser,
+    AuthUser { user_id, .. }: AuthUser,
 ) -> Result<Response, (StatusCode, String)> {
     let tags = sqlx::query_as!(
         TagWithCount,
@@ -31,7 +42,7 @@ pub async fn list_tags_handler(
         FROM "tag" "t"
         JOIN "photo_tag" "pt" ON "t"."id" = "pt"."tag_id"
         JOIN "photo" "p" ON "pt"."photo_id" = "p"."id"
-        WHERE "p"."user_id" = $1
+        WHERE "p"."user_id" = $1 AND "p"."deleted_at" IS NULL
         GROUP BY "t"."name"
         HAVING COUNT("pt"."photo_id") > 0
         ORDER BY "count!" DESC
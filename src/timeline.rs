@@ -0,0 +1,196 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::{Date, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{auth::AuthUser, search, tags::parse_tag_list};
+
+/// 时间线分页的位置：上一页最后一张照片的 `(timeline_at, id)`，下一页从它之后开始
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+    pub at: OffsetDateTime,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    /// 编码为不透明的字符串，客户端只需原样传回
+    pub fn encode(&self) -> String {
+        let micros = self.at.unix_timestamp_nanos() / 1000;
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(format!("{}:{}", micros, self.id))
+    }
+
+    pub fn decode(value: &str) -> Option<Self> {
+        let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(value)
+            .ok()?;
+        let value = String::from_utf8(bytes).ok()?;
+        let (micros, id) = value.split_once(':')?;
+        let micros: i128 = micros.parse().ok()?;
+        Some(Self {
+            at: OffsetDateTime::from_unix_timestamp_nanos(micros.checked_mul(1000)?).ok()?,
+            id: Uuid::parse_str(id).ok()?,
+        })
+    }
+}
+
+pub fn invalid_cursor() -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, "Invalid cursor".to_string())
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    #[default]
+    Day,
+    Month,
+}
+
+impl Granularity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Month => "month",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BucketParams {
+    #[serde(default)]
+    granularity: Granularity,
+    q: Option<String>,
+    tags: Option<String>,
+    untagged: Option<bool>,
+    favorite: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct Bucket {
+    /// 拍摄地的当地日期，例如 `2024-05-01`；按月分组时为 `2024-05`
+    date: String,
+    count: i64,
+    /// 从这个分组的第一张照片开始列出的分页位置，用于在时间线上跳转
+    cursor: String,
+}
+
+#[derive(Serialize)]
+pub struct BucketsResponse {
+    buckets: Vec<Bucket>,
+}
+
+/// 按天或按月统计照片数量，从新到旧排列，供前端绘制可拖动的时间轴。
+/// 筛选参数与 `/photos/list` 相同
+pub async fn buckets_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Query(params): Query<BucketParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let filter = match search::build_filter(
+        params.q.as_deref(),
+        parse_tag_list(params.tags),
+        params.untagged.unwrap_or(false),
+        params.favorite,
+    ) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(e.into_response()),
+    };
+
+    // 按拍摄地的当地时间分组，时区未知的按 UTC
+    let mut qb = sqlx::QueryBuilder::new(
+        r#"
+        SELECT
+            date_trunc("#,
+    );
+    qb.push_bind(params.granularity.as_str());
+    qb.push(
+        r#",
+                ("photo"."timeline_at" AT TIME ZONE 'UTC')
+                    + make_interval(secs => COALESCE("photo"."captured_at_offset", 0))
+            )::date as "date",
+            COUNT(*) as "count",
+            MAX("photo"."timeline_at") as "latest"
+        FROM "photo"
+        JOIN "image" ON "photo"."image_hash" = "image"."hash"
+        WHERE "photo"."deleted_at" IS NULL AND "photo"."user_id" = "#,
+    );
+    qb.push_bind(user_id);
+    if let Some(filter) = &filter {
+        qb.push(" AND ");
+        filter.push_sql(&mut qb);
+    }
+    qb.push(" GROUP BY 1 ORDER BY 1 DESC");
+
+    let rows: Vec<(Date, i64, OffsetDateTime)> =
+        qb.build_query_as().fetch_all(&db).await.map_err(|e| {
+            tracing::error!(error = ?e, "Failed to fetch timeline buckets");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal server error".to_string(),
+            )
+        })?;
+
+    let format = match params.granularity {
+        Granularity::Day => time::macros::format_description!("[year]-[month]-[day]"),
+        Granularity::Month => time::macros::format_description!("[year]-[month]"),
+    };
+    let buckets = rows
+        .into_iter()
+        .map(|(date, count, latest)| Bucket {
+            date: date.format(format).unwrap_or_default(),
+            count,
+            // 同一时刻的照片按 id 降序排列，最大的 id 之后就是这一时刻的第一张
+            cursor: Cursor {
+                at: latest,
+                id: Uuid::max(),
+            }
+            .encode(),
+        })
+        .collect();
+
+    Ok(Json(BucketsResponse { buckets }).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn encode(value: &str) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(value)
+    }
+
+    #[test]
+    fn cursor() {
+        let id = Uuid::parse_str("0190a6f2-3c4d-7e8f-9a0b-1c2d3e4f5a6b").unwrap();
+        for at in [
+            datetime!(2024-05-01 12:34:56.789123 UTC),
+            datetime!(1969-12-31 23:59:59.5 UTC),
+            OffsetDateTime::UNIX_EPOCH,
+        ] {
+            let decoded = Cursor::decode(&Cursor { at, id }.encode()).unwrap();
+            assert_eq!(decoded.at, at);
+            assert_eq!(decoded.id, id);
+        }
+
+        let invalid = [
+            "".to_string(),
+            "not base64!".to_string(),
+            encode("1714566896789123"),
+            encode("1714566896789123:not-a-uuid"),
+            encode("abc:0190a6f2-3c4d-7e8f-9a0b-1c2d3e4f5a6b"),
+            encode(&format!("{}:{}", i128::MAX, id)),
+            encode(&format!("{}:{}", i64::MAX, id)),
+        ];
+        for value in invalid {
+            assert!(Cursor::decode(&value).is_none(), "{value}");
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use sqlx::PgPool;
+use std::time::Duration;
+
+use crate::config::TrashConfig;
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 永久删除在回收站中超过保留期的照片，返回删除的数量
+pub async fn purge_expired(db: &PgPool, retention_days: u32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"DELETE FROM "photo" WHERE "deleted_at" < NOW() - $1::integer * INTERVAL '1 day'"#,
+        i32::try_from(retention_days).unwrap_or(i32::MAX)
+    )
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// 启动后台任务，每小时清理一次回收站
+pub fn spawn_purge_task(db: PgPool, config: TrashConfig) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match purge_expired(&db, config.retention_days).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "Purged expired photos from trash"),
+                Err(e) => tracing::error!(error = ?e, "Failed to purge trash"),
+            }
+        }
+    })
+}
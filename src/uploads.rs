@@ -0,0 +1,563 @@
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, TryStreamExt, stream};
+use reverse_geocoder::ReverseGeocoder;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use std::{sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthUser,
+    config::UploadConfig,
+    images::{StageError, StagedFile},
+    infra::storage::{SharedStorage, Storage, StorageError, is_content_hash},
+    photos::{self, MAX_UPLOAD_FILE_SIZE},
+};
+
+/// 单个分块的最大大小
+pub const MAX_CHUNK_SIZE: u64 = 16 * 1024 * 1024; // 16MB
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+fn internal_error(e: impl std::fmt::Debug) -> (StatusCode, String) {
+    tracing::error!(error = ?e, "Upload session operation failed");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error".to_string(),
+    )
+}
+
+fn not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Upload not found".to_string())
+}
+
+/// 分块在对象存储中的 key
+fn chunk_key(upload_id: Uuid, index: i32) -> String {
+    format!("{}.chunk{}", upload_id.simple(), index)
+}
+
+struct UploadSession {
+    id: Uuid,
+    file_name: Option<String>,
+    size: i64,
+    sha256: String,
+    expires_at: time::OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct UploadChunk {
+    index: i32,
+    size: i64,
+}
+
+async fn get_session(
+    db: &PgPool,
+    user_id: Uuid,
+    upload_id: Uuid,
+) -> Result<UploadSession, (StatusCode, String)> {
+    sqlx::query_as!(
+        UploadSession,
+        r#"
+        SELECT "id", "file_name", "size", "sha256", "expires_at"
+        FROM "upload_session"
+        WHERE "id" = $1 AND "user_id" = $2 AND "expires_at" > NOW()
+        "#,
+        upload_id,
+        user_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(internal_error)?
+    .ok_or_else(not_found)
+}
+
+/// 检查新的分块加上其余已收到的分块不超过声明的文件大小
+fn check_chunk_fits(received: i64, chunk_size: i64, size: i64) -> Result<(), (StatusCode, String)> {
+    if received + chunk_size > size {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Chunks exceed the declared file size".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// 检查分块序号从 0 开始连续，且总大小等于声明的文件大小
+fn check_complete(chunks: &[UploadChunk], size: i64) -> Result<(), (StatusCode, String)> {
+    let contiguous = chunks.iter().enumerate().all(|(i, c)| c.index == i as i32);
+    let received_bytes: i64 = chunks.iter().map(|c| c.size).sum();
+    if !contiguous || received_bytes != size {
+        return Err((
+            StatusCode::CONFLICT,
+            format!(
+                "Upload is incomplete, received {} of {} bytes",
+                received_bytes, size
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// 按序读取分块并合并到临时文件
+async fn stage_chunks(
+    storage: SharedStorage,
+    upload_id: Uuid,
+    chunks: Vec<UploadChunk>,
+    size: u64,
+) -> Result<StagedFile, StageError> {
+    let chunk_stream = stream::iter(chunks)
+        .then(move |chunk| {
+            let storage = storage.clone();
+            async move {
+                storage
+                    .read(&chunk_key(upload_id, chunk.index), None)
+                    .await
+                    .map(ReaderStream::new)
+                    .map_err(std::io::Error::other)
+            }
+        })
+        .try_flatten()
+        .boxed();
+    StagedFile::from_stream(chunk_stream, size).await
+}
+
+async fn get_chunks(db: &PgPool, upload_id: Uuid) -> Result<Vec<UploadChunk>, sqlx::Error> {
+    sqlx::query_as!(
+        UploadChunk,
+        r#"SELECT "index", "size" FROM "upload_chunk" WHERE "session_id" = $1 ORDER BY "index""#,
+        upload_id
+    )
+    .fetch_all(db)
+    .await
+}
+
+async fn status_response(
+    db: &PgPool,
+    session: &UploadSession,
+) -> Result<Response, (StatusCode, String)> {
+    let chunks = get_chunks(db, session.id).await.map_err(internal_error)?;
+    let received_bytes: i64 = chunks.iter().map(|c| c.size).sum();
+    Ok(Json(json!({
+        "upload_id": session.id.to_string(),
+        "file_name": session.file_name,
+        "size": session.size,
+        "received_bytes": received_bytes,
+        "chunks": chunks,
+        "max_chunk_size": MAX_CHUNK_SIZE,
+        "expires_at": session.expires_at.unix_timestamp(),
+    }))
+    .into_response())
+}
+
+/// 删除会话及其已上传的分块
+async fn delete_session(
+    db: &PgPool,
+    storage: &dyn Storage,
+    upload_id: Uuid,
+) -> Result<(), UploadError> {
+    let keys = sqlx::query_scalar!(
+        r#"SELECT "storage_key" FROM "upload_chunk" WHERE "session_id" = $1"#,
+        upload_id
+    )
+    .fetch_all(db)
+    .await?;
+    for key in &keys {
+        storage.delete(key).await?;
+    }
+    sqlx::query!(r#"DELETE FROM "upload_session" WHERE "id" = $1"#, upload_id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct CreateUploadPayload {
+    file_name: Option<String>,
+    /// 仅作记录，格式在合并后按文件内容识别
+    content_type: String,
+    size: u64,
+    /// 完整文件的 SHA256 HEX
+    sha256: String,
+}
+
+pub async fn create_upload_handler(
+    State(db): State<PgPool>,
+    State(config): State<Arc<UploadConfig>>,
+    AuthUser { user_id, .. }: AuthUser,
+    Json(payload): Json<CreateUploadPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    if payload.size == 0 || payload.size > MAX_UPLOAD_FILE_SIZE {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "File size must be between 1 and {} bytes",
+                MAX_UPLOAD_FILE_SIZE
+            ),
+        ));
+    }
+    let sha256 = payload.sha256.to_ascii_lowercase();
+    if !is_content_hash(&sha256) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid sha256".to_string()));
+    }
+
+    let session = sqlx::query_as!(
+        UploadSession,
+        r#"
+        INSERT INTO "upload_session" ("id", "user_id", "file_name", "content_type", "size", "sha256", "expires_at")
+        VALUES ($1, $2, $3, $4, $5, $6, NOW() + $7::integer * INTERVAL '1 hour')
+        RETURNING "id", "file_name", "size", "sha256", "expires_at"
+        "#,
+        Uuid::now_v7(),
+        user_id,
+        payload.file_name,
+        payload.content_type,
+        payload.size as i64,
+        sha256,
+        i32::try_from(config.session_ttl_hours).unwrap_or(i32::MAX)
+    )
+    .fetch_one(&db)
+    .await
+    .map_err(internal_error)?;
+
+    status_response(&db, &session).await
+}
+
+pub async fn get_upload_handler(
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(upload_id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let session = get_session(&db, user_id, upload_id).await?;
+    status_response(&db, &session).await
+}
+
+/// 上传一个分块，重复上传同一序号的分块会覆盖之前的内容，便于客户端重试
+pub async fn put_chunk_handler(
+    State(storage): State<SharedStorage>,
+    State(db): State<PgPool>,
+    State(config): State<Arc<UploadConfig>>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path((upload_id, index)): Path<(Uuid, u32)>,
+    body: Bytes,
+) -> Result<Response, (StatusCode, String)> {
+    let index = i32::try_from(index)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid chunk index".to_string()))?;
+    if body.is_empty() || body.len() as u64 > MAX_CHUNK_SIZE {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Chunk size must be between 1 and {} bytes", MAX_CHUNK_SIZE),
+        ));
+    }
+
+    // 锁住会话直到分块写入完成，避免并发上传的分块各自通过大小检查后总和超出声明的大小
+    let mut tx = db.begin().await.map_err(internal_error)?;
+    let declared_size = sqlx::query_scalar!(
+        r#"
+        SELECT "size" FROM "upload_session"
+        WHERE "id" = $1 AND "user_id" = $2 AND "expires_at" > NOW()
+        FOR UPDATE
+        "#,
+        upload_id,
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal_error)?
+    .ok_or_else(not_found)?;
+    let received = sqlx::query_scalar!(
+        r#"SELECT COALESCE(SUM("size"), 0)::bigint as "received!" FROM "upload_chunk" WHERE "session_id" = $1 AND "index" != $2"#,
+        upload_id,
+        index
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    let size = body.len() as i64;
+    check_chunk_fits(received, size, declared_size)?;
+
+    let key = chunk_key(upload_id, index);
+    storage.save(&key, body).await.map_err(internal_error)?;
+    sqlx::query!(
+        r#"
+        INSERT INTO "upload_chunk" ("session_id", "index", "size", "storage_key") VALUES ($1, $2, $3, $4)
+        ON CONFLICT ("session_id", "index") DO UPDATE SET "size" = EXCLUDED."size", "created_at" = NOW()
+        "#,
+        upload_id,
+        index,
+        size,
+        key
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+
+    // 每次活动都延长会话的有效期
+    let session = sqlx::query_as!(
+        UploadSession,
+        r#"
+        UPDATE "upload_session" SET "expires_at" = NOW() + $2::integer * INTERVAL '1 hour'
+        WHERE "id" = $1
+        RETURNING "id", "file_name", "size", "sha256", "expires_at"
+        "#,
+        upload_id,
+        i32::try_from(config.session_ttl_hours).unwrap_or(i32::MAX)
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    status_response(&db, &session).await
+}
+
+/// 按序合并全部分块，校验 SHA256 后创建照片，成功后会话被删除
+pub async fn complete_upload_handler(
+    State(storage): State<SharedStorage>,
+    State(db): State<PgPool>,
+    State(geocoder): State<Arc<ReverseGeocoder>>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(upload_id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let session = get_session(&db, user_id, upload_id).await?;
+    let chunks = get_chunks(&db, upload_id).await.map_err(internal_error)?;
+    check_complete(&chunks, session.size)?;
+
+    let file = stage_chunks(storage.clone(), upload_id, chunks, session.size as u64)
+        .await
+        .map_err(internal_error)?;
+
+    // 校验失败时保留会话，客户端可以重新上传分块
+    if file.hash != session.sha256 {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "Checksum mismatch, expected {} but got {}",
+                session.sha256, file.hash
+            ),
+        ));
+    }
+
+    let result = photos::create_photo(
+        &file,
+        session.file_name,
+        user_id,
+        storage.as_ref(),
+        &db,
+        &geocoder,
+    )
+    .await?;
+
+    delete_session(&db, storage.as_ref(), upload_id)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(result).into_response())
+}
+
+pub async fn delete_upload_handler(
+    State(storage): State<SharedStorage>,
+    State(db): State<PgPool>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(upload_id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let session = get_session(&db, user_id, upload_id).await?;
+    delete_session(&db, storage.as_ref(), session.id)
+        .await
+        .map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// 删除过期的上传会话及其分块，返回删除的会话数量
+pub async fn expire_sessions(db: &PgPool, storage: &dyn Storage) -> Result<u64, UploadError> {
+    let expired =
+        sqlx::query_scalar!(r#"SELECT "id" FROM "upload_session" WHERE "expires_at" <= NOW()"#)
+            .fetch_all(db)
+            .await?;
+    for upload_id in &expired {
+        delete_session(db, storage, *upload_id).await?;
+    }
+    Ok(expired.len() as u64)
+}
+
+/// 启动后台任务，每小时清理一次过期的上传会话
+pub fn spawn_expire_task(db: PgPool, storage: SharedStorage) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match expire_sessions(&db, storage.as_ref()).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "Removed expired upload sessions"),
+                Err(e) => tracing::error!(error = ?e, "Failed to remove expired upload sessions"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+    use crate::infra::storage::memory::MemoryStorage;
+
+    fn chunks(sizes: &[(i32, i64)]) -> Vec<UploadChunk> {
+        sizes
+            .iter()
+            .map(|&(index, size)| UploadChunk { index, size })
+            .collect()
+    }
+
+    #[test]
+    fn chunk_fits() {
+        assert!(check_chunk_fits(0, 10, 10).is_ok());
+        assert!(check_chunk_fits(6, 4, 10).is_ok());
+        assert!(check_chunk_fits(6, 3, 10).is_ok());
+        assert_eq!(
+            check_chunk_fits(6, 5, 10).unwrap_err().0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn complete() {
+        assert!(check_complete(&chunks(&[(0, 10)]), 10).is_ok());
+        assert!(check_complete(&chunks(&[(0, 4), (1, 4), (2, 2)]), 10).is_ok());
+
+        let incomplete = [
+            // 没有分块
+            (chunks(&[]), 10),
+            // 缺少第一个分块
+            (chunks(&[(1, 10)]), 10),
+            // 中间缺少分块
+            (chunks(&[(0, 4), (2, 6)]), 10),
+            // 大小不足
+            (chunks(&[(0, 4), (1, 4)]), 10),
+            // 大小超出
+            (chunks(&[(0, 4), (1, 7)]), 10),
+        ];
+        for (chunks, size) in incomplete {
+            let (status, _) = check_complete(&chunks, size).unwrap_err();
+            assert_eq!(status, StatusCode::CONFLICT);
+        }
+    }
+
+    #[tokio::test]
+    async fn stage_chunks_in_order() {
+        let storage: SharedStorage = Arc::new(MemoryStorage::new());
+        let upload_id = Uuid::now_v7();
+        for (index, data) in [b"hello, ".as_slice(), b"chunked ", b"world"]
+            .iter()
+            .enumerate()
+        {
+            storage
+                .save(
+                    &chunk_key(upload_id, index as i32),
+                    Bytes::copy_from_slice(data),
+                )
+                .await
+                .unwrap();
+        }
+
+        let file = stage_chunks(
+            storage.clone(),
+            upload_id,
+            chunks(&[(0, 7), (1, 8), (2, 5)]),
+            20,
+        )
+        .await
+        .unwrap();
+        assert_eq!(file.size, 20);
+        assert_eq!(
+            file.hash,
+            format!("{:x}", Sha256::digest(b"hello, chunked world"))
+        );
+        assert_eq!(std::fs::read(&file.path).unwrap(), b"hello, chunked world");
+
+        // 分块内容被替换后 hash 不再与声明的一致
+        storage
+            .save(&chunk_key(upload_id, 1), Bytes::from_static(b"CHUNKED "))
+            .await
+            .unwrap();
+        let file = stage_chunks(storage, upload_id, chunks(&[(0, 7), (1, 8), (2, 5)]), 20)
+            .await
+            .unwrap();
+        assert_ne!(
+            file.hash,
+            format!("{:x}", Sha256::digest(b"hello, chunked world"))
+        );
+    }
+
+    #[sqlx::test]
+    async fn expire(db: PgPool) {
+        let storage = MemoryStorage::new();
+        let user_id = Uuid::now_v7();
+        sqlx::query(r#"INSERT INTO "user" (id, name, email, password) VALUES ($1, $2, $3, $4)"#)
+            .bind(user_id)
+            .bind(user_id.to_string())
+            .bind(format!("{}@example.com", user_id))
+            .bind("password")
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let mut sessions = Vec::new();
+        for expires_in_hours in [-1, 1] {
+            let upload_id = Uuid::now_v7();
+            sqlx::query(
+                r#"
+                INSERT INTO "upload_session" ("id", "user_id", "content_type", "size", "sha256", "expires_at")
+                VALUES ($1, $2, 'image/jpeg', 4, $3, NOW() + $4 * INTERVAL '1 hour')
+                "#,
+            )
+            .bind(upload_id)
+            .bind(user_id)
+            .bind(format!("{:x}", Sha256::digest(b"data")))
+            .bind(expires_in_hours)
+            .execute(&db)
+            .await
+            .unwrap();
+            let key = chunk_key(upload_id, 0);
+            storage
+                .save(&key, Bytes::from_static(b"data"))
+                .await
+                .unwrap();
+            sqlx::query(
+                r#"INSERT INTO "upload_chunk" ("session_id", "index", "size", "storage_key") VALUES ($1, 0, 4, $2)"#,
+            )
+            .bind(upload_id)
+            .bind(&key)
+            .execute(&db)
+            .await
+            .unwrap();
+            sessions.push((upload_id, key));
+        }
+
+        assert_eq!(expire_sessions(&db, &storage).await.unwrap(), 1);
+        let remaining: Vec<Uuid> = sqlx::query_scalar(r#"SELECT "id" FROM "upload_session""#)
+            .fetch_all(&db)
+            .await
+            .unwrap();
+        assert_eq!(remaining, vec![sessions[1].0]);
+        assert!(!storage.exists(&sessions[0].1).await.unwrap());
+        assert!(storage.exists(&sessions[1].1).await.unwrap());
+
+        assert_eq!(expire_sessions(&db, &storage).await.unwrap(), 0);
+    }
+}
@@ -5,7 +5,7 @@ use sqlx::postgres::PgPool;
 
 pub async fn get_own_profile_handler(
     State(db): State<PgPool>,
-    AuthUser { user_id }: AuthUser,
+    AuthUser { user_id, .. }: AuthUser,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     let user = sqlx::query!(
         r#"SELECT id, name, email FROM "user" WHERE id = $1"#,